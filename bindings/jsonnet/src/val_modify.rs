@@ -2,9 +2,11 @@
 //! Only tested with variables, which haven't altered by code before appearing here
 //! In jrsonnet every value is immutable, and this code is probally broken
 
-use jrsonnet_evaluator::{EvaluationState, LazyBinding, LazyVal, ObjMember, ObjValue, Val};
+use jrsonnet_evaluator::{
+	ArrValue, EvaluationState, LazyBinding, LazyVal, ObjMember, ObjValue, Val,
+};
 use jrsonnet_parser::Visibility;
-use std::{collections::HashMap, ffi::CStr, os::raw::c_char, rc::Rc};
+use std::{ffi::CStr, os::raw::c_char, rc::Rc};
 
 /// # Safety
 ///
@@ -16,10 +18,10 @@ pub unsafe extern "C" fn jsonnet_json_array_append(
 	val: &Val,
 ) {
 	match *Box::from_raw(arr) {
-		Val::Arr(old) => {
+		Val::Arr(ArrValue::Eager(old)) => {
 			let mut new = Rc::try_unwrap(old).expect("arr with no refs");
 			new.push(val.clone());
-			*arr = Val::Arr(Rc::new(new));
+			*arr = Val::Arr(ArrValue::Eager(Rc::new(new)));
 		}
 		_ => panic!("should receive array"),
 	}
@@ -37,8 +39,7 @@ pub unsafe extern "C" fn jsonnet_json_object_append(
 ) {
 	match obj {
 		Val::Obj(old) => {
-			let mut new = HashMap::new();
-			new.insert(
+			let new = std::iter::once((
 				CStr::from_ptr(name).to_str().unwrap().into(),
 				ObjMember {
 					add: false,
@@ -46,7 +47,8 @@ pub unsafe extern "C" fn jsonnet_json_object_append(
 					invoke: LazyBinding::Bound(LazyVal::new_resolved(val.clone())),
 					location: None,
 				},
-			);
+			))
+			.collect();
 			let new_obj = ObjValue::new(Some(old.clone()), Rc::new(new));
 			*obj = Val::Obj(new_obj);
 		}
@@ -1,6 +1,6 @@
 use crate::ConfigureState;
 use clap::Clap;
-use jrsonnet_evaluator::{error::Result, EvaluationState, ManifestFormat};
+use jrsonnet_evaluator::{error::Result, EmptyYamlStream, EvaluationState, ManifestFormat};
 use std::{path::PathBuf, str::FromStr};
 
 pub enum ManifestFormatName {
@@ -8,6 +8,10 @@ pub enum ManifestFormatName {
 	String,
 	Json,
 	Yaml,
+	Toml,
+	Ini,
+	Csv,
+	Tsv,
 }
 
 impl FromStr for ManifestFormatName {
@@ -17,6 +21,10 @@ impl FromStr for ManifestFormatName {
 			"string" => ManifestFormatName::String,
 			"json" => ManifestFormatName::Json,
 			"yaml" => ManifestFormatName::Yaml,
+			"toml" => ManifestFormatName::Toml,
+			"ini" => ManifestFormatName::Ini,
+			"csv" => ManifestFormatName::Csv,
+			"tsv" => ManifestFormatName::Tsv,
 			_ => return Err("no such format"),
 		})
 	}
@@ -28,7 +36,7 @@ pub struct ManifestOpts {
 	/// Output format, wraps resulting value to corresponding std.manifest call.
 	/// If set to `string` then plain string value is expected to be returned,
 	/// otherwise output will be serialized to the specified format.
-	#[clap(long, short = 'f', default_value = "json", possible_values = &["string", "json", "yaml"]/*, group = "output_format"*/)]
+	#[clap(long, short = 'f', default_value = "json", possible_values = &["string", "json", "yaml", "toml", "ini", "csv", "tsv"]/*, group = "output_format"*/)]
 	format: ManifestFormatName,
 	/// Expect plain string as output.
 	/// Shortcut for `--format=string` thus this option is mutually exclusive with `format` option.
@@ -37,10 +45,16 @@ pub struct ManifestOpts {
 	/// Write output as YAML stream, can be used with --format json/yaml
 	#[clap(long, short = 'y')]
 	yaml_stream: bool,
+	/// Write single empty document, when YAML stream has no documents
+	#[clap(long)]
+	yaml_stream_empty_document: bool,
 	/// Number of spaces to pad output manifest with.
 	/// `0` for hard tabs, `-1` for single line output/
 	#[clap(long, default_value = "3")]
 	line_padding: usize,
+	/// Don't write row of field names, when output format is csv/tsv
+	#[clap(long)]
+	no_csv_header: bool,
 }
 impl ConfigureState for ManifestOpts {
 	fn configure(&self, state: &EvaluationState) -> Result<()> {
@@ -55,12 +69,30 @@ impl ConfigureState for ManifestOpts {
 				ManifestFormatName::Yaml => {
 					state.set_manifest_format(ManifestFormat::Yaml(self.line_padding))
 				}
+				ManifestFormatName::Toml => {
+					state.set_manifest_format(ManifestFormat::Toml(self.line_padding))
+				}
+				ManifestFormatName::Ini => state.set_manifest_format(ManifestFormat::Ini),
+				ManifestFormatName::Csv => state.set_manifest_format(ManifestFormat::Csv {
+					delimiter: ',',
+					header: !self.no_csv_header,
+				}),
+				ManifestFormatName::Tsv => state.set_manifest_format(ManifestFormat::Csv {
+					delimiter: '\t',
+					header: !self.no_csv_header,
+				}),
 			}
 		}
 		if self.yaml_stream {
-			state.set_manifest_format(ManifestFormat::YamlStream(Box::new(
-				state.manifest_format(),
-			)))
+			let empty = if self.yaml_stream_empty_document {
+				EmptyYamlStream::Document
+			} else {
+				EmptyYamlStream::Empty
+			};
+			state.set_manifest_format(ManifestFormat::YamlStream(
+				Box::new(state.manifest_format()),
+				empty,
+			))
 		}
 		Ok(())
 	}
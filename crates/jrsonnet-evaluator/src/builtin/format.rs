@@ -281,7 +281,8 @@ const NUMBERS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
 #[inline]
 pub fn render_integer(
 	out: &mut String,
-	iv: i64,
+	neg: bool,
+	mag: f64,
 	padding: usize,
 	precision: usize,
 	blank: bool,
@@ -292,18 +293,23 @@ pub fn render_integer(
 ) {
 	// Digit char indexes in reverse order, i.e
 	// for radix = 16 and n = 12f: [15, 2, 1]
-	let digits = if iv == 0 {
+	// Magnitude is kept as float, same as reference implementation does,
+	// so numbers not fitting in i64 are still rendered correctly
+	let digits = if mag < 1.0 {
 		vec![0u8]
 	} else {
-		let mut v = iv.abs();
+		let radix = radix as f64;
+		let mut v = mag.floor();
 		let mut nums = Vec::with_capacity(1);
-		while v > 0 {
+		loop {
 			nums.push((v % radix) as u8);
-			v /= radix;
+			v = (v / radix).floor();
+			if v < 1.0 {
+				break;
+			}
 		}
 		nums
 	};
-	let neg = iv < 0;
 	let zp = padding.saturating_sub(if neg || blank || sign { 1 } else { 0 });
 	let zp2 = zp
 		.max(precision)
@@ -317,11 +323,11 @@ pub fn render_integer(
 		out.push(' ');
 	}
 
+	out.push_str(prefix);
 	out.reserve(zp2);
 	for _ in 0..zp2 {
 		out.push('0');
 	}
-	out.push_str(prefix);
 
 	for digit in digits.into_iter().rev() {
 		let ch = NUMBERS[digit as usize] as char;
@@ -329,40 +335,51 @@ pub fn render_integer(
 	}
 }
 
+/// Splits number to sign and magnitude, in the same way as `%d` does,
+/// so `-0.5` is rendered as `0`
+fn int_parts(n: f64) -> (bool, f64) {
+	(n <= -1.0, n.abs().floor())
+}
+
 pub fn render_decimal(
 	out: &mut String,
-	iv: i64,
+	n: f64,
 	padding: usize,
 	precision: usize,
 	blank: bool,
 	sign: bool,
 ) {
-	render_integer(out, iv, padding, precision, blank, sign, 10, "", false)
+	let (neg, mag) = int_parts(n);
+	render_integer(
+		out, neg, mag, padding, precision, blank, sign, 10, "", false,
+	)
 }
 pub fn render_octal(
 	out: &mut String,
-	iv: i64,
+	n: f64,
 	padding: usize,
 	precision: usize,
 	alt: bool,
 	blank: bool,
 	sign: bool,
 ) {
+	let (neg, mag) = int_parts(n);
 	render_integer(
 		out,
-		iv,
+		neg,
+		mag,
 		padding,
 		precision,
 		blank,
 		sign,
 		8,
-		if alt && iv != 0 { "0" } else { "" },
+		if alt && mag != 0.0 { "0" } else { "" },
 		false,
 	)
 }
 pub fn render_hexadecimal(
 	out: &mut String,
-	iv: i64,
+	n: f64,
 	padding: usize,
 	precision: usize,
 	alt: bool,
@@ -370,9 +387,11 @@ pub fn render_hexadecimal(
 	sign: bool,
 	caps: bool,
 ) {
+	let (neg, mag) = int_parts(n);
 	render_integer(
 		out,
-		iv,
+		neg,
+		mag,
 		padding,
 		precision,
 		blank,
@@ -399,35 +418,52 @@ pub fn render_float(
 ) {
 	let dot_size = if precision == 0 && !ensure_pt { 0 } else { 1 };
 	padding = padding.saturating_sub(dot_size + precision);
-	render_decimal(out, n.floor() as i64, padding, 0, blank, sign);
+	// Rounding is done before splitting, so 0.999 with precision 2 carries to 1.00
+	let denominator = 10.0_f64.powi(precision as i32);
+	let numerator = n.abs().mul_add(denominator, 0.5);
+	let whole = (numerator / denominator).floor();
+	render_integer(out, n < 0.0, whole, padding, 0, blank, sign, 10, "", false);
 	if precision == 0 {
 		if ensure_pt {
 			out.push('.');
 		}
 		return;
 	}
-	let frac = n
-		.fract()
-		.mul_add(10.0_f64.powf(precision as f64), 0.5)
-		.floor();
+	let frac = numerator.floor() % denominator;
 	if trailing || frac > 0.0 {
 		out.push('.');
 		let mut frac_str = String::new();
-		render_decimal(&mut frac_str, frac as i64, precision, 0, false, false);
-		let mut trim = frac_str.len();
-		if !trailing {
-			for b in frac_str.as_bytes().iter().rev() {
-				if *b == b'0' {
-					trim -= 1;
-				}
-			}
+		render_integer(
+			&mut frac_str,
+			false,
+			frac,
+			precision,
+			0,
+			false,
+			false,
+			10,
+			"",
+			false,
+		);
+		if trailing {
+			out.push_str(&frac_str);
+		} else {
+			out.push_str(frac_str.trim_end_matches('0'));
 		}
-		out.push_str(&frac_str[..trim]);
 	} else if ensure_pt {
 		out.push('.');
 	}
 }
 
+/// Decimal exponent of number, zero for zero
+fn exponent_of(n: f64) -> f64 {
+	if n == 0.0 {
+		0.0
+	} else {
+		n.abs().log10().floor()
+	}
+}
+
 pub fn render_float_sci(
 	out: &mut String,
 	n: f64,
@@ -439,14 +475,14 @@ pub fn render_float_sci(
 	trailing: bool,
 	caps: bool,
 ) {
-	let exponent = n.log10().floor();
+	let exponent = exponent_of(n);
 	let mantissa = if exponent as i16 == -324 {
 		n * 10.0 / 10.0_f64.powf(exponent + 1.0)
 	} else {
 		n / 10.0_f64.powf(exponent)
 	};
 	let mut exponent_str = String::new();
-	render_decimal(&mut exponent_str, exponent as i64, 3, 0, false, true);
+	render_decimal(&mut exponent_str, exponent, 3, 0, false, true);
 
 	// +1 for e
 	padding = padding.saturating_sub(exponent_str.len() + 1);
@@ -485,7 +521,7 @@ pub fn format_code(
 			let value = value.clone().try_cast_num("%d/%u/%i requires number")?;
 			render_decimal(
 				&mut tmp_out,
-				value as i64,
+				value,
 				padding,
 				iprec,
 				clfags.blank,
@@ -496,7 +532,7 @@ pub fn format_code(
 			let value = value.clone().try_cast_num("%o requires number")?;
 			render_octal(
 				&mut tmp_out,
-				value as i64,
+				value,
 				padding,
 				iprec,
 				clfags.alt,
@@ -508,7 +544,7 @@ pub fn format_code(
 			let value = value.clone().try_cast_num("%x/%X requires number")?;
 			render_hexadecimal(
 				&mut tmp_out,
-				value as i64,
+				value,
 				padding,
 				iprec,
 				clfags.alt,
@@ -532,7 +568,7 @@ pub fn format_code(
 			);
 		}
 		ConvTypeV::Float => {
-			let value = value.clone().try_cast_num("%e/%E requires number")?;
+			let value = value.clone().try_cast_num("%f/%F requires number")?;
 			render_float(
 				&mut tmp_out,
 				value,
@@ -546,13 +582,13 @@ pub fn format_code(
 		}
 		ConvTypeV::Shorter => {
 			let value = value.clone().try_cast_num("%g/%G requires number")?;
-			let exponent = value.log10().floor();
+			let exponent = exponent_of(value);
 			if exponent < -4.0 || exponent >= fpprec as f64 {
 				render_float_sci(
 					&mut tmp_out,
 					value,
 					padding,
-					fpprec - 1,
+					fpprec.saturating_sub(1),
 					clfags.blank,
 					clfags.sign,
 					clfags.alt,
@@ -565,7 +601,7 @@ pub fn format_code(
 					&mut tmp_out,
 					value,
 					padding,
-					fpprec - digits_before_pt,
+					fpprec.saturating_sub(digits_before_pt),
 					clfags.blank,
 					clfags.sign,
 					clfags.alt,
@@ -744,6 +780,101 @@ pub mod test_format {
 		assert_eq!(format_arr("%+-04o", &[Val::Num(8.0)]).unwrap(), "+10 ");
 	}
 
+	/// Expected strings are produced by reference implementation
+	#[test]
+	fn conformance() {
+		let num = Val::Num;
+		let str = |s: &str| Val::Str(s.into());
+		let cases: &[(&str, Val, &str)] = &[
+			("%d", num(42.0), "42"),
+			("%i", num(-7.0), "-7"),
+			("%5d|", num(42.0), "   42|"),
+			("%-5d|", num(42.0), "42   |"),
+			("%05d", num(42.0), "00042"),
+			("%05d", num(-42.0), "-0042"),
+			("%+d", num(5.0), "+5"),
+			("% d", num(5.0), " 5"),
+			("%.3d", num(7.0), "007"),
+			("%d", num(1.5), "1"),
+			("%d", num(-1.5), "-1"),
+			("%d", num(-0.5), "0"),
+			("%d", num(1e20), "100000000000000000000"),
+			("%x", num(255.0), "ff"),
+			("%X", num(255.0), "FF"),
+			("%#x", num(255.0), "0xff"),
+			("%#06x", num(255.0), "0x00ff"),
+			("%x", num(-255.0), "-ff"),
+			("%o", num(8.0), "10"),
+			("%f", num(1.5), "1.500000"),
+			("%.2f", num(1.23456), "1.23"),
+			("%-8.2f|", num(1.23456), "1.23    |"),
+			("%8.2f|", num(1.23456), "    1.23|"),
+			("%08.2f", num(-1.5), "-0001.50"),
+			("%+.1f", num(2.0), "+2.0"),
+			("%.2f", num(-0.5), "-0.50"),
+			("%.2f", num(0.999), "1.00"),
+			("%.0f", num(2.5), "3"),
+			("%#.0f", num(2.0), "2."),
+			("%5.1f|", num(2.25), "  2.3|"),
+			("%e", num(12345.678), "1.234568e+04"),
+			("%.2E", num(-0.00123), "-1.23E-03"),
+			("%e", num(0.0), "0.000000e+00"),
+			("%g", num(0.0001), "0.0001"),
+			("%g", num(0.00001), "1e-05"),
+			("%g", num(123456789.0), "1.23457e+08"),
+			("%g", num(100.0), "100"),
+			("%g", num(-2.5), "-2.5"),
+			("%g", num(0.105), "0.105"),
+			("%s", str("hello"), "hello"),
+			("%10s|", str("hi"), "        hi|"),
+			("%-4s|", str("hi"), "hi  |"),
+			("%s", num(1.5), "1.5"),
+			("%s", Val::Null, "null"),
+			("%s", Val::Bool(true), "true"),
+			(
+				"%s",
+				Val::Arr(vec![num(1.0), str("a")].into()),
+				"[1, \"a\"]",
+			),
+			("%c", num(65.0), "A"),
+			("%c", str("é"), "é"),
+			("%d%%", num(50.0), "50%"),
+		];
+		for (format, value, expected) in cases {
+			assert_eq!(
+				&format_arr(format, std::slice::from_ref(value)).unwrap(),
+				expected,
+				"{} of {:?}",
+				format,
+				value
+			);
+		}
+	}
+
+	#[test]
+	fn mapping() {
+		let obj = ObjValue::from_values(vec![
+			("a".into(), Val::Str("x".into())),
+			("b".into(), Val::Num(2.25)),
+			(
+				"c".into(),
+				Val::Obj(ObjValue::from_values(vec![("d".into(), Val::Num(1.0))])),
+			),
+		]);
+		assert_eq!(
+			format_obj("%(a)s-%(b)05.1f %(c)s %%", &obj).unwrap(),
+			"x-002.3 {\"d\": 1} %"
+		);
+		assert!(matches!(
+			format_obj("%(e)s", &obj).unwrap_err().error(),
+			Format(NoSuchFormatField(f)) if &**f == "e"
+		));
+		assert!(matches!(
+			format_arr("%d %d", &[Val::Num(1.0)]).unwrap_err().error(),
+			Format(NotEnoughValues)
+		));
+	}
+
 	#[test]
 	fn percent_doesnt_consumes_values() {
 		assert_eq!(
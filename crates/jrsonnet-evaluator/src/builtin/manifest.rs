@@ -1,6 +1,7 @@
 use crate::error::Error::*;
 use crate::error::Result;
-use crate::{throw, Val};
+use crate::{throw, ArrValue, FieldOrder, FuncVal, ObjValue, Val, ValType};
+use std::{io, rc::Rc};
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum ManifestType {
@@ -15,25 +16,192 @@ pub enum ManifestType {
 	Minify,
 }
 
+#[derive(PartialEq, Eq, Clone, Copy, Default)]
+pub enum BraceStyle {
+	/// Opening brace of object field value is written on the same line as the key
+	#[default]
+	SameLine,
+	/// Opening brace of object field value is written on its own line, aligned with the key
+	OwnLine,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Default)]
+pub enum NumberFormat {
+	/// Shortest representation, which is parsed back to the same number, i.e `0.1`
+	#[default]
+	ShortestRoundTrip,
+	/// Fixed number of significant digits, same as `%.Ng` in C, i.e `0.10000000000000001`
+	/// with precision of 17, as written by go-jsonnet. Whole numbers are written as in
+	/// [`NumberFormat::ShortestRoundTrip`]
+	Precision(usize),
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Default)]
+pub enum FunctionHandling {
+	/// Manifestification fails, as required by spec
+	#[default]
+	Error,
+	/// Function is written as `"<function name>"` string
+	Placeholder,
+	/// Object fields with function values are omitted, functions in arrays are written as null,
+	/// so indices of other elements are kept
+	Skip,
+}
+
 pub struct ManifestJsonOptions<'s> {
 	pub padding: &'s str,
 	pub mtype: ManifestType,
+	/// Escape all non-ascii characters, using surrogate pairs for characters outside of BMP,
+	/// i.e `\ud83d\ude00`. Hex digits are lowercase, as in every other `\u` escape
+	pub escape_unicode: bool,
+	/// Arrays of scalar values with fewer elements than this are written on single line, i.e `[1, 2, 3]`
+	pub inline_array_threshold: Option<usize>,
+	/// Ignored in `ToString` and `Minify` modes
+	pub brace_style: BraceStyle,
+	/// Overrides rendering of numbers, returned string is written as is
+	pub render_number: Option<&'s dyn Fn(f64) -> String>,
+	/// Overrides rendering of string values (but not keys), returned string is written as is
+	pub render_string: Option<&'s dyn Fn(&str) -> String>,
+	/// Whole numbers with absolute value below this limit are always written in decimal notation,
+	/// by default only numbers below `1e21` are
+	pub decimal_integers_below: Option<f64>,
+	/// Ignored when `render_number` is set
+	pub number_format: NumberFormat,
+	/// Comments, written above object fields, making output JSONC. Keys of this object are
+	/// dot-separated paths of fields (array elements are referenced by index, i.e `a.0.b`),
+	/// values are comment strings. Ignored in `ToString` and `Minify` modes
+	pub comments: Option<&'s ObjValue>,
+	/// Output hidden object fields too, this is not spec-compliant, and is intended for diagnostics
+	pub include_hidden: bool,
+	/// Pad keys to the longest key of object, so values start at the same column.
+	/// Ignored in `ToString` and `Minify` modes
+	pub align_values: bool,
+	/// Written as is in place of null values, fields with null values are kept
+	pub null_token: &'s str,
+	pub field_order: FieldOrder,
+	/// Non-default handling of functions is not spec-compliant, and is intended for debugging dumps
+	pub functions: FunctionHandling,
+	/// Arrays and objects are written on single line, i.e `{"a": [1, 2]}`, if line doesn't
+	/// get longer than this, comments inside of such values are not written.
+	/// Ignored in `ToString` and `Minify` modes
+	pub max_line_length: Option<usize>,
+	/// Object fields with these names are written first, in the same order as listed here,
+	/// other fields follow them in `field_order`
+	pub key_order: &'s [Rc<str>],
+}
+impl Default for ManifestJsonOptions<'_> {
+	fn default() -> Self {
+		Self {
+			padding: "",
+			mtype: ManifestType::Manifest,
+			escape_unicode: false,
+			inline_array_threshold: None,
+			brace_style: BraceStyle::SameLine,
+			render_number: None,
+			render_string: None,
+			decimal_integers_below: None,
+			number_format: NumberFormat::default(),
+			comments: None,
+			include_hidden: false,
+			align_values: false,
+			null_token: "null",
+			field_order: FieldOrder::default(),
+			functions: FunctionHandling::default(),
+			max_line_length: None,
+			key_order: &[],
+		}
+	}
+}
+
+impl ManifestJsonOptions<'_> {
+	/// Output matches `json.dumps(value, indent=2, sort_keys=True)` in Python
+	pub fn python_json_dumps() -> Self {
+		Self {
+			padding: "  ",
+			mtype: ManifestType::Manifest,
+			escape_unicode: true,
+			..Default::default()
+		}
+	}
 }
 
 pub fn manifest_json_ex(val: &Val, options: &ManifestJsonOptions<'_>) -> Result<String> {
 	let mut out = String::new();
-	manifest_json_ex_buf(val, &mut out, &mut String::new(), options)?;
+	manifest_json_ex_buf(
+		val,
+		&mut out,
+		&mut None,
+		&mut String::new(),
+		&mut String::new(),
+		&mut None,
+		options,
+	)?;
 	Ok(out)
 }
+
+/// Buffered output is written to sink once it grows past this size
+const JSON_STREAM_CHUNK: usize = 64 * 1024;
+/// When set, buffered output is written to it between array elements and object fields
+type JsonSink<'w> = Option<&'w mut dyn io::Write>;
+
+fn flush_json_buf(buf: &mut String, sink: &mut JsonSink<'_>, force: bool) -> Result<()> {
+	if let Some(writer) = sink {
+		if force || buf.len() >= JSON_STREAM_CHUNK {
+			if let Err(e) = writer.write_all(buf.as_bytes()) {
+				throw!(RuntimeError(
+					format!("failed to write output: {}", e).into()
+				));
+			}
+			buf.clear();
+		}
+	}
+	Ok(())
+}
+
+/// Same as [`manifest_json_ex`], but output is written to `writer` as it is produced,
+/// instead of being accumulated in memory
+pub fn manifest_json_ex_to(
+	val: &Val,
+	writer: &mut dyn io::Write,
+	options: &ManifestJsonOptions<'_>,
+) -> Result<()> {
+	let mut buf = String::new();
+	let mut sink: JsonSink<'_> = Some(writer);
+	manifest_json_ex_buf(
+		val,
+		&mut buf,
+		&mut sink,
+		&mut String::new(),
+		&mut String::new(),
+		&mut None,
+		options,
+	)?;
+	flush_json_buf(&mut buf, &mut sink, true)
+}
+
+/// `cur_path` is only tracked when comments are enabled.
+/// When `budget` is set, writing stops as soon as output doesn't fit into it
 fn manifest_json_ex_buf(
 	val: &Val,
 	buf: &mut String,
+	sink: &mut JsonSink<'_>,
 	cur_padding: &mut String,
+	cur_path: &mut String,
+	budget: &mut Option<WidthBudget>,
 	options: &ManifestJsonOptions<'_>,
 ) -> Result<()> {
-	use std::fmt::Write;
 	let mtype = options.mtype;
-	match val.unwrap_if_lazy()? {
+	let val = val.unwrap_if_lazy()?;
+	if let Some(max_line_length) = options.max_line_length {
+		if mtype != ManifestType::ToString
+			&& mtype != ManifestType::Minify
+			&& is_json_block(&val, options)?
+			&& push_json_if_fits(&val, buf, max_line_length, options)?
+		{
+			return Ok(());
+		}
+	}
+	match val {
 		Val::Bool(v) => {
 			if v {
 				buf.push_str("true");
@@ -41,12 +209,45 @@ fn manifest_json_ex_buf(
 				buf.push_str("false");
 			}
 		}
-		Val::Null => buf.push_str("null"),
-		Val::Str(s) => buf.push_str(&escape_string_json(&s)),
-		Val::Num(n) => write!(buf, "{}", n).unwrap(),
+		Val::Null => buf.push_str(options.null_token),
+		// Escaped string has at least as many chars as it has bytes / 4, no need to escape it
+		// when even that doesn't fit
+		Val::Str(s)
+			if options.render_string.is_none()
+				&& matches!(budget, Some(b) if b.remaining.filter(|r| s.len() / 4 <= *r).is_none()) =>
+		{
+			budget.as_mut().unwrap().remaining = None;
+		}
+		Val::Str(s) => match options.render_string {
+			Some(render) => buf.push_str(&render(&s)),
+			None => buf.push_str(&escape_string_json_ex(&s, options.escape_unicode)),
+		},
+		Val::Num(n) => match options.render_number {
+			Some(render) => buf.push_str(&render(n)),
+			None => manifest_json_number(n, buf, options)?,
+		},
 		Val::Arr(items) => {
+			// Elements are evaluated one by one, so large arrays are never fully materialized
 			buf.push('[');
-			if !items.is_empty() {
+			if is_inline_array(&items, options)? {
+				for (i, item) in items.iter().enumerate() {
+					if i != 0 {
+						buf.push_str(", ");
+					}
+					manifest_json_ex_buf(
+						&item?,
+						buf,
+						sink,
+						cur_padding,
+						cur_path,
+						budget,
+						options,
+					)?;
+					if width_exceeded(budget, buf) {
+						return Ok(());
+					}
+				}
+			} else if !items.is_empty() {
 				if mtype != ManifestType::ToString && mtype != ManifestType::Minify {
 					buf.push('\n');
 				}
@@ -63,7 +264,24 @@ fn manifest_json_ex_buf(
 						}
 					}
 					buf.push_str(cur_padding);
-					manifest_json_ex_buf(item, buf, cur_padding, options)?;
+					let old_path_len = cur_path.len();
+					if options.comments.is_some() {
+						push_json_path(cur_path, &i.to_string());
+					}
+					manifest_json_ex_buf(
+						&item?,
+						buf,
+						sink,
+						cur_padding,
+						cur_path,
+						budget,
+						options,
+					)?;
+					if width_exceeded(budget, buf) {
+						return Ok(());
+					}
+					cur_path.truncate(old_path_len);
+					flush_json_buf(buf, sink, false)?;
 				}
 				cur_padding.truncate(old_len);
 
@@ -80,16 +298,54 @@ fn manifest_json_ex_buf(
 			buf.push(']');
 		}
 		Val::Obj(obj) => {
+			// Failing assertion shouldn't leave partially written object
+			obj.run_assertions()?;
 			buf.push('{');
-			let fields = obj.visible_fields();
+			let mut fields = obj.fields_ex(options.include_hidden, options.field_order);
+			if options.functions == FunctionHandling::Skip {
+				let mut kept = Vec::with_capacity(fields.len());
+				for field in fields {
+					if !matches!(
+						obj.get(field.clone())?.unwrap().unwrap_if_lazy()?,
+						Val::Func(_)
+					) {
+						kept.push(field);
+					}
+				}
+				fields = kept;
+			}
+			if !options.key_order.is_empty() {
+				// Stable sort keeps `field_order` for fields not listed in `key_order`
+				fields.sort_by_key(|field| {
+					options
+						.key_order
+						.iter()
+						.position(|key| key == field)
+						.unwrap_or(options.key_order.len())
+				});
+			}
 			if !fields.is_empty() {
-				if mtype != ManifestType::ToString && mtype != ManifestType::Minify {
+				let pretty = mtype != ManifestType::ToString && mtype != ManifestType::Minify;
+				if pretty {
 					buf.push('\n');
 				}
 
+				let keys = fields
+					.iter()
+					.map(|field| escape_string_json_ex(field, options.escape_unicode))
+					.collect::<Vec<_>>();
+				let key_width = if options.align_values && pretty {
+					keys.iter()
+						.map(|key| key.chars().count())
+						.max()
+						.unwrap_or(0)
+				} else {
+					0
+				};
+
 				let old_len = cur_padding.len();
 				cur_padding.push_str(options.padding);
-				for (i, field) in fields.into_iter().enumerate() {
+				for (i, (field, key)) in fields.into_iter().zip(keys).enumerate() {
 					if i != 0 {
 						buf.push(',');
 						if mtype == ManifestType::ToString {
@@ -98,14 +354,52 @@ fn manifest_json_ex_buf(
 							buf.push('\n');
 						}
 					}
+					let old_path_len = cur_path.len();
+					if let Some(comments) = options.comments {
+						push_json_path(cur_path, &field);
+						if pretty {
+							if let Some(comment) = comments.get(cur_path.as_str().into())? {
+								for line in comment.try_cast_str("json comment")?.lines() {
+									buf.push_str(cur_padding);
+									buf.push_str("// ");
+									buf.push_str(line);
+									buf.push('\n');
+								}
+							}
+						}
+					}
 					buf.push_str(cur_padding);
-					buf.push_str(&escape_string_json(&field));
-					buf.push_str(": ");
-					manifest_json_ex_buf(&obj.get(field)?.unwrap(), buf, cur_padding, options)?;
+					buf.push_str(&key);
+					let value = obj.get(field)?.unwrap().unwrap_if_lazy()?;
+					if options.brace_style == BraceStyle::OwnLine
+						&& pretty && is_json_block(&value, options)?
+					{
+						buf.push_str(":\n");
+						buf.push_str(cur_padding);
+					} else {
+						buf.push_str(": ");
+						for _ in key.chars().count()..key_width {
+							buf.push(' ');
+						}
+					}
+					manifest_json_ex_buf(
+						&value,
+						buf,
+						sink,
+						cur_padding,
+						cur_path,
+						budget,
+						options,
+					)?;
+					if width_exceeded(budget, buf) {
+						return Ok(());
+					}
+					cur_path.truncate(old_path_len);
+					flush_json_buf(buf, sink, false)?;
 				}
 				cur_padding.truncate(old_len);
 
-				if mtype != ManifestType::ToString && mtype != ManifestType::Minify {
+				if pretty {
 					buf.push('\n');
 					buf.push_str(cur_padding);
 				}
@@ -117,12 +411,643 @@ fn manifest_json_ex_buf(
 			}
 			buf.push('}');
 		}
-		Val::Func(_) => throw!(RuntimeError("tried to manifest function".into())),
+		Val::Func(f) => match options.functions {
+			// Same message as reference implementation gives for std.toString
+			FunctionHandling::Error if mtype == ManifestType::ToString => {
+				throw!(RuntimeError("couldn't manifest function as JSON".into()))
+			}
+			FunctionHandling::Error => throw!(RuntimeError("tried to manifest function".into())),
+			FunctionHandling::Placeholder => buf.push_str(&escape_string_json_ex(
+				&format!("<function {}>", f.name()),
+				options.escape_unicode,
+			)),
+			FunctionHandling::Skip => buf.push_str(options.null_token),
+		},
 		Val::Lazy(_) => unreachable!(),
 	};
 	Ok(())
 }
+#[derive(Clone, Copy)]
+pub enum IniArrayMode<'s> {
+	/// Every element is written as separate `key = value` line
+	Repeat,
+	/// Elements are joined into single value with specified separator
+	Join(&'s str),
+}
+
+pub struct ManifestIniOptions<'s> {
+	pub array_mode: IniArrayMode<'s>,
+}
+
+fn manifest_ini_body(body: &Val, buf: &mut String, options: &ManifestIniOptions<'_>) -> Result<()> {
+	use std::fmt::Write;
+	let body = match body.unwrap_if_lazy()? {
+		Val::Obj(o) => o,
+		v => throw!(TypeMismatch(
+			"manifestIni section",
+			vec![ValType::Obj],
+			v.value_type()?
+		)),
+	};
+	for key in body.visible_fields() {
+		let value = body.get(key.clone())?.unwrap().unwrap_if_lazy()?;
+		match (value, options.array_mode) {
+			(Val::Arr(items), IniArrayMode::Repeat) => {
+				for item in items.iter() {
+					writeln!(buf, "{} = {}", key, item?.to_string()?).unwrap();
+				}
+			}
+			(Val::Arr(items), IniArrayMode::Join(separator)) => {
+				let mut values = Vec::with_capacity(items.len());
+				for item in items.iter() {
+					values.push(item?.to_string()?);
+				}
+				writeln!(buf, "{} = {}", key, values.join(separator)).unwrap();
+			}
+			(value, _) => writeln!(buf, "{} = {}", key, value.to_string()?).unwrap(),
+		}
+	}
+	Ok(())
+}
+
+pub fn manifest_ini_ex(ini: &Val, options: &ManifestIniOptions<'_>) -> Result<String> {
+	use std::fmt::Write;
+	let ini = match ini.unwrap_if_lazy()? {
+		Val::Obj(o) => o,
+		v => throw!(TypeMismatch(
+			"manifestIni",
+			vec![ValType::Obj],
+			v.value_type()?
+		)),
+	};
+	let mut out = String::new();
+	if ini.fields_visibility().get("main") == Some(&true) {
+		manifest_ini_body(&ini.get("main".into())?.unwrap(), &mut out, options)?;
+	}
+	let sections = match ini.get("sections".into())? {
+		Some(Val::Obj(o)) => o,
+		Some(v) => throw!(TypeMismatch(
+			"manifestIni sections",
+			vec![ValType::Obj],
+			v.value_type()?
+		)),
+		None => throw!(NoSuchField("sections".into())),
+	};
+	for section in sections.visible_fields() {
+		writeln!(out, "[{}]", section).unwrap();
+		manifest_ini_body(&sections.get(section)?.unwrap(), &mut out, options)?;
+	}
+	Ok(out)
+}
+
+pub struct ManifestCsvOptions {
+	/// Separator between fields of a row, `,` for CSV and `\t` for TSV
+	pub delimiter: char,
+	/// Write row of field names before records
+	pub header: bool,
+}
+
+fn push_csv_field(buf: &mut String, field: &str, delimiter: char) {
+	if field.contains(&[delimiter, '"', '\n', '\r'][..]) {
+		buf.push('"');
+		buf.push_str(&field.replace('"', "\"\""));
+		buf.push('"');
+	} else {
+		buf.push_str(field);
+	}
+}
+
+/// Expects array of objects with the same set of visible fields, every object is written as
+/// a record, columns are ordered by fields of the first object. Fields are quoted per RFC 4180
+pub fn manifest_csv_ex(csv: &Val, options: &ManifestCsvOptions) -> Result<String> {
+	let rows = match csv.unwrap_if_lazy()? {
+		Val::Arr(a) => a,
+		v => throw!(TypeMismatch(
+			"manifestCsv",
+			vec![ValType::Arr],
+			v.value_type()?
+		)),
+	};
+	let mut out = String::new();
+	let mut columns: Option<Vec<Rc<str>>> = None;
+	for (i, row) in rows.iter().enumerate() {
+		let row = match row? {
+			Val::Obj(o) => o,
+			v => throw!(TypeMismatch(
+				"manifestCsv row",
+				vec![ValType::Obj],
+				v.value_type()?
+			)),
+		};
+		let fields = row.visible_fields();
+		let columns = match &columns {
+			Some(columns) => {
+				if let Some(missing) = columns.iter().find(|c| !fields.contains(c)) {
+					throw!(RuntimeError(
+						format!("csv row {} has no field {}", i, missing).into()
+					));
+				}
+				if let Some(extra) = fields.iter().find(|f| !columns.contains(f)) {
+					throw!(RuntimeError(
+						format!("csv row {} has unexpected field {}", i, extra).into()
+					));
+				}
+				columns
+			}
+			None => {
+				if options.header {
+					for (j, field) in fields.iter().enumerate() {
+						if j != 0 {
+							out.push(options.delimiter);
+						}
+						push_csv_field(&mut out, field, options.delimiter);
+					}
+					out.push('\n');
+				}
+				columns.insert(fields)
+			}
+		};
+		for (j, column) in columns.iter().enumerate() {
+			if j != 0 {
+				out.push(options.delimiter);
+			}
+			let value = match row.get(column.clone())?.unwrap() {
+				Val::Str(s) => s.flatten(),
+				v @ (Val::Num(_) | Val::Bool(_) | Val::Null) => v.to_string()?,
+				v => throw!(TypeMismatch(
+					"manifestCsv field",
+					vec![ValType::Str, ValType::Num, ValType::Bool, ValType::Null],
+					v.value_type()?
+				)),
+			};
+			push_csv_field(&mut out, &value, options.delimiter);
+		}
+		out.push('\n');
+	}
+	Ok(out)
+}
+
+pub struct ManifestTomlOptions<'s> {
+	/// Indentation of nested tables
+	pub padding: &'s str,
+}
+
+/// Objects of form `{__toml_datetime__: "1979-05-27T07:32:00Z"}` are written as bare TOML datetimes
+pub const TOML_DATETIME_TAG: &str = "__toml_datetime__";
+
+fn toml_datetime(val: &Val) -> Result<Option<Rc<str>>> {
+	let obj = match val {
+		Val::Obj(obj) => obj,
+		_ => return Ok(None),
+	};
+	let fields = obj.visible_fields();
+	if fields.len() != 1 || &*fields[0] != TOML_DATETIME_TAG {
+		return Ok(None);
+	}
+	match obj.get(fields[0].clone())?.unwrap().unwrap_if_lazy()? {
+		Val::Str(s)
+			if !s.is_empty()
+				&& s.chars().all(|c| {
+					c.is_ascii_digit()
+						|| matches!(c, '-' | ':' | '.' | '+' | 'T' | 't' | 'Z' | 'z' | ' ')
+				}) =>
+		{
+			Ok(Some(s.flatten()))
+		}
+		Val::Str(s) => throw!(RuntimeError(format!("invalid toml datetime: {}", s).into())),
+		v => throw!(TypeMismatch(
+			"toml datetime",
+			vec![ValType::Str],
+			v.value_type()?
+		)),
+	}
+}
+
+/// Objects and non-empty arrays of objects are written as `[table]` and `[[array]]` sections
+fn is_toml_section(val: &Val) -> Result<bool> {
+	Ok(match val {
+		Val::Obj(_) => toml_datetime(val)?.is_none(),
+		Val::Arr(items) if !items.is_empty() => {
+			for item in items.iter() {
+				let item = item?;
+				if !matches!(item, Val::Obj(_)) || toml_datetime(&item)?.is_some() {
+					return Ok(false);
+				}
+			}
+			true
+		}
+		_ => false,
+	})
+}
+
+fn escape_toml_key(key: &str) -> String {
+	if !key.is_empty()
+		&& key
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+	{
+		key.to_owned()
+	} else {
+		escape_string_json(key)
+	}
+}
+
+/// Multiline basic string, i.e
+/// ```toml
+/// a = """
+/// first
+/// second"""
+/// ```
+fn escape_toml_multiline(s: &str) -> String {
+	use std::fmt::Write;
+	// Newline right after opening delimiter is trimmed by parser
+	let mut out = String::from("\"\"\"\n");
+	for c in s.chars() {
+		match c {
+			'\n' | '\t' => out.push(c),
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\r' => out.push_str("\\r"),
+			c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+			c => out.push(c),
+		}
+	}
+	out.push_str("\"\"\"");
+	out
+}
+
+fn manifest_toml_value(
+	val: &Val,
+	path: &[Rc<str>],
+	inline: bool,
+	cur_padding: &str,
+	options: &ManifestTomlOptions<'_>,
+) -> Result<String> {
+	Ok(match val.unwrap_if_lazy()? {
+		Val::Bool(v) => v.to_string(),
+		Val::Null => throw!(RuntimeError(
+			format!("tried to manifest null at {}", path.join(".")).into()
+		)),
+		// nan/inf are valid in TOML, but can only be constructed by native code
+		Val::Num(n) if !n.is_finite() => throw!(RuntimeError(
+			format!(
+				"tried to manifest non-finite number {} at {}",
+				n,
+				path.join(".")
+			)
+			.into()
+		)),
+		// Integers not fitting in i64 are invalid in TOML, float notation is used instead
+		Val::Num(n) if n.fract() == 0.0 && n.abs() >= 9.223_372_036_854_776e18 => {
+			format!("{:e}", n)
+		}
+		Val::Num(n) => n.to_string(),
+		Val::Str(s) if s.contains('\n') => escape_toml_multiline(&s),
+		Val::Str(s) => escape_string_json(&s),
+		Val::Arr(items) if items.is_empty() => "[]".to_owned(),
+		Val::Arr(items) => {
+			let (new_padding, separator) = if inline {
+				(String::new(), " ")
+			} else {
+				(format!("{}{}", cur_padding, options.padding), "\n")
+			};
+			let mut out = String::from("[");
+			out.push_str(separator);
+			for (i, item) in items.iter().enumerate() {
+				if i != 0 {
+					out.push(',');
+					out.push_str(separator);
+				}
+				out.push_str(&new_padding);
+				out.push_str(&manifest_toml_value(&item?, path, true, "", options)?);
+			}
+			out.push_str(separator);
+			if !inline {
+				out.push_str(cur_padding);
+			}
+			out.push(']');
+			out
+		}
+		v @ Val::Obj(_) if toml_datetime(&v)?.is_some() => toml_datetime(&v)?.unwrap().to_string(),
+		Val::Obj(obj) => {
+			let mut out = String::from("{ ");
+			for (i, field) in obj.visible_fields().into_iter().enumerate() {
+				if i != 0 {
+					out.push_str(", ");
+				}
+				out.push_str(&escape_toml_key(&field));
+				out.push_str(" = ");
+				let value = obj.get(field)?.unwrap();
+				out.push_str(&manifest_toml_value(&value, path, true, "", options)?);
+			}
+			out.push_str(" }");
+			out
+		}
+		Val::Func(_) => throw!(RuntimeError(
+			format!("tried to manifest function at {}", path.join(".")).into()
+		)),
+		Val::Lazy(_) => unreachable!(),
+	})
+}
+
+fn manifest_toml_table_body(
+	obj: &ObjValue,
+	path: &mut Vec<Rc<str>>,
+	cur_padding: &str,
+	options: &ManifestTomlOptions<'_>,
+) -> Result<String> {
+	let mut values = Vec::new();
+	let mut sections = Vec::new();
+	for field in obj.visible_fields() {
+		let value = obj.get(field.clone())?.unwrap().unwrap_if_lazy()?;
+		path.push(field.clone());
+		if is_toml_section(&value)? {
+			sections.push(manifest_toml_section(&value, path, cur_padding, options)?);
+		} else {
+			values.push(format!(
+				"{}{} = {}",
+				cur_padding,
+				escape_toml_key(&field),
+				manifest_toml_value(&value, path, false, cur_padding, options)?
+			));
+		}
+		path.pop();
+	}
+	if !values.is_empty() {
+		sections.insert(0, values.join("\n"));
+	}
+	Ok(sections.join("\n\n"))
+}
+
+fn manifest_toml_section(
+	val: &Val,
+	path: &mut Vec<Rc<str>>,
+	cur_padding: &str,
+	options: &ManifestTomlOptions<'_>,
+) -> Result<String> {
+	let header = path
+		.iter()
+		.map(|k| escape_toml_key(k))
+		.collect::<Vec<_>>()
+		.join(".");
+	let new_padding = format!("{}{}", cur_padding, options.padding);
+	let mut tables = Vec::new();
+	let mut push_table = |obj: &ObjValue, brackets: (&str, &str)| -> Result<()> {
+		let mut out = format!("{}{}{}{}", cur_padding, brackets.0, header, brackets.1);
+		let body = manifest_toml_table_body(obj, path, &new_padding, options)?;
+		if !body.is_empty() {
+			out.push('\n');
+			out.push_str(&body);
+		}
+		tables.push(out);
+		Ok(())
+	};
+	match val {
+		Val::Obj(obj) => push_table(obj, ("[", "]"))?,
+		Val::Arr(items) => {
+			for item in items.iter() {
+				match item? {
+					Val::Obj(obj) => push_table(&obj, ("[[", "]]"))?,
+					_ => unreachable!("checked by is_toml_section"),
+				}
+			}
+		}
+		_ => unreachable!("checked by is_toml_section"),
+	}
+	Ok(tables.join("\n\n"))
+}
+
+pub fn manifest_toml_ex(val: &Val, options: &ManifestTomlOptions<'_>) -> Result<String> {
+	match val.unwrap_if_lazy()? {
+		Val::Obj(obj) => manifest_toml_table_body(&obj, &mut Vec::new(), "", options),
+		v => throw!(TypeMismatch(
+			"toml body",
+			vec![ValType::Obj],
+			v.value_type()?
+		)),
+	}
+}
+
+/// Json, in which every value is annotated with its type, i.e `{"$type": "number", "value": 5}`
+pub fn manifest_debug_json(val: &Val) -> Result<String> {
+	let mut out = String::new();
+	manifest_debug_json_buf(val, &mut out)?;
+	Ok(out)
+}
+fn manifest_debug_json_buf(val: &Val, buf: &mut String) -> Result<()> {
+	use std::fmt::Write;
+	let val = val.unwrap_if_lazy()?;
+	buf.push_str("{\"$type\": ");
+	buf.push_str(&escape_string_json(val.value_type()?.name()));
+	match &val {
+		Val::Func(f) => {
+			buf.push_str(", \"name\": ");
+			buf.push_str(&escape_string_json(&f.name()));
+			buf.push_str(", \"params\": ");
+			let params = match f as &FuncVal {
+				FuncVal::Normal(desc) => Some(&desc.params),
+				FuncVal::NativeExt(_, handler) => Some(&handler.params),
+				// Intrinsic parameters are only known to its implementation
+				FuncVal::Intrinsic(_) => None,
+			};
+			if let Some(params) = params {
+				buf.push('[');
+				for (i, param) in params.iter().enumerate() {
+					if i != 0 {
+						buf.push_str(", ");
+					}
+					buf.push_str(&escape_string_json(&param.0));
+				}
+				buf.push(']');
+			} else {
+				buf.push_str("null");
+			}
+		}
+		_ => {
+			buf.push_str(", \"value\": ");
+			match &val {
+				Val::Bool(v) => write!(buf, "{}", v).unwrap(),
+				Val::Null => buf.push_str("null"),
+				Val::Str(s) => buf.push_str(&escape_string_json(s)),
+				Val::Num(n) => write!(buf, "{}", n).unwrap(),
+				Val::Arr(items) => {
+					buf.push('[');
+					for (i, item) in items.iter().enumerate() {
+						if i != 0 {
+							buf.push_str(", ");
+						}
+						manifest_debug_json_buf(&item?, buf)?;
+					}
+					buf.push(']');
+				}
+				Val::Obj(obj) => {
+					buf.push('{');
+					for (i, field) in obj.visible_fields().into_iter().enumerate() {
+						if i != 0 {
+							buf.push_str(", ");
+						}
+						buf.push_str(&escape_string_json(&field));
+						buf.push_str(": ");
+						manifest_debug_json_buf(&obj.get(field)?.unwrap(), buf)?;
+					}
+					buf.push('}');
+				}
+				Val::Func(_) | Val::Lazy(_) => unreachable!(),
+			}
+		}
+	}
+	buf.push('}');
+	Ok(())
+}
+
+fn push_json_path(path: &mut String, segment: &str) {
+	if !path.is_empty() {
+		path.push('.');
+	}
+	path.push_str(segment);
+}
+
+/// Non-empty arrays and objects, which are written on multiple lines
+fn is_json_block(val: &Val, options: &ManifestJsonOptions<'_>) -> Result<bool> {
+	Ok(match val {
+		Val::Arr(items) => !items.is_empty() && !is_inline_array(items, options)?,
+		Val::Obj(obj) if options.include_hidden => !obj.fields().is_empty(),
+		Val::Obj(obj) => !obj.visible_fields().is_empty(),
+		_ => false,
+	})
+}
+
+/// Width, left for single line rendering in [`push_json_if_fits`]
+struct WidthBudget {
+	/// `None` once output doesn't fit
+	remaining: Option<usize>,
+	/// Length of output prefix, which is already subtracted from `remaining`
+	counted: usize,
+}
+
+/// Subtracts output, written since last call, from budget. Returns true, if it doesn't fit
+fn width_exceeded(budget: &mut Option<WidthBudget>, buf: &str) -> bool {
+	let budget = match budget {
+		Some(budget) => budget,
+		None => return false,
+	};
+	if let Some(remaining) = budget.remaining {
+		let added = buf[budget.counted..].chars().count();
+		budget.counted = buf.len();
+		budget.remaining = remaining.checked_sub(added);
+	}
+	budget.remaining.is_none()
+}
+
+/// Writes value on single line, if it fits into `max_line_length` together with already written
+/// part of current line. Rendering stops as soon as line gets too long, so large values are not
+/// rendered in full for every enclosing block
+fn push_json_if_fits(
+	val: &Val,
+	buf: &mut String,
+	max_line_length: usize,
+	options: &ManifestJsonOptions<'_>,
+) -> Result<bool> {
+	let line_start = buf.rfind('\n').map_or(0, |i| i + 1);
+	let column = buf[line_start..].chars().count();
+	let mut budget = Some(WidthBudget {
+		remaining: max_line_length.checked_sub(column),
+		counted: 0,
+	});
+	let mut inline = String::new();
+	manifest_json_ex_buf(
+		val,
+		&mut inline,
+		&mut None,
+		&mut String::new(),
+		&mut String::new(),
+		&mut budget,
+		&ManifestJsonOptions {
+			padding: "",
+			mtype: ManifestType::ToString,
+			max_line_length: None,
+			..*options
+		},
+	)?;
+	if width_exceeded(&mut budget, &inline) {
+		return Ok(false);
+	}
+	buf.push_str(&inline);
+	Ok(true)
+}
+
+/// Very large and very small numbers are written in scientific notation, i.e `1e21`
+fn manifest_json_number(n: f64, buf: &mut String, options: &ManifestJsonOptions<'_>) -> Result<()> {
+	use std::fmt::Write;
+	// Not representable in JSON, can only be constructed by native code
+	if !n.is_finite() {
+		throw!(RuntimeError(
+			format!("tried to manifest non-finite number {}", n).into()
+		));
+	}
+	let abs = n.abs();
+	let force_decimal =
+		n.fract() == 0.0 && matches!(options.decimal_integers_below, Some(limit) if abs < limit);
+	match options.number_format {
+		NumberFormat::Precision(precision)
+			if n.fract() != 0.0 || (!force_decimal && abs >= 1e21) =>
+		{
+			push_number_precision(n, precision, buf)
+		}
+		_ if !force_decimal && n != 0.0 && !(1e-7..1e21).contains(&abs) => {
+			write!(buf, "{:e}", n).unwrap()
+		}
+		_ => write!(buf, "{}", n).unwrap(),
+	}
+	Ok(())
+}
+
+/// Same as `%.{precision}g` in C: scientific notation is used for exponents below `-4`,
+/// or not less than precision, trailing zeros are removed
+fn push_number_precision(n: f64, precision: usize, buf: &mut String) {
+	use std::fmt::Write;
+	fn trim_zeros(s: &str) -> &str {
+		if s.contains('.') {
+			s.trim_end_matches('0').trim_end_matches('.')
+		} else {
+			s
+		}
+	}
+	let precision = precision.max(1);
+	// Exponent is taken after rounding, as 9.99 becomes 1e1 with precision of 2
+	let scientific = format!("{:.*e}", precision - 1, n);
+	let (mantissa, exp) = scientific.split_once('e').expect("exponent is written");
+	let exp: i32 = exp.parse().expect("exponent is integer");
+	if exp < -4 || exp >= precision as i32 {
+		buf.push_str(trim_zeros(mantissa));
+		let sign = if exp < 0 { '-' } else { '+' };
+		write!(buf, "e{}{:02}", sign, exp.abs()).unwrap();
+	} else {
+		let fixed = format!("{:.*}", (precision as i32 - 1 - exp) as usize, n);
+		buf.push_str(trim_zeros(&fixed));
+	}
+}
+
+fn is_inline_array(items: &ArrValue, options: &ManifestJsonOptions<'_>) -> Result<bool> {
+	if options.mtype == ManifestType::ToString || options.mtype == ManifestType::Minify {
+		return Ok(false);
+	}
+	match options.inline_array_threshold {
+		Some(threshold) if !items.is_empty() && items.len() < threshold => {}
+		_ => return Ok(false),
+	}
+	for item in items.iter() {
+		if matches!(item?, Val::Arr(_) | Val::Obj(_) | Val::Func(_)) {
+			return Ok(false);
+		}
+	}
+	Ok(true)
+}
+
 pub fn escape_string_json(s: &str) -> String {
+	escape_string_json_ex(s, false)
+}
+pub fn escape_string_json_ex(s: &str, escape_unicode: bool) -> String {
 	use std::fmt::Write;
 	let mut out = String::new();
 	out.push('"');
@@ -138,6 +1063,12 @@ pub fn escape_string_json(s: &str) -> String {
 			c if c < 32 as char || (c >= 127 as char && c <= 159 as char) => {
 				write!(out, "\\u{:04x}", c as u32).unwrap()
 			}
+			c if escape_unicode && !c.is_ascii() => {
+				let mut units = [0; 2];
+				for unit in c.encode_utf16(&mut units) {
+					write!(out, "\\u{:04x}", unit).unwrap()
+				}
+			}
 			c => out.push(c),
 		}
 	}
@@ -145,7 +1076,1378 @@ pub fn escape_string_json(s: &str) -> String {
 	out
 }
 
+#[derive(PartialEq, Eq, Clone, Copy, Default)]
+pub enum QuoteStrings {
+	/// Quote strings only when plain scalar would be invalid, or parsed as non-string value
+	#[default]
+	Auto,
+	/// Quote all strings
+	Always,
+	/// Quote strings only when plain scalar would be invalid
+	Never,
+}
+
+pub struct ManifestYamlOptions<'s> {
+	/// Padding before fields, i.e
+	/// ```yaml
+	/// a:
+	///   b:
+	/// ## <- this
+	/// ```
+	pub padding: &'s str,
+	/// Indent arrays, which are values of object fields, i.e
+	/// ```yaml
+	/// a:
+	///   - 1
+	/// ```
+	/// instead of
+	/// ```yaml
+	/// a:
+	/// - 1
+	/// ```
+	pub indent_array_in_object: bool,
+	pub quote_strings: QuoteStrings,
+	/// Limits nesting of manifested value, deeper values produce error instead of overflowing stack
+	pub max_depth: usize,
+	/// Write non-empty objects with only `true` values (sets) as sequences of their keys
+	pub set_objects: bool,
+	/// Objects of scalar values with fewer fields than this are written in flow style, i.e `{"a": 1, "b": 2}`
+	pub flow_object_threshold: Option<usize>,
+	pub field_order: FieldOrder,
+	/// Write explicit type tags before scalar values, i.e `!!int 1`, `!!str "1"`
+	pub type_tags: bool,
+}
+impl Default for ManifestYamlOptions<'_> {
+	fn default() -> Self {
+		Self {
+			padding: "  ",
+			indent_array_in_object: false,
+			quote_strings: QuoteStrings::default(),
+			max_depth: 1000,
+			set_objects: false,
+			flow_object_threshold: None,
+			field_order: FieldOrder::default(),
+			type_tags: false,
+		}
+	}
+}
+
+pub fn manifest_yaml_ex(val: &Val, options: &ManifestYamlOptions<'_>) -> Result<String> {
+	let mut out = String::new();
+	manifest_yaml_ex_buf(val, &mut out, &mut String::new(), 0, options)?;
+	Ok(out)
+}
+
+/// Non-empty arrays and objects are written on new line, other values are written inline
+fn is_yaml_block(val: &Val, options: &ManifestYamlOptions<'_>) -> Result<bool> {
+	Ok(match val {
+		Val::Arr(a) => !a.is_empty(),
+		Val::Obj(o) => {
+			o.fields_visibility().into_iter().any(|(_k, v)| v) && !is_yaml_flow_object(o, options)?
+		}
+		_ => false,
+	})
+}
+
+fn is_yaml_flow_object(obj: &ObjValue, options: &ManifestYamlOptions<'_>) -> Result<bool> {
+	let fields = obj.fields_ex(false, options.field_order);
+	match options.flow_object_threshold {
+		Some(threshold) if !fields.is_empty() && fields.len() < threshold => {}
+		_ => return Ok(false),
+	}
+	for field in fields {
+		if matches!(
+			obj.get(field)?.unwrap().unwrap_if_lazy()?,
+			Val::Arr(_) | Val::Obj(_) | Val::Func(_)
+		) {
+			return Ok(false);
+		}
+	}
+	Ok(true)
+}
+
+/// Converts set objects (`{a: true, b: true}`) to sequences of their keys, if enabled
+fn yaml_set_to_seq(val: Val, options: &ManifestYamlOptions<'_>) -> Result<Val> {
+	let obj = match &val {
+		Val::Obj(obj) if options.set_objects => obj,
+		_ => return Ok(val),
+	};
+	let fields = obj.fields_ex(false, options.field_order);
+	if fields.is_empty() {
+		return Ok(val);
+	}
+	for field in fields.iter() {
+		if !matches!(
+			obj.get(field.clone())?.unwrap().unwrap_if_lazy()?,
+			Val::Bool(true)
+		) {
+			return Ok(val);
+		}
+	}
+	Ok(Val::Arr(
+		fields
+			.into_iter()
+			.map(|f| Val::Str(f.into()))
+			.collect::<Vec<_>>()
+			.into(),
+	))
+}
+
+/// Tags are only written for scalars, type of collection is unambiguous
+fn push_yaml_type_tag(val: &Val, buf: &mut String) {
+	let tag = match val {
+		Val::Bool(_) => "!!bool ",
+		Val::Null => "!!null ",
+		Val::Str(_) => "!!str ",
+		Val::Num(n) if n.fract() == 0.0 => "!!int ",
+		Val::Num(_) => "!!float ",
+		_ => return,
+	};
+	buf.push_str(tag);
+}
+
+fn manifest_yaml_ex_buf(
+	val: &Val,
+	buf: &mut String,
+	cur_padding: &mut String,
+	depth: usize,
+	options: &ManifestYamlOptions<'_>,
+) -> Result<()> {
+	use std::fmt::Write;
+	if depth > options.max_depth {
+		throw!(RuntimeError("max manifest depth exceeded".into()));
+	}
+	let val = yaml_set_to_seq(val.unwrap_if_lazy()?, options)?;
+	if options.type_tags {
+		push_yaml_type_tag(&val, buf);
+	}
+	match val {
+		Val::Bool(v) => {
+			if v {
+				buf.push_str("true");
+			} else {
+				buf.push_str("false");
+			}
+		}
+		Val::Null => buf.push_str("null"),
+		Val::Str(s) => {
+			if s.is_empty() {
+				buf.push_str("\"\"");
+			} else if let Some(s) = s.strip_suffix('\n') {
+				buf.push('|');
+				for line in s.split('\n') {
+					buf.push('\n');
+					buf.push_str(cur_padding);
+					buf.push_str(options.padding);
+					buf.push_str(line);
+				}
+			} else {
+				manifest_yaml_string(&s, buf, options.quote_strings);
+			}
+		}
+		Val::Num(n) => write!(buf, "{}", n).unwrap(),
+		Val::Arr(items) => {
+			if items.is_empty() {
+				buf.push_str("[]");
+			} else {
+				for (i, item) in items.evaluated()?.iter().enumerate() {
+					if i != 0 {
+						buf.push('\n');
+						buf.push_str(cur_padding);
+					}
+					let item = yaml_set_to_seq(item.unwrap_if_lazy()?, options)?;
+					let prev_len = cur_padding.len();
+					buf.push('-');
+					match &item {
+						// While we could avoid the new line, it yields YAML that is
+						// hard to read
+						Val::Arr(a) if !a.is_empty() => {
+							cur_padding.push_str(options.padding);
+							buf.push('\n');
+							buf.push_str(cur_padding);
+						}
+						// Object can start on the same line as the `-`, because the indentation
+						// matches up then
+						item if is_yaml_block(item, options)? => {
+							cur_padding.push_str(options.padding);
+							buf.push(' ');
+						}
+						_ => buf.push(' '),
+					}
+					manifest_yaml_ex_buf(&item, buf, cur_padding, depth + 1, options)?;
+					cur_padding.truncate(prev_len);
+				}
+			}
+		}
+		Val::Obj(obj) => {
+			obj.run_assertions()?;
+			let fields = obj.fields_ex(false, options.field_order);
+			if fields.is_empty() {
+				buf.push_str("{}");
+			} else if is_yaml_flow_object(&obj, options)? {
+				buf.push('{');
+				for (i, field) in fields.into_iter().enumerate() {
+					if i != 0 {
+						buf.push_str(", ");
+					}
+					buf.push_str(&escape_string_json(&field));
+					buf.push_str(": ");
+					match obj.get(field)?.unwrap().unwrap_if_lazy()? {
+						// Neither block scalars, nor plain scalars with flow indicators are allowed here
+						Val::Str(s) => {
+							if options.type_tags {
+								buf.push_str("!!str ");
+							}
+							buf.push_str(&escape_string_json(&s));
+						}
+						value => {
+							manifest_yaml_ex_buf(&value, buf, cur_padding, depth + 1, options)?
+						}
+					}
+				}
+				buf.push('}');
+			} else {
+				for (i, field) in fields.into_iter().enumerate() {
+					if i != 0 {
+						buf.push('\n');
+						buf.push_str(cur_padding);
+					}
+					buf.push_str(&escape_string_json(&field));
+					buf.push(':');
+					let value =
+						yaml_set_to_seq(obj.get(field)?.unwrap().unwrap_if_lazy()?, options)?;
+					let prev_len = cur_padding.len();
+					match &value {
+						Val::Arr(a) if !a.is_empty() => {
+							if options.indent_array_in_object {
+								cur_padding.push_str(options.padding);
+							}
+							buf.push('\n');
+							buf.push_str(cur_padding);
+						}
+						value if is_yaml_block(value, options)? => {
+							cur_padding.push_str(options.padding);
+							buf.push('\n');
+							buf.push_str(cur_padding);
+						}
+						_ => buf.push(' '),
+					}
+					manifest_yaml_ex_buf(&value, buf, cur_padding, depth + 1, options)?;
+					cur_padding.truncate(prev_len);
+				}
+			}
+		}
+		Val::Func(_) => throw!(RuntimeError("tried to manifest function".into())),
+		Val::Lazy(_) => unreachable!(),
+	};
+	Ok(())
+}
+
+fn manifest_yaml_string(s: &str, buf: &mut String, quote: QuoteStrings) {
+	let plain = match quote {
+		QuoteStrings::Always => false,
+		QuoteStrings::Never => is_yaml_plain_safe(s),
+		QuoteStrings::Auto => is_yaml_plain_safe(s) && !is_yaml_plain_ambiguous(s),
+	};
+	if plain {
+		buf.push_str(s);
+	} else {
+		buf.push_str(&escape_string_json(s));
+	}
+}
+
+/// Is string representable as plain (unquoted) block scalar
+fn is_yaml_plain_safe(s: &str) -> bool {
+	let mut chars = s.chars();
+	let first = match chars.next() {
+		Some(c) => c,
+		None => return false,
+	};
+	match first {
+		// Indicators, which are only allowed at start if followed by non-space character
+		'-' | '?' | ':' => {
+			if matches!(chars.next(), None | Some(' ')) {
+				return false;
+			}
+		}
+		',' | '[' | ']' | '{' | '}' | '#' | '&' | '*' | '!' | '|' | '>' | '\'' | '"' | '%'
+		| '@' | '`' => return false,
+		_ => {}
+	}
+	!(s.starts_with(' ')
+		|| s.ends_with(' ')
+		|| s.ends_with(':')
+		|| s.starts_with("---")
+		|| s.starts_with("...")
+		|| s.contains(": ")
+		|| s.contains(" #")
+		|| s.chars().any(|c| c.is_control() || c == '\u{feff}'))
+}
+
+/// Plain scalars, which are parsed as null, booleans or special floats
+const YAML_RESERVED_SCALARS: &[&str] = &[
+	"~", "null", "Null", "NULL", "true", "True", "TRUE", "false", "False", "FALSE", "y", "Y",
+	"yes", "Yes", "YES", "n", "N", "no", "No", "NO", "on", "On", "ON", "off", "Off", "OFF", ".inf",
+	".Inf", ".INF", "+.inf", "+.Inf", "+.INF", "-.inf", "-.Inf", "-.INF", ".nan", ".NaN", ".NAN",
+];
+
+/// Would plain scalar be parsed as non-string value
+fn is_yaml_plain_ambiguous(s: &str) -> bool {
+	if YAML_RESERVED_SCALARS.contains(&s) {
+		return true;
+	}
+	let unsigned = s.strip_prefix(|c| c == '+' || c == '-').unwrap_or(s);
+	if !unsigned.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+		return false;
+	}
+	// YAML 1.1 timestamps, i.e 2001-12-14
+	let bytes = unsigned.as_bytes();
+	if bytes.len() >= 10
+		&& bytes[4] == b'-'
+		&& bytes[7] == b'-'
+		&& bytes[..10]
+			.iter()
+			.enumerate()
+			.all(|(i, c)| i == 4 || i == 7 || c.is_ascii_digit())
+	{
+		return true;
+	}
+	// Ints in other bases, i.e 0x1f, 0o17, 0b11
+	if unsigned.len() > 2
+		&& (unsigned.starts_with("0x") || unsigned.starts_with("0o") || unsigned.starts_with("0b"))
+	{
+		return true;
+	}
+	// YAML 1.1 allows digit separators and sexagesimal numbers (1:30)
+	let digits = unsigned.replace(['_', ':'], "");
+	digits.parse::<f64>().is_ok()
+}
+
 #[test]
 fn json_test() {
 	assert_eq!(escape_string_json("\u{001f}"), "\"\\u001f\"")
 }
+
+#[test]
+fn json_number_notation_test() {
+	let manifest = |n: f64, decimal_integers_below: Option<f64>| {
+		manifest_json_ex(
+			&Val::Num(n),
+			&ManifestJsonOptions {
+				decimal_integers_below,
+				..Default::default()
+			},
+		)
+		.unwrap()
+	};
+	assert_eq!(manifest(1e21, None), "1e21");
+	assert_eq!(manifest(1e21, Some(1e22)), "1000000000000000000000");
+	assert_eq!(manifest(1e22, Some(1e22)), "1e22");
+	assert_eq!(manifest(1.5e-10, Some(1e22)), "1.5e-10");
+	assert_eq!(manifest(123456789.5, None), "123456789.5");
+	assert_eq!(manifest(0.0, None), "0");
+}
+
+#[test]
+fn json_number_format_test() {
+	let manifest = |n: f64, number_format| {
+		manifest_json_ex(
+			&Val::Num(n),
+			&ManifestJsonOptions {
+				number_format,
+				..Default::default()
+			},
+		)
+		.unwrap()
+	};
+	let shortest = NumberFormat::ShortestRoundTrip;
+	assert_eq!(manifest(0.1 + 0.2, shortest), "0.30000000000000004");
+	assert_eq!(manifest(0.1, shortest), "0.1");
+	assert_eq!(manifest(5.0, shortest), "5");
+	assert_eq!(manifest(1e21, shortest), "1e21");
+	assert_eq!(manifest(1e-7, shortest), "0.0000001");
+	assert_eq!(manifest(1e-8, shortest), "1e-8");
+	assert_eq!(manifest(-2.5e-8, shortest), "-2.5e-8");
+
+	// Reference strings are produced by `printf("%.17g")`, as used by go-jsonnet
+	let go = NumberFormat::Precision(17);
+	assert_eq!(manifest(0.1 + 0.2, go), "0.30000000000000004");
+	assert_eq!(manifest(0.1, go), "0.10000000000000001");
+	assert_eq!(manifest(1.5, go), "1.5");
+	assert_eq!(manifest(5.0, go), "5");
+	assert_eq!(manifest(-123456789.0, go), "-123456789");
+	assert_eq!(manifest(1e21, go), "1e+21");
+	assert_eq!(manifest(1.0 / 3.0, go), "0.33333333333333331");
+	assert_eq!(manifest(1e-7 + 1e-22, go), "1.000000000000001e-07");
+	assert_eq!(manifest(0.0001, go), "0.0001");
+	assert_eq!(manifest(2.5e-300, go), "2.5e-300");
+
+	let short = NumberFormat::Precision(3);
+	assert_eq!(manifest(3.14159, short), "3.14");
+	assert_eq!(manifest(9.999, short), "10");
+	assert_eq!(manifest(1234.5, short), "1.23e+03");
+	assert_eq!(manifest(0.000012345, short), "1.23e-05");
+	assert_eq!(manifest(0.5, NumberFormat::Precision(0)), "0.5");
+}
+
+#[test]
+fn json_subnormal_number_test() {
+	use crate::builtin::parse::{parse_json, ParseJsonOptions};
+	let numbers = [
+		f64::MIN_POSITIVE,
+		-f64::MIN_POSITIVE,
+		// Smallest and largest subnormals
+		f64::from_bits(1),
+		f64::from_bits(0x000f_ffff_ffff_ffff),
+		f64::MIN_POSITIVE / 3.0,
+		f64::MAX,
+	];
+	for n in numbers.iter() {
+		assert!(n.is_normal() || n.is_subnormal());
+		let manifested = manifest_json_ex(&Val::Num(*n), &ManifestJsonOptions::default()).unwrap();
+		match parse_json(&manifested, &ParseJsonOptions::default()).unwrap() {
+			Val::Num(parsed) => assert_eq!(parsed.to_bits(), n.to_bits(), "{}", manifested),
+			_ => panic!("number expected"),
+		}
+	}
+	assert_eq!(
+		manifest_json_ex(
+			&Val::Num(f64::from_bits(1)),
+			&ManifestJsonOptions::default()
+		)
+		.unwrap(),
+		"5e-324"
+	);
+}
+
+#[test]
+fn json_brace_style_test() {
+	use crate::ArrValue;
+	let val = Val::Obj(ObjValue::from_values(vec![
+		(
+			"a".into(),
+			Val::Obj(ObjValue::from_values(vec![(
+				"b".into(),
+				Val::Arr(vec![Val::Num(1.0)].into()),
+			)])),
+		),
+		("c".into(), Val::Arr(ArrValue::new_eager())),
+	]));
+	let manifest = |brace_style| {
+		manifest_json_ex(
+			&val,
+			&ManifestJsonOptions {
+				padding: "  ",
+				brace_style,
+				..Default::default()
+			},
+		)
+		.unwrap()
+	};
+	assert_eq!(
+		manifest(BraceStyle::SameLine),
+		"{\n  \"a\": {\n    \"b\": [\n      1\n    ]\n  },\n  \"c\": []\n}"
+	);
+	assert_eq!(
+		manifest(BraceStyle::OwnLine),
+		"{\n  \"a\":\n  {\n    \"b\":\n    [\n      1\n    ]\n  },\n  \"c\": []\n}"
+	);
+}
+
+#[test]
+fn json_comments_test() {
+	let val = Val::Obj(ObjValue::from_values(vec![
+		("a".into(), Val::Num(1.0)),
+		(
+			"b".into(),
+			Val::Obj(ObjValue::from_values(vec![("c".into(), Val::Num(2.0))])),
+		),
+	]));
+	let comments = ObjValue::from_values(vec![
+		("a".into(), Val::Str("first".into())),
+		("b.c".into(), Val::Str("nested\nmultiline".into())),
+	]);
+	let manifest = |comments| {
+		manifest_json_ex(
+			&val,
+			&ManifestJsonOptions {
+				padding: "  ",
+				comments,
+				..Default::default()
+			},
+		)
+		.unwrap()
+	};
+	assert_eq!(
+		manifest(Some(&comments)),
+		"{\n  // first\n  \"a\": 1,\n  \"b\": {\n    // nested\n    // multiline\n    \"c\": 2\n  }\n}"
+	);
+	assert_eq!(
+		manifest(None),
+		"{\n  \"a\": 1,\n  \"b\": {\n    \"c\": 2\n  }\n}"
+	);
+}
+
+#[test]
+fn json_python_json_dumps_test() {
+	let val = Val::Obj(ObjValue::from_values(vec![
+		(
+			"b".into(),
+			Val::Arr(vec![Val::Num(1.0), Val::Num(2.5), Val::Str("é\n\"q\"😀".into())].into()),
+		),
+		("a".into(), Val::Obj(ObjValue::new_empty())),
+		("c".into(), Val::Arr(crate::ArrValue::new_eager())),
+		(
+			"d".into(),
+			Val::Obj(ObjValue::from_values(vec![
+				("x".into(), Val::Null),
+				("y".into(), Val::Bool(true)),
+			])),
+		),
+	]));
+	// json.dumps({"b": [1, 2.5, "é\n\"q\"😀"], "a": {}, "c": [], "d": {"x": None, "y": True}}, indent=2, sort_keys=True)
+	assert_eq!(
+		manifest_json_ex(&val, &ManifestJsonOptions::python_json_dumps()).unwrap(),
+		r#"{
+  "a": {},
+  "b": [
+    1,
+    2.5,
+    "\u00e9\n\"q\"\ud83d\ude00"
+  ],
+  "c": [],
+  "d": {
+    "x": null,
+    "y": true
+  }
+}"#
+	);
+}
+
+#[test]
+fn json_align_values_test() {
+	let val = Val::Obj(ObjValue::from_values(vec![
+		("a".into(), Val::Num(1.0)),
+		("long_key".into(), Val::Str("x".into())),
+		(
+			"mid".into(),
+			Val::Obj(ObjValue::from_values(vec![
+				("xy".into(), Val::Null),
+				("z".into(), Val::Bool(true)),
+			])),
+		),
+	]));
+	let options = ManifestJsonOptions {
+		padding: "  ",
+		align_values: true,
+		..Default::default()
+	};
+	assert_eq!(
+		manifest_json_ex(&val, &options).unwrap(),
+		r#"{
+  "a":        1,
+  "long_key": "x",
+  "mid":      {
+    "xy": null,
+    "z":  true
+  }
+}"#
+	);
+	// Only pretty output is aligned
+	assert_eq!(
+		manifest_json_ex(
+			&val,
+			&ManifestJsonOptions {
+				padding: "",
+				mtype: ManifestType::Minify,
+				..options
+			}
+		)
+		.unwrap(),
+		r#"{"a": 1,"long_key": "x","mid": {"xy": null,"z": true}}"#
+	);
+}
+
+#[test]
+fn assertions_before_output_test() {
+	let state = crate::EvaluationState::default();
+	state.with_stdlib();
+	let val = state
+		.evaluate_snippet_raw(
+			std::rc::Rc::new(std::path::PathBuf::from("raw.jsonnet")),
+			"{a: 1, b: 2, assert self.a == self.b : 'a != b'}".into(),
+		)
+		.unwrap();
+	state.run_in_state(|| {
+		let mut buf = String::new();
+		let err = manifest_json_ex_buf(
+			&val,
+			&mut buf,
+			&mut None,
+			&mut String::new(),
+			&mut String::new(),
+			&mut None,
+			&ManifestJsonOptions::default(),
+		)
+		.unwrap_err();
+		assert!(matches!(err.error(), AssertionFailed(msg) if &**msg == "a != b"));
+		assert_eq!(buf, "");
+
+		let mut buf = String::new();
+		assert!(manifest_yaml_ex_buf(
+			&val,
+			&mut buf,
+			&mut String::new(),
+			0,
+			&ManifestYamlOptions::default()
+		)
+		.is_err());
+		assert_eq!(buf, "");
+	});
+}
+
+#[test]
+fn yaml_flow_objects_test() {
+	let leaf = |values: Vec<(&str, Val)>| {
+		Val::Obj(ObjValue::from_values(
+			values.into_iter().map(|(k, v)| (k.into(), v)),
+		))
+	};
+	let val = leaf(vec![
+		(
+			"small",
+			leaf(vec![("a", Val::Num(1.0)), ("b", Val::Str("x, }".into()))]),
+		),
+		(
+			"large",
+			leaf(vec![
+				("a", Val::Num(1.0)),
+				("b", Val::Num(2.0)),
+				("c", Val::Num(3.0)),
+			]),
+		),
+		(
+			"nested",
+			leaf(vec![("inner", leaf(vec![("a", Val::Null)]))]),
+		),
+		(
+			"items",
+			Val::Arr(vec![leaf(vec![("a", Val::Bool(true))])].into()),
+		),
+	]);
+	let options = ManifestYamlOptions {
+		flow_object_threshold: Some(3),
+		..Default::default()
+	};
+	assert_eq!(
+		manifest_yaml_ex(&val, &options).unwrap(),
+		r#""items":
+- {"a": true}
+"large":
+  "a": 1
+  "b": 2
+  "c": 3
+"nested":
+  "inner": {"a": null}
+"small": {"a": 1, "b": "x, }"}"#
+	);
+	// Disabled by default
+	assert!(manifest_yaml_ex(&val, &ManifestYamlOptions::default())
+		.unwrap()
+		.starts_with("\"items\":\n- \"a\": true\n"));
+}
+
+#[test]
+fn field_order_test() {
+	use crate::EvaluationState;
+	use std::path::PathBuf;
+	let state = EvaluationState::default();
+	state.with_stdlib();
+	let val = state
+		.evaluate_snippet_raw(
+			Rc::new(PathBuf::from("raw.jsonnet")),
+			"{z: 1, a: {y: [], b: null}, m:: 2} + {a+: {c: 3}, b: 4, z: 5}".into(),
+		)
+		.unwrap();
+	state.run_in_state(|| {
+		assert_eq!(
+			manifest_yaml_ex(
+				&val,
+				&ManifestYamlOptions {
+					field_order: FieldOrder::AsDefined,
+					..Default::default()
+				}
+			)
+			.unwrap(),
+			r#""z": 5
+"a":
+  "y": []
+  "b": null
+  "c": 3
+"b": 4"#
+		);
+		assert_eq!(
+			manifest_yaml_ex(&val, &ManifestYamlOptions::default()).unwrap(),
+			r#""a":
+  "b": null
+  "c": 3
+  "y": []
+"b": 4
+"z": 5"#
+		);
+		let json = |field_order| {
+			manifest_json_ex(
+				&val,
+				&ManifestJsonOptions {
+					mtype: ManifestType::ToString,
+					field_order,
+					..Default::default()
+				},
+			)
+			.unwrap()
+		};
+		assert_eq!(
+			json(FieldOrder::AsDefined),
+			r#"{"z": 5, "a": {"y": [ ], "b": null, "c": 3}, "b": 4}"#
+		);
+		assert_eq!(
+			json(FieldOrder::Sorted),
+			r#"{"a": {"b": null, "c": 3, "y": [ ]}, "b": 4, "z": 5}"#
+		);
+	});
+}
+
+#[test]
+fn yaml_indent_array_in_object_test() {
+	use crate::builtin::parse::{parse_json, ParseJsonOptions};
+	let val = parse_json(
+		r#"{"a": {"b": [1, [2, 3], {"c": [4]}]}, "d": []}"#,
+		&ParseJsonOptions::default(),
+	)
+	.unwrap();
+	// Reference style keeps items at the key's indentation
+	assert_eq!(
+		manifest_yaml_ex(&val, &ManifestYamlOptions::default()).unwrap(),
+		r#""a":
+  "b":
+  - 1
+  -
+    - 2
+    - 3
+  - "c":
+    - 4
+"d": []"#
+	);
+	assert_eq!(
+		manifest_yaml_ex(
+			&val,
+			&ManifestYamlOptions {
+				indent_array_in_object: true,
+				..Default::default()
+			}
+		)
+		.unwrap(),
+		r#""a":
+  "b":
+    - 1
+    -
+      - 2
+      - 3
+    - "c":
+        - 4
+"d": []"#
+	);
+}
+
+#[test]
+fn yaml_null_in_array_test() {
+	use crate::builtin::parse::{parse_json, ParseJsonOptions};
+	let val = parse_json(
+		r#"{"a": [1, null, "null"], "c": [[null], null]}"#,
+		&ParseJsonOptions::default(),
+	)
+	.unwrap();
+	assert_eq!(
+		manifest_yaml_ex(&val, &ManifestYamlOptions::default()).unwrap(),
+		r#""a":
+- 1
+- null
+- "null"
+"c":
+-
+  - null
+- null"#
+	);
+}
+
+#[test]
+fn json_roundtrip_test() {
+	use crate::{
+		builtin::parse::{parse_json, ParseJsonOptions},
+		equals,
+	};
+	let inputs = [
+		r#""\b\f\n\r\t\u0000\u0001\u001f\u007f\u0080\u009f""#,
+		r#""mixed \u0007 bell\b\u000b\u001b[0m \"quoted\" \\ \/ \u2028\ud83d\ude00é""#,
+		r#"{"\b\f": ["\u0000", {"\t": null}], "": [true, false, -0, 0.1, 1e-8, 1e21, 5e-324, 1.7976931348623157e308]}"#,
+		"[[], {}, [[{}]], 12345678901234567890, -1.5e-300]",
+	];
+	let options = [
+		ManifestJsonOptions::default(),
+		ManifestJsonOptions {
+			padding: "  ",
+			mtype: ManifestType::Std,
+			..Default::default()
+		},
+		ManifestJsonOptions {
+			mtype: ManifestType::ToString,
+			..Default::default()
+		},
+		ManifestJsonOptions {
+			mtype: ManifestType::Minify,
+			escape_unicode: true,
+			..Default::default()
+		},
+	];
+	for input in inputs.iter() {
+		let val = parse_json(input, &ParseJsonOptions::default()).unwrap();
+		for options in options.iter() {
+			let manifested = manifest_json_ex(&val, options).unwrap();
+			let parsed = parse_json(&manifested, &ParseJsonOptions::default()).unwrap();
+			assert!(equals(&val, &parsed).unwrap(), "{}", manifested);
+		}
+	}
+}
+
+#[test]
+fn json_non_finite_number_test() {
+	for n in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY].iter() {
+		assert!(manifest_json_ex(&Val::Num(*n), &ManifestJsonOptions::default()).is_err());
+	}
+}
+
+#[test]
+fn json_null_token_test() {
+	let val = Val::Obj(ObjValue::from_values(vec![
+		("a".into(), Val::Null),
+		("b".into(), Val::Arr(vec![Val::Null, Val::Num(1.0)].into())),
+	]));
+	assert_eq!(
+		manifest_json_ex(
+			&val,
+			&ManifestJsonOptions {
+				mtype: ManifestType::ToString,
+				..Default::default()
+			}
+		)
+		.unwrap(),
+		r#"{"a": null, "b": [null, 1]}"#
+	);
+	assert_eq!(
+		manifest_json_ex(
+			&val,
+			&ManifestJsonOptions {
+				mtype: ManifestType::ToString,
+				null_token: r#""""#,
+				..Default::default()
+			}
+		)
+		.unwrap(),
+		r#"{"a": "", "b": ["", 1]}"#
+	);
+}
+
+#[test]
+fn json_escape_unicode_test() {
+	assert_eq!(escape_string_json_ex("é", true), "\"\\u00e9\"");
+	assert_eq!(escape_string_json_ex("😀", true), "\"\\ud83d\\ude00\"");
+	assert_eq!(escape_string_json_ex("😀", false), "\"😀\"");
+	// All escapes use the same hex case
+	assert_eq!(
+		escape_string_json_ex("\u{1f}\u{9f}\u{fe}😀", true),
+		"\"\\u001f\\u009f\\u00fe\\ud83d\\ude00\""
+	);
+}
+
+#[test]
+fn yaml_quote_strings_test() {
+	let manifest = |v: &str, quote_strings| {
+		manifest_yaml_ex(
+			&Val::Str(v.into()),
+			&ManifestYamlOptions {
+				quote_strings,
+				..Default::default()
+			},
+		)
+		.unwrap()
+	};
+	assert_eq!(manifest("hello", QuoteStrings::Auto), "hello");
+	assert_eq!(manifest("hello", QuoteStrings::Always), "\"hello\"");
+	assert_eq!(manifest("hello", QuoteStrings::Never), "hello");
+
+	assert_eq!(manifest("true", QuoteStrings::Auto), "\"true\"");
+	assert_eq!(manifest("true", QuoteStrings::Never), "true");
+	assert_eq!(manifest("a: b", QuoteStrings::Never), "\"a: b\"");
+
+	// Leading and trailing zeros would be lost, if parsed as number
+	assert_eq!(manifest("007", QuoteStrings::Auto), "\"007\"");
+	assert_eq!(manifest("1.0", QuoteStrings::Auto), "\"1.0\"");
+	assert_eq!(manifest("1.0", QuoteStrings::Always), "\"1.0\"");
+
+	// Digit followed by a multibyte char shouldn't be sliced mid-char
+	assert_eq!(manifest("0é", QuoteStrings::Auto), "0é");
+	assert_eq!(manifest("1€", QuoteStrings::Auto), "1€");
+}
+
+#[test]
+fn yaml_indicator_keys_test() {
+	let obj = || {
+		ObjValue::from_values(vec![
+			("@timestamp".into(), Val::Num(1.0)),
+			("!tag".into(), Val::Bool(true)),
+			("#comment".into(), Val::Null),
+			("a: b".into(), Val::Str("c".into())),
+		])
+	};
+	let val = Val::Obj(ObjValue::from_values(vec![(
+		"&anchor".into(),
+		Val::Obj(obj()),
+	)]));
+	let manifest =
+		|val: &Val, options: &ManifestYamlOptions<'_>| manifest_yaml_ex(val, options).unwrap();
+	// Keys are always written as double-quoted scalars, so indicators can't be misparsed
+	assert_eq!(
+		manifest(&val, &ManifestYamlOptions::default()),
+		"\"&anchor\":\n  \"!tag\": true\n  \"#comment\": null\n  \"@timestamp\": 1\n  \"a: b\": c"
+	);
+	assert_eq!(
+		manifest(
+			&val,
+			&ManifestYamlOptions {
+				flow_object_threshold: Some(10),
+				..Default::default()
+			}
+		),
+		"\"&anchor\": {\"!tag\": true, \"#comment\": null, \"@timestamp\": 1, \"a: b\": \"c\"}"
+	);
+	// Set objects write keys as sequence items, which are quoted like other strings
+	let set = Val::Obj(ObjValue::from_values(vec![
+		("@a".into(), Val::Bool(true)),
+		("!b".into(), Val::Bool(true)),
+		("#c".into(), Val::Bool(true)),
+		("d".into(), Val::Bool(true)),
+	]));
+	assert_eq!(
+		manifest(
+			&set,
+			&ManifestYamlOptions {
+				set_objects: true,
+				..Default::default()
+			}
+		),
+		"- \"!b\"\n- \"#c\"\n- \"@a\"\n- d"
+	);
+}
+
+#[test]
+fn json_inline_array_test() {
+	let arr = |len: usize| {
+		Val::Arr(
+			(0..len)
+				.map(|i| Val::Num(i as f64))
+				.collect::<Vec<_>>()
+				.into(),
+		)
+	};
+	let options = ManifestJsonOptions {
+		padding: "  ",
+		inline_array_threshold: Some(4),
+		..Default::default()
+	};
+	assert_eq!(manifest_json_ex(&arr(3), &options).unwrap(), "[0, 1, 2]");
+	assert_eq!(
+		manifest_json_ex(&arr(4), &options).unwrap(),
+		"[\n  0,\n  1,\n  2,\n  3\n]"
+	);
+	assert_eq!(
+		manifest_json_ex(&Val::Arr(vec![arr(1)].into()), &options).unwrap(),
+		"[\n  [0]\n]"
+	);
+}
+
+#[test]
+fn yaml_set_objects_test() {
+	let val = Val::Obj(ObjValue::from_values(vec![
+		(
+			"set".into(),
+			Val::Obj(ObjValue::from_values(vec![
+				("b".into(), Val::Bool(true)),
+				("a".into(), Val::Bool(true)),
+			])),
+		),
+		(
+			"map".into(),
+			Val::Obj(ObjValue::from_values(vec![
+				("a".into(), Val::Bool(true)),
+				("b".into(), Val::Bool(false)),
+			])),
+		),
+	]));
+	let manifest = |set_objects| {
+		manifest_yaml_ex(
+			&val,
+			&ManifestYamlOptions {
+				set_objects,
+				..Default::default()
+			},
+		)
+		.unwrap()
+	};
+	assert_eq!(
+		manifest(true),
+		"\"map\":\n  \"a\": true\n  \"b\": false\n\"set\":\n- a\n- b"
+	);
+	assert_eq!(
+		manifest(false),
+		"\"map\":\n  \"a\": true\n  \"b\": false\n\"set\":\n  \"a\": true\n  \"b\": true"
+	);
+}
+
+#[test]
+fn yaml_depth_limit_test() {
+	let nested = |depth: usize| {
+		let mut val = Val::Null;
+		for _ in 0..depth {
+			val = Val::Arr(vec![val].into());
+		}
+		val
+	};
+	let options = ManifestYamlOptions::default();
+	assert!(manifest_yaml_ex(&nested(500), &options).is_ok());
+	let err = manifest_yaml_ex(&nested(5000), &options).unwrap_err();
+	assert!(matches!(err.error(), RuntimeError(e) if &**e == "max manifest depth exceeded"));
+}
+
+#[test]
+fn json_large_eager_array_test() {
+	use crate::{resolved_lazy_val, ArrValue};
+	let len = 100_000;
+	let eager = Val::Arr(ArrValue::Eager(std::rc::Rc::new(
+		(0..len).map(|i| Val::Num(i as f64)).collect(),
+	)));
+	let lazy = Val::Arr(ArrValue::Lazy(std::rc::Rc::new(
+		(0..len)
+			.map(|i| resolved_lazy_val!(Val::Num(i as f64)))
+			.collect(),
+	)));
+	let options = ManifestJsonOptions {
+		padding: "",
+		mtype: ManifestType::Minify,
+		..Default::default()
+	};
+	let expected = format!(
+		"[{}]",
+		(0..len)
+			.map(|i| i.to_string())
+			.collect::<Vec<_>>()
+			.join(",")
+	);
+	assert_eq!(manifest_json_ex(&eager, &options).unwrap(), expected);
+	assert_eq!(manifest_json_ex(&lazy, &options).unwrap(), expected);
+}
+
+#[test]
+fn toml_non_finite_number_test() {
+	use crate::ObjValue;
+	for n in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY].iter() {
+		let val = Val::Obj(ObjValue::from_values(vec![("a".into(), Val::Num(*n))]));
+		assert!(manifest_toml_ex(&val, &ManifestTomlOptions { padding: "  " }).is_err());
+	}
+}
+
+#[test]
+fn json_write_test() {
+	let state = crate::EvaluationState::default();
+	state.with_stdlib();
+	let val = state
+		.evaluate_snippet_raw(
+			std::rc::Rc::new(std::path::PathBuf::from("raw.jsonnet")),
+			"{a: [{b: std.repeat('x', 1000)} for _ in std.range(1, 200)], c: 'ü', d: []}".into(),
+		)
+		.unwrap();
+	state.run_in_state(|| {
+		for padding in [0, 4].iter() {
+			let mut out = Vec::new();
+			val.write_json(&mut out, *padding).unwrap();
+			assert!(out.len() > JSON_STREAM_CHUNK);
+			assert_eq!(out, val.to_json(*padding).unwrap().as_bytes());
+		}
+		let mut out = Vec::new();
+		manifest_json_ex_to(&val, &mut out, &ManifestJsonOptions::default()).unwrap();
+		assert_eq!(
+			out,
+			manifest_json_ex(&val, &ManifestJsonOptions::default())
+				.unwrap()
+				.as_bytes()
+		);
+	});
+}
+
+#[test]
+fn deterministic_output_test() {
+	use crate::EvaluationState;
+	use std::path::PathBuf;
+	let keys = (0..50)
+		.map(|i| format!("k{}", (i * 37) % 50))
+		.collect::<Vec<_>>();
+	let json = format!(
+		"{{{}}}",
+		keys.iter()
+			.map(|k| format!("\\\"{}\\\": 1", k))
+			.collect::<Vec<_>>()
+			.join(", ")
+	);
+	let code = format!(
+		"{{parsed: std.parseJson(\"{json}\"), yaml: std.parseYaml(\"{json}\"), obj: {{b: 1, a: [{{d: 1, c: 2}}]}}}}",
+		json = json
+	);
+	let manifest = || {
+		// Fresh state for every evaluation
+		let state = EvaluationState::default();
+		state.with_stdlib();
+		let val = state
+			.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.as_str().into())
+			.unwrap();
+		state.run_in_state(|| {
+			[FieldOrder::Sorted, FieldOrder::AsDefined]
+				.iter()
+				.map(|field_order| {
+					manifest_json_ex(
+						&val,
+						&ManifestJsonOptions {
+							field_order: *field_order,
+							..Default::default()
+						},
+					)
+					.unwrap()
+				})
+				.collect::<Vec<_>>()
+		})
+	};
+	let first = manifest();
+	for _ in 0..5 {
+		assert_eq!(manifest(), first);
+	}
+	// Parsed objects keep source order
+	let expected = keys
+		.iter()
+		.map(|k| format!("\"{}\"", k))
+		.collect::<Vec<_>>();
+	let parsed_keys = first[1]
+		.lines()
+		.filter(|l| l.starts_with("\"k"))
+		.map(|l| l.split(':').next().unwrap())
+		.collect::<Vec<_>>();
+	assert_eq!(parsed_keys.len(), 100);
+	assert_eq!(&parsed_keys[..50], &expected[..]);
+	assert_eq!(&parsed_keys[50..], &expected[..]);
+}
+
+#[test]
+fn json_functions_test() {
+	use crate::EvaluationState;
+	use std::path::PathBuf;
+	let state = EvaluationState::default();
+	state.with_stdlib();
+	let val = state
+		.evaluate_snippet_raw(
+			Rc::new(PathBuf::from("raw.jsonnet")),
+			"local f(x) = x; {a: 1, f: f, g: std.length, h: [1, function(x) x]}".into(),
+		)
+		.unwrap();
+	let manifest = |functions| {
+		state.run_in_state(|| {
+			manifest_json_ex(
+				&val,
+				&ManifestJsonOptions {
+					mtype: ManifestType::Minify,
+					functions,
+					..Default::default()
+				},
+			)
+		})
+	};
+	let err = manifest(FunctionHandling::Error).unwrap_err();
+	assert!(matches!(err.error(), RuntimeError(e) if &**e == "tried to manifest function"));
+	assert_eq!(
+		manifest(FunctionHandling::Placeholder).unwrap(),
+		r#"{"a": 1,"f": "<function f>","g": "<function std.length>","h": [1,"<function anonymous>"]}"#
+	);
+	assert_eq!(
+		manifest(FunctionHandling::Skip).unwrap(),
+		r#"{"a": 1,"h": [1,null]}"#
+	);
+}
+
+#[test]
+fn json_max_line_length_test() {
+	use crate::builtin::parse::{parse_json, ParseJsonOptions};
+	let val = parse_json(
+		r#"{"short": {"a": 1, "b": [1, 2]}, "long": {"key": "some long value", "other": [1, 2, 3, 4, 5]}}"#,
+		&ParseJsonOptions::default(),
+	)
+	.unwrap();
+	let manifest = |max_line_length| {
+		manifest_json_ex(
+			&val,
+			&ManifestJsonOptions {
+				padding: "  ",
+				max_line_length: Some(max_line_length),
+				field_order: FieldOrder::AsDefined,
+				..Default::default()
+			},
+		)
+		.unwrap()
+	};
+	assert_eq!(
+		manifest(40),
+		r#"{
+  "short": {"a": 1, "b": [1, 2]},
+  "long": {
+    "key": "some long value",
+    "other": [1, 2, 3, 4, 5]
+  }
+}"#
+	);
+	// Limit is inclusive, separating comma is not counted
+	let line = r#"  "short": {"a": 1, "b": [1, 2]}"#;
+	assert!(manifest(line.len()).starts_with(&format!("{{\n{},\n", line)));
+	assert!(manifest(line.len() - 1).starts_with("{\n  \"short\": {\n"));
+	assert!(manifest(line.len() - 1).contains("\"b\": [1, 2]"));
+	assert_eq!(
+		manifest(1000),
+		r#"{"short": {"a": 1, "b": [1, 2]}, "long": {"key": "some long value", "other": [1, 2, 3, 4, 5]}}"#
+	);
+
+	// Width is counted in chars, not bytes
+	let val = parse_json(r#"{"a": ["éééééééééé"]}"#, &ParseJsonOptions::default()).unwrap();
+	let manifest = |max_line_length| {
+		manifest_json_ex(
+			&val,
+			&ManifestJsonOptions {
+				padding: "  ",
+				max_line_length: Some(max_line_length),
+				..Default::default()
+			},
+		)
+		.unwrap()
+	};
+	assert_eq!(manifest(21), r#"{"a": ["éééééééééé"]}"#);
+	assert_eq!(manifest(20), "{\n  \"a\": [\n    \"éééééééééé\"\n  ]\n}");
+}
+
+#[test]
+fn natural_field_order_test() {
+	use crate::EvaluationState;
+	use std::path::PathBuf;
+	let state = EvaluationState::default();
+	state.with_stdlib();
+	let val = state
+		.evaluate_snippet_raw(
+			Rc::new(PathBuf::from("raw.jsonnet")),
+			"{item10: 1, item2: 2, item1: 3, item02: 4, b: 5}".into(),
+		)
+		.unwrap();
+	state.run_in_state(|| {
+		let json = |field_order| {
+			manifest_json_ex(
+				&val,
+				&ManifestJsonOptions {
+					mtype: ManifestType::ToString,
+					field_order,
+					..Default::default()
+				},
+			)
+			.unwrap()
+		};
+		assert_eq!(
+			json(FieldOrder::Natural),
+			r#"{"b": 5, "item1": 3, "item2": 2, "item02": 4, "item10": 1}"#
+		);
+		assert_eq!(
+			json(FieldOrder::Sorted),
+			r#"{"b": 5, "item02": 4, "item1": 3, "item10": 1, "item2": 2}"#
+		);
+	});
+}
+
+#[test]
+fn json_key_order_test() {
+	use crate::builtin::parse::{parse_json, ParseJsonOptions};
+	let val = parse_json(
+		r#"{"spec": {"replicas": 1}, "status": {}, "metadata": {"name": "x", "kind": "y"}, "kind": "Deployment", "apiVersion": "apps/v1"}"#,
+		&ParseJsonOptions::default(),
+	)
+	.unwrap();
+	let key_order: Vec<Rc<str>> = vec!["apiVersion".into(), "kind".into(), "metadata".into()];
+	assert_eq!(
+		manifest_json_ex(
+			&val,
+			&ManifestJsonOptions {
+				padding: "  ",
+				key_order: &key_order,
+				..Default::default()
+			},
+		)
+		.unwrap(),
+		r#"{
+  "apiVersion": "apps/v1",
+  "kind": "Deployment",
+  "metadata": {
+    "kind": "y",
+    "name": "x"
+  },
+  "spec": {
+    "replicas": 1
+  },
+  "status": {}
+}"#
+	);
+}
+
+#[test]
+fn yaml_type_tags_test() {
+	use crate::builtin::parse::{parse_json, ParseJsonOptions};
+	let val = parse_json(
+		r#"{"s": "1", "i": 1, "f": 1.5, "b": true, "n": null, "l": ["a\n", 2, {}], "o": {"x": "y"}}"#,
+		&ParseJsonOptions::default(),
+	)
+	.unwrap();
+	let manifest = |flow_object_threshold| {
+		manifest_yaml_ex(
+			&val,
+			&ManifestYamlOptions {
+				type_tags: true,
+				flow_object_threshold,
+				..Default::default()
+			},
+		)
+		.unwrap()
+	};
+	assert_eq!(
+		manifest(None),
+		r#""b": !!bool true
+"f": !!float 1.5
+"i": !!int 1
+"l":
+- !!str |
+  a
+- !!int 2
+- {}
+"n": !!null null
+"o":
+  "x": !!str "y"
+"s": !!str "1""#
+	);
+	assert!(manifest(Some(2)).contains(r#""o": {"x": !!str "y"}"#));
+}
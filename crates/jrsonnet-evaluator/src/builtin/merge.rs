@@ -0,0 +1,81 @@
+use crate::{error::Result, ObjValue, Val};
+
+/// Recursively merges objects, values from `b` replace values from `a` in any other case,
+/// except for arrays, which are concatenated if `concat_arrays` is set
+pub fn deep_merge(a: &Val, b: &Val, concat_arrays: bool) -> Result<Val> {
+	Ok(match (a.unwrap_if_lazy()?, b.unwrap_if_lazy()?) {
+		(Val::Obj(a), Val::Obj(b)) => {
+			let mut values = Vec::new();
+			let a_fields = a.visible_fields();
+			let b_fields = b.visible_fields();
+			for field in a_fields.iter().cloned() {
+				if b_fields.binary_search(&field).is_err() {
+					let value = a.get(field.clone())?.expect("field exists");
+					values.push((field, value));
+				}
+			}
+			for field in b_fields {
+				let value = b.get(field.clone())?.expect("field exists");
+				let value = if a_fields.binary_search(&field).is_ok() {
+					let a_value = a.get(field.clone())?.expect("field exists");
+					deep_merge(&a_value, &value, concat_arrays)?
+				} else {
+					value
+				};
+				values.push((field, value));
+			}
+			Val::Obj(ObjValue::from_values(values))
+		}
+		(Val::Arr(a), Val::Arr(b)) if concat_arrays => {
+			let mut out = Vec::with_capacity(a.len() + b.len());
+			out.extend(a.iter_lazy());
+			out.extend(b.iter_lazy());
+			Val::Arr(out.into())
+		}
+		(_, b) => b,
+	})
+}
+
+/// Applies JSON merge patch (RFC 7386) to `target`: `null` fields of `patch` delete
+/// corresponding fields, object fields are patched recursively, anything else replaces target
+pub fn merge_patch(target: &Val, patch: &Val) -> Result<Val> {
+	let patch = match patch.unwrap_if_lazy()? {
+		Val::Obj(patch) => patch,
+		patch => return Ok(patch),
+	};
+	let target = match target.unwrap_if_lazy()? {
+		Val::Obj(target) => Some(target),
+		_ => None,
+	};
+	let target_fields = target
+		.as_ref()
+		.map_or_else(Vec::new, ObjValue::visible_fields);
+	let patch_fields = patch.visible_fields();
+
+	let mut values = Vec::new();
+	if let Some(target) = &target {
+		for field in target_fields.iter().cloned() {
+			if patch_fields.binary_search(&field).is_err() {
+				let value = target.get(field.clone())?.expect("field exists");
+				values.push((field, value));
+			}
+		}
+	}
+	for field in patch_fields.iter().cloned() {
+		let value = patch
+			.get(field.clone())?
+			.expect("field exists")
+			.unwrap_if_lazy()?;
+		if matches!(value, Val::Null) {
+			continue;
+		}
+		let target_value = match &target {
+			Some(target) if target_fields.binary_search(&field).is_ok() => {
+				target.get(field.clone())?.expect("field exists")
+			}
+			_ => Val::Null,
+		};
+		values.push((field, merge_patch(&target_value, &value)?));
+	}
+	Ok(Val::Obj(ObjValue::from_values(values)))
+}
@@ -1,19 +1,29 @@
 use crate::{
-	equals,
+	compare, equals, equals_set,
 	error::{Error::*, Result},
-	evaluate, parse_args, primitive_equals, push, throw, with_state, Context, FuncVal, Val,
-	ValType,
+	evaluate, parse_args, primitive_equals, push, throw, with_state, ArrValue, ArrValueBuilder,
+	Context, FuncVal, LazyVal, ObjValue, Val, ValType,
 };
 use format::{format_arr, format_obj};
 use jrsonnet_parser::{ArgsDesc, ExprLocation};
-use manifest::{escape_string_json, manifest_json_ex, ManifestJsonOptions, ManifestType};
-use std::{path::PathBuf, rc::Rc};
+use manifest::{
+	escape_string_json, manifest_ini_ex, manifest_json_ex, manifest_toml_ex, manifest_yaml_ex,
+	IniArrayMode, ManifestIniOptions, ManifestJsonOptions, ManifestTomlOptions, ManifestType,
+	ManifestYamlOptions, QuoteStrings,
+};
+use merge::{deep_merge, merge_patch};
+use parse::{parse_json, parse_yaml, ParseJsonOptions};
+use std::{cmp::Ordering, path::PathBuf, rc::Rc};
 
 pub mod stdlib;
 pub use stdlib::*;
 
 pub mod format;
 pub mod manifest;
+pub mod merge;
+pub mod parse;
+#[cfg(feature = "regex-intrinsics")]
+pub mod regexp;
 pub mod sort;
 
 #[allow(clippy::cognitive_complexity)]
@@ -44,7 +54,7 @@ pub fn call_builtin(
 		"type" => parse_args!(context, "std.type", args, 1, [
 			0, x, vec![];
 		], {
-			Ok(Val::Str(x.value_type()?.name().into()))
+			Ok(Val::Str(x.unwrap_if_lazy()?.type_name().into()))
 		})?,
 		// length, idx=>any
 		"makeArray" => parse_args!(context, "std.makeArray", args, 2, [
@@ -61,7 +71,7 @@ pub fn call_builtin(
 					&[Val::Num(i as f64)]
 				)?)
 			}
-			Ok(Val::Arr(Rc::new(out)))
+			Ok(Val::Arr(out.into()))
 		})?,
 		// string
 		"codepoint" => parse_args!(context, "std.codepoint", args, 1, [
@@ -73,6 +83,36 @@ pub fn call_builtin(
 			);
 			Ok(Val::Num(str.chars().take(1).next().unwrap() as u32 as f64))
 		})?,
+		// object
+		"objectKeysValues" => parse_args!(context, "std.objectKeysValues", args, 1, [
+			0, obj: [Val::Obj]!!Val::Obj, vec![ValType::Obj];
+		], {
+			Ok(Val::Arr(object_keys_values(&obj, obj.visible_fields())))
+		})?,
+		// object
+		"objectKeysValuesAll" => parse_args!(context, "std.objectKeysValuesAll", args, 1, [
+			0, obj: [Val::Obj]!!Val::Obj, vec![ValType::Obj];
+		], {
+			Ok(Val::Arr(object_keys_values(&obj, obj.fields())))
+		})?,
+		// object, keys
+		"objectPick" => parse_args!(context, "std.objectPick", args, 2, [
+			0, obj: [Val::Obj]!!Val::Obj, vec![ValType::Obj];
+			1, keys: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+		], {
+			let keys = keys.iter()
+				.map(|key| key?.try_cast_str("objectPick key"))
+				.collect::<Result<Vec<_>>>()?;
+			let keys = keys.iter().map(|key| &**key).collect::<Vec<_>>();
+			Ok(Val::Obj(obj.pick(&keys)))
+		})?,
+		// object, key
+		"objectRemoveKey" => parse_args!(context, "std.objectRemoveKey", args, 2, [
+			0, obj: [Val::Obj]!!Val::Obj, vec![ValType::Obj];
+			1, key: [Val::Str]!!Val::Str, vec![ValType::Str];
+		], {
+			Ok(Val::Obj(obj.remove_key(&key)))
+		})?,
 		// object, includeHidden
 		"objectFieldsEx" => parse_args!(context, "std.objectFieldsEx",args, 2, [
 			0, obj: [Val::Obj]!!Val::Obj, vec![ValType::Obj];
@@ -84,7 +124,7 @@ pub fn call_builtin(
 				.map(|(k, _v)|k)
 				.collect::<Vec<_>>();
 			out.sort();
-			Ok(Val::Arr(Rc::new(out.into_iter().map(Val::Str).collect())))
+			Ok(Val::Arr(out.into_iter().map(|s| Val::Str(s.into())).collect::<Vec<_>>().into()))
 		})?,
 		// object, field, includeHidden
 		"objectHasEx" => parse_args!(context, "std.objectHasEx", args, 3, [
@@ -99,6 +139,20 @@ pub fn call_builtin(
 					.any(|(k, _v)| *k == *f),
 			))
 		})?,
+		#[allow(non_snake_case)]
+		"getImpl" => parse_args!(context, "std.get", args, 4, [
+			0, o: [Val::Obj]!!Val::Obj, vec![ValType::Obj];
+			1, f: [Val::Str]!!Val::Str, vec![ValType::Str];
+			3, inc_hidden: [Val::Bool]!!Val::Bool, vec![ValType::Bool];
+		], {
+			let f = f.flatten();
+			let visible = o.fields_visibility().get(&f).copied();
+			match visible {
+				Some(visible) if visible || inc_hidden => Ok(o.get(f)?.expect("field exists")),
+				// Default is only evaluated when used
+				_ => evaluate(context, &args[2].1),
+			}
+		})?,
 		"primitiveEquals" => parse_args!(context, "std.primitiveEquals", args, 2, [
 			0, a, vec![];
 			1, b, vec![];
@@ -112,12 +166,29 @@ pub fn call_builtin(
 		], {
 			Ok(Val::Bool(equals(&a, &b)?))
 		})?,
+		"equalsSet" => parse_args!(context, "std.equalsSet", args, 2, [
+			0, a: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+			1, b: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+		], {
+			Ok(Val::Bool(equals_set(&a, &b)?))
+		})?,
 		"modulo" => parse_args!(context, "std.modulo", args, 2, [
 			0, a: [Val::Num]!!Val::Num, vec![ValType::Num];
 			1, b: [Val::Num]!!Val::Num, vec![ValType::Num];
 		], {
 			Ok(Val::Num(a % b))
 		})?,
+		"safeDivImpl" => parse_args!(context, "std.safeDiv", args, 3, [
+			0, a: [Val::Num]!!Val::Num, vec![ValType::Num];
+			1, b: [Val::Num]!!Val::Num, vec![ValType::Num];
+			2, default, vec![];
+		], {
+			if b == 0.0 {
+				Ok(default)
+			} else {
+				Val::new_checked_num(a / b)
+			}
+		})?,
 		"floor" => parse_args!(context, "std.floor", args, 1, [
 			0, x: [Val::Num]!!Val::Num, vec![ValType::Num];
 		], {
@@ -151,33 +222,63 @@ pub fn call_builtin(
 		"extVar" => parse_args!(context, "std.extVar", args, 1, [
 			0, x: [Val::Str]!!Val::Str, vec![ValType::Str];
 		], {
-			Ok(with_state(|s| s.settings().ext_vars.get(&x).cloned()).ok_or_else(
-				|| UndefinedExternalVariable(x),
+			Ok(with_state(|s| s.settings().ext_vars.get(&*x).cloned()).ok_or_else(
+				|| UndefinedExternalVariable(x.flatten()),
 			)?)
 		})?,
 		"native" => parse_args!(context, "std.native", args, 1, [
 			0, x: [Val::Str]!!Val::Str, vec![ValType::Str];
 		], {
-			Ok(with_state(|s| s.settings().ext_natives.get(&x).cloned()).map(|v| Val::Func(Rc::new(FuncVal::NativeExt(x.clone(), v)))).ok_or_else(
-				|| UndefinedExternalFunction(x),
+			if matches!(with_state(|s| s.sandbox()), Some(sandbox) if sandbox.disable_natives) {
+				throw!(RuntimeError("native functions disabled in sandbox".into()));
+			}
+			Ok(with_state(|s| s.settings().ext_natives.get(&*x).cloned()).map(|v| Val::Func(Rc::new(FuncVal::NativeExt(x.flatten(), v)))).ok_or_else(
+				|| UndefinedExternalFunction(x.flatten()),
 			)?)
 		})?,
 		"filter" => parse_args!(context, "std.filter", args, 2, [
 			0, func: [Val::Func]!!Val::Func, vec![ValType::Func];
 			1, arr: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
 		], {
-			Ok(Val::Arr(Rc::new(
-				arr.iter()
-					.cloned()
-					.filter(|e| {
-						func
-							.evaluate_values(context.clone(), &[e.clone()])
-							.unwrap()
-							.try_cast_bool("filter predicate")
-							.unwrap()
-					})
-					.collect(),
-			)))
+			let mut out = ArrValueBuilder::new();
+			for item in arr.iter_lazy() {
+				if func
+					.evaluate_values(context.clone(), &[item.evaluate()?])?
+					.try_cast_bool("filter predicate")?
+				{
+					out.push(item);
+				}
+			}
+			Ok(Val::Arr(out.build()))
+		})?,
+		"findFirst" => parse_args!(context, "std.findFirst", args, 2, [
+			0, func: [Val::Func]!!Val::Func, vec![ValType::Func];
+			1, arr: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+		], {
+			let index = arr.position(|item| {
+				func.evaluate_values(Context::new(), std::slice::from_ref(item))?
+					.try_cast_bool("position predicate")
+			})?;
+			Ok(index.map_or(Val::Null, |i| Val::Num(i as f64)))
+		})?,
+		"find" => parse_args!(context, "std.find", args, 2, [
+			0, value, vec![];
+			1, arr: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+		], {
+			let mut out = Vec::new();
+			let mut start = 0;
+			while let Some(i) = arr.position_from(start, |item| equals(item, &value))? {
+				out.push(Val::Num(i as f64));
+				start = i + 1;
+			}
+			Ok(Val::Arr(out.into()))
+		})?,
+		#[allow(non_snake_case)]
+		"groupBy" => parse_args!(context, "std.groupBy", args, 2, [
+			0, arr: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+			1, keyF: [Val::Func]!!Val::Func, vec![ValType::Func];
+		], {
+			Ok(Val::Obj(arr.group_by(&keyF)?))
 		})?,
 		// faster
 		"foldl" => parse_args!(context, "std.foldl", args, 3, [
@@ -186,8 +287,11 @@ pub fn call_builtin(
 			2, init, vec![];
 		], {
 			let mut acc = init;
-			for i in arr.iter().cloned() {
-				acc = func.evaluate_values(context.clone(), &[acc, i])?;
+			for (i, item) in arr.iter().enumerate() {
+				let item = item?;
+				acc = push(loc, || format!("foldl element {}", i), || {
+					func.evaluate_values(context.clone(), &[acc, item])
+				})?;
 			}
 			Ok(acc)
 		})?,
@@ -198,11 +302,39 @@ pub fn call_builtin(
 			2, init, vec![];
 		], {
 			let mut acc = init;
-			for i in arr.iter().rev().cloned() {
-				acc = func.evaluate_values(context.clone(), &[acc, i])?;
+			let len = arr.len();
+			for (i, item) in arr.iter().rev().enumerate() {
+				let item = item?;
+				// Unlike foldl, element is passed before the accumulator
+				acc = push(loc, || format!("foldr element {}", len - 1 - i), || {
+					func.evaluate_values(context.clone(), &[item, acc])
+				})?;
 			}
 			Ok(acc)
 		})?,
+		"sum" => parse_args!(context, "std.sum", args, 1, [
+			0, arr: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+		], {
+			let mut sum = 0.0;
+			for item in arr.iter() {
+				sum += item?.try_cast_num("std.sum element")?;
+			}
+			Ok(Val::new_checked_num(sum)?)
+		})?,
+		// Kahan summation, slower, but accumulates less error
+		"sumStable" => parse_args!(context, "std.sumStable", args, 1, [
+			0, arr: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+		], {
+			let mut sum = 0.0;
+			let mut compensation = 0.0;
+			for item in arr.iter() {
+				let y = item?.try_cast_num("std.sumStable element")? - compensation;
+				let t = sum + y;
+				compensation = (t - sum) - y;
+				sum = t;
+			}
+			Ok(Val::new_checked_num(sum)?)
+		})?,
 		// faster
 		#[allow(non_snake_case)]
 		"sortImpl" => parse_args!(context, "std.sort", args, 2, [
@@ -212,7 +344,26 @@ pub fn call_builtin(
 			if arr.len() <= 1 {
 				return Ok(Val::Arr(arr))
 			}
-			Ok(Val::Arr(sort::sort(context, arr, &keyF)?))
+			Ok(Val::Arr(ArrValue::Eager(sort::sort(context, arr.evaluated()?, &keyF)?)))
+		})?,
+		"naturalSort" => parse_args!(context, "std.naturalSort", args, 1, [
+			0, arr: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+		], {
+			let mut strings = arr.iter()
+				.map(|v| v?.try_cast_str("naturalSort"))
+				.collect::<Result<Vec<_>>>()?;
+			strings.sort_by(|a, b| sort::natural_cmp(a, b));
+			Ok(Val::Arr(strings.into_iter().map(|s| Val::Str(s.into())).collect::<Vec<_>>().into()))
+		})?,
+		"__compare" => parse_args!(context, "std.__compare", args, 2, [
+			0, a, vec![];
+			1, b, vec![];
+		], {
+			Ok(Val::Num(match compare(&a, &b)? {
+				Ordering::Less => -1.0,
+				Ordering::Equal => 0.0,
+				Ordering::Greater => 1.0,
+			}))
 		})?,
 		// faster
 		"format" => parse_args!(context, "std.format", args, 2, [
@@ -221,7 +372,7 @@ pub fn call_builtin(
 		], {
 			push(&Some(ExprLocation(Rc::from(PathBuf::from("std.jsonnet")), 0, 0)), ||format!("std.format of {}", str), ||{
 				Ok(match vals {
-					Val::Arr(vals) => Val::Str(format_arr(&str, &vals)?.into()),
+					Val::Arr(vals) => Val::Str(format_arr(&str, &vals.evaluated()?)?.into()),
 					Val::Obj(obj) => Val::Str(format_obj(&str, &obj)?.into()),
 					o => Val::Str(format_arr(&str, &[o])?.into()),
 				})
@@ -232,11 +383,14 @@ pub fn call_builtin(
 			0, from: [Val::Num]!!Val::Num, vec![ValType::Num];
 			1, to: [Val::Num]!!Val::Num, vec![ValType::Num];
 		], {
-			let mut out = Vec::with_capacity((1+to as usize-from as usize).max(0));
-			for i in from as usize..=to as usize {
-				out.push(Val::Num(i as f64));
+			// Larger integers aren't exact in f64, and `as i64` would silently saturate them
+			const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+			for bound in &[from, to] {
+				if bound.is_nan() || bound.abs() > MAX_SAFE_INTEGER {
+					throw!(RuntimeError(format!("std.range bound is out of range: {}", bound).into()));
+				}
 			}
-			Ok(Val::Arr(Rc::new(out)))
+			Ok(Val::Arr(ArrValue::new_range(from as i64, to as i64)))
 		})?,
 		"char" => parse_args!(context, "std.char", args, 1, [
 			0, n: [Val::Num]!!Val::Num, vec![ValType::Num];
@@ -250,7 +404,7 @@ pub fn call_builtin(
 		"encodeUTF8" => parse_args!(context, "std.encodeUtf8", args, 1, [
 			0, str: [Val::Str]!!Val::Str, vec![ValType::Str];
 		], {
-			Ok(Val::Arr(Rc::new(str.bytes().map(|b| Val::Num(b as f64)).collect())))
+			Ok(Val::Arr(str.bytes().map(|b| Val::Num(b as f64)).collect::<Vec<_>>().into()))
 		})?,
 		"md5" => parse_args!(context, "std.md5", args, 1, [
 			0, str: [Val::Str]!!Val::Str, vec![ValType::Str];
@@ -267,7 +421,7 @@ pub fn call_builtin(
 				},
 				Val::Arr(a) => {
 					base64::encode(a.iter().map(|v| {
-						Ok(v.clone().try_cast_num("base64 array")? as u8)
+						Ok(v?.try_cast_num("base64 array")? as u8)
 					}).collect::<Result<Vec<_>>>()?).into()
 				},
 				_ => unreachable!()
@@ -280,31 +434,29 @@ pub fn call_builtin(
 		], {
 			Ok(match sep {
 				Val::Arr(joiner_items) => {
-					let mut out = Vec::new();
+					let mut out = ArrValueBuilder::new();
 
 					let mut first = true;
-					for item in arr.iter().cloned() {
-						if let Val::Arr(items) = item.unwrap_if_lazy()? {
+					for item in arr.iter() {
+						if let Val::Arr(items) = item? {
 							if !first {
-								out.reserve(joiner_items.len());
-								out.extend(joiner_items.iter().cloned());
+								out.extend(&joiner_items);
 							}
 							first = false;
-							out.reserve(items.len());
-							out.extend(items.iter().cloned());
+							out.extend(&items);
 						} else {
 							throw!(RuntimeError("in std.join all items should be arrays".into()));
 						}
 					}
 
-					Val::Arr(Rc::new(out))
+					Val::Arr(out.build())
 				},
 				Val::Str(sep) => {
 					let mut out = String::new();
 
 					let mut first = true;
-					for item in arr.iter().cloned() {
-						if let Val::Str(item) = item.unwrap_if_lazy()? {
+					for item in arr.iter() {
+						if let Val::Str(item) = item? {
 							if !first {
 								out += &sep;
 							}
@@ -334,15 +486,156 @@ pub fn call_builtin(
 			Ok(Val::Str(manifest_json_ex(&value, &ManifestJsonOptions {
 				padding: &indent,
 				mtype: ManifestType::Std,
+				..Default::default()
+			})?.into()))
+		})?,
+		#[allow(non_snake_case)]
+		"manifestJsonExOrdered" => parse_args!(context, "std.manifestJsonExOrdered", args, 3, [
+			0, value, vec![];
+			1, indent: [Val::Str]!!Val::Str, vec![ValType::Str];
+			2, keyOrder: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+		], {
+			let key_order = keyOrder.iter()
+				.map(|key| key?.try_cast_str("manifestJsonExOrdered key"))
+				.collect::<Result<Vec<_>>>()?;
+			Ok(Val::Str(manifest_json_ex(&value, &ManifestJsonOptions {
+				padding: &indent,
+				mtype: ManifestType::Std,
+				key_order: &key_order,
+				..Default::default()
+			})?.into()))
+		})?,
+		// Faster
+		"manifestYamlDocImpl" => parse_args!(context, "std.manifestYamlDocImpl", args, 2, [
+			0, value, vec![];
+			1, indent_array_in_object: [Val::Bool]!!Val::Bool, vec![ValType::Bool];
+		], {
+			Ok(Val::Str(manifest_yaml_ex(&value, &ManifestYamlOptions {
+				padding: "  ",
+				indent_array_in_object,
+				quote_strings: QuoteStrings::Always,
+				..Default::default()
 			})?.into()))
 		})?,
 		// Faster
+		"manifestIni" => parse_args!(context, "std.manifestIni", args, 1, [
+			0, ini, vec![ValType::Obj];
+		], {
+			Ok(Val::Str(manifest_ini_ex(&ini, &ManifestIniOptions {
+				array_mode: IniArrayMode::Repeat,
+			})?.into()))
+		})?,
+		"manifestTomlEx" => parse_args!(context, "std.manifestTomlEx", args, 2, [
+			0, value, vec![ValType::Obj];
+			1, indent: [Val::Str]!!Val::Str, vec![ValType::Str];
+		], {
+			Ok(Val::Str(manifest_toml_ex(&value, &ManifestTomlOptions {
+				padding: &indent,
+			})?.into()))
+		})?,
+		// Also used by string concatenation
+		"toString" => parse_args!(context, "std.toString", args, 1, [
+			0, a, vec![];
+		], {
+			Ok(Val::Str(a.to_string()?.into()))
+		})?,
+		// Faster
+		"flattenArrays" => parse_args!(context, "std.flattenArrays", args, 1, [
+			0, arrs: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
+		], {
+			Ok(Val::Arr(arrs.flatten()?))
+		})?,
+		// Faster, also used for `arr[start:end:step]` syntax
+		"slice" => parse_args!(context, "std.slice", args, 4, [
+			0, indexable: [Val::Str | Val::Arr], vec![ValType::Str, ValType::Arr];
+			1, index, vec![];
+			2, end, vec![];
+			3, step, vec![];
+		], {
+			let len = match &indexable {
+				Val::Str(s) => s.chars().count(),
+				Val::Arr(a) => a.len(),
+				_ => unreachable!(),
+			};
+			let start = slice_bound(index, len, 0)?;
+			let end = slice_bound(end, len, len)?;
+			let step = match step {
+				Val::Num(n) if n.fract() == 0.0 && n < 1.0 => throw!(InvalidSliceStep(n)),
+				step => slice_bound(step, usize::MAX, 1)?,
+			};
+			Ok(match indexable {
+				Val::Str(s) => Val::Str(
+					s.chars()
+						.skip(start)
+						.take(end.saturating_sub(start))
+						.step_by(step)
+						.collect::<String>()
+						.into(),
+				),
+				Val::Arr(a) => Val::Arr(a.slice(start, end, step)?),
+				_ => unreachable!(),
+			})
+		})?,
+		// Faster
 		"reverse" => parse_args!(context, "std.reverse", args, 1, [
 			0, arr: [Val::Arr]!!Val::Arr, vec![ValType::Arr];
 		], {
-			let mut marr = arr;
-			Rc::make_mut(&mut marr).reverse();
-			Ok(Val::Arr(marr))
+			Ok(Val::Arr(arr.reversed()))
+		})?,
+		#[allow(non_snake_case)]
+		"deepMergeImpl" => parse_args!(context, "std.deepMerge", args, 3, [
+			0, a, vec![];
+			1, b, vec![];
+			2, concatArrays: [Val::Bool]!!Val::Bool, vec![ValType::Bool];
+		], {
+			deep_merge(&a, &b, concatArrays)
+		})?,
+		// target, patch
+		"mergePatch" => parse_args!(context, "std.mergePatch", args, 2, [
+			0, target, vec![];
+			1, patch, vec![];
+		], {
+			merge_patch(&target, &patch)
+		})?,
+		#[allow(non_snake_case)]
+		"parseJsonImpl" => parse_args!(context, "std.parseJson", args, 4, [
+			0, str: [Val::Str]!!Val::Str, vec![ValType::Str];
+			1, allowDuplicateKeys: [Val::Bool]!!Val::Bool, vec![ValType::Bool];
+			2, allowTrailingCommas: [Val::Bool]!!Val::Bool, vec![ValType::Bool];
+			3, allowComments: [Val::Bool]!!Val::Bool, vec![ValType::Bool];
+		], {
+			parse_json(&str, &ParseJsonOptions {
+				allow_duplicate_keys: allowDuplicateKeys,
+				allow_trailing_commas: allowTrailingCommas,
+				allow_comments: allowComments,
+			})
+		})?,
+		#[cfg(feature = "regex-intrinsics")]
+		"splitByRegex" => parse_args!(context, "std.splitByRegex", args, 2, [
+			0, str: [Val::Str]!!Val::Str, vec![ValType::Str];
+			1, pattern: [Val::Str]!!Val::Str, vec![ValType::Str];
+		], {
+			regexp::split_by_regex(&str, &pattern)
+		})?,
+		#[cfg(feature = "regex-intrinsics")]
+		"matchRegex" => parse_args!(context, "std.matchRegex", args, 2, [
+			0, str: [Val::Str]!!Val::Str, vec![ValType::Str];
+			1, pattern: [Val::Str]!!Val::Str, vec![ValType::Str];
+		], {
+			regexp::match_regex(&str, &pattern)
+		})?,
+		#[cfg(feature = "regex-intrinsics")]
+		"replaceRegex" => parse_args!(context, "std.replaceRegex", args, 3, [
+			0, str: [Val::Str]!!Val::Str, vec![ValType::Str];
+			1, pattern: [Val::Str]!!Val::Str, vec![ValType::Str];
+			2, replacement: [Val::Str]!!Val::Str, vec![ValType::Str];
+		], {
+			regexp::replace_regex(&str, &pattern, &replacement)
+		})?,
+		"parseYaml" => parse_args!(context, "std.parseYaml", args, 1, [
+			0, str: [Val::Str]!!Val::Str, vec![ValType::Str];
+		], {
+			parse_yaml(&str)
 		})?,
 		"id" => parse_args!(context, "std.id", args, 1, [
 			0, v, vec![];
@@ -352,3 +645,31 @@ pub fn call_builtin(
 		name => throw!(IntrinsicNotFound(name.into())),
 	})
 }
+
+/// Negative bounds are counted from the end, out of range bounds are clamped to `0..=len`
+fn slice_bound(bound: Val, len: usize, default: usize) -> Result<usize> {
+	Ok(match bound {
+		Val::Null => default,
+		Val::Num(n) if n.fract() != 0.0 => throw!(FractionalIndex),
+		Val::Num(n) if n < 0.0 => (len as f64 + n).max(0.0) as usize,
+		Val::Num(n) => (n as usize).min(len),
+		v => throw!(TypeMismatch(
+			"slice bound",
+			vec![ValType::Num, ValType::Null],
+			v.value_type()?
+		)),
+	})
+}
+
+/// `{key, value}` pair for every field, values are only evaluated when used
+fn object_keys_values(obj: &ObjValue, fields: Vec<Rc<str>>) -> ArrValue {
+	let mut out = ArrValueBuilder::with_capacity(fields.len());
+	for key in fields {
+		let value = obj.get_lazy(key.clone()).expect("field exists");
+		out.push_val(Val::Obj(ObjValue::from_lazy_values(vec![
+			("key".into(), LazyVal::new_resolved(Val::Str(key.into()))),
+			("value".into(), value),
+		])));
+	}
+	out.build()
+}
@@ -0,0 +1,359 @@
+use crate::{
+	error::{Error::*, Result},
+	throw, ObjValue, Val,
+};
+use indexmap::{map::Entry, IndexMap};
+use std::rc::Rc;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// Strict JSON is parsed by default
+#[derive(Default, Clone, Copy)]
+pub struct ParseJsonOptions {
+	/// Last value of duplicate object key wins, instead of error
+	pub allow_duplicate_keys: bool,
+	/// Allow comma after last array element/object member, as in JSON5
+	pub allow_trailing_commas: bool,
+	/// Allow `//` and `/* */` comments, as in JSON5
+	pub allow_comments: bool,
+}
+
+/// Object keys are kept in source order, so parsing the same input always produces the same value
+pub fn parse_json(s: &str, options: &ParseJsonOptions) -> Result<Val> {
+	let mut parser = JsonParser {
+		src: s,
+		pos: 0,
+		options,
+	};
+	let value = parser.parse_value()?;
+	parser.skip_whitespace()?;
+	if parser.pos != s.len() {
+		return parser.error("trailing characters");
+	}
+	Ok(value)
+}
+
+struct JsonParser<'s> {
+	src: &'s str,
+	pos: usize,
+	options: &'s ParseJsonOptions,
+}
+impl JsonParser<'_> {
+	fn error<T>(&self, message: &str) -> Result<T> {
+		throw!(RuntimeError(
+			format!("failed to parse json: {} at offset {}", message, self.pos).into()
+		))
+	}
+	fn peek(&self) -> Option<u8> {
+		self.src.as_bytes().get(self.pos).copied()
+	}
+	fn eat(&mut self, literal: &str) -> bool {
+		if self.src[self.pos..].starts_with(literal) {
+			self.pos += literal.len();
+			true
+		} else {
+			false
+		}
+	}
+
+	fn skip_whitespace(&mut self) -> Result<()> {
+		loop {
+			match self.peek() {
+				Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => self.pos += 1,
+				Some(b'/') if self.options.allow_comments => {
+					let rest = &self.src[self.pos..];
+					if rest.starts_with("//") {
+						self.pos += rest.find('\n').unwrap_or(rest.len());
+					} else if let Some(comment) = rest.strip_prefix("/*") {
+						match comment.find("*/") {
+							Some(len) => self.pos += 2 + len + 2,
+							None => return self.error("unterminated comment"),
+						}
+					} else {
+						return self.error("unexpected character");
+					}
+				}
+				_ => return Ok(()),
+			}
+		}
+	}
+
+	fn parse_value(&mut self) -> Result<Val> {
+		self.skip_whitespace()?;
+		match self.peek() {
+			Some(b'{') => self.parse_object(),
+			Some(b'[') => self.parse_array(),
+			Some(b'"') => Ok(Val::Str(self.parse_string()?.into())),
+			Some(b'-') | Some(b'0'..=b'9') => self.parse_number(),
+			Some(_) if self.eat("true") => Ok(Val::Bool(true)),
+			Some(_) if self.eat("false") => Ok(Val::Bool(false)),
+			Some(_) if self.eat("null") => Ok(Val::Null),
+			Some(_) => self.error("unexpected character"),
+			None => self.error("unexpected end of input"),
+		}
+	}
+
+	/// Parses comma-separated items until `close`, opening bracket should be already consumed
+	fn parse_items(
+		&mut self,
+		close: u8,
+		mut item: impl FnMut(&mut Self) -> Result<()>,
+	) -> Result<()> {
+		self.skip_whitespace()?;
+		if self.peek() == Some(close) {
+			self.pos += 1;
+			return Ok(());
+		}
+		loop {
+			item(self)?;
+			self.skip_whitespace()?;
+			match self.peek() {
+				Some(b',') => {
+					self.pos += 1;
+					if self.options.allow_trailing_commas {
+						self.skip_whitespace()?;
+						if self.peek() == Some(close) {
+							self.pos += 1;
+							return Ok(());
+						}
+					}
+				}
+				Some(c) if c == close => {
+					self.pos += 1;
+					return Ok(());
+				}
+				_ => return self.error(&format!("expected ',' or '{}'", close as char)),
+			}
+		}
+	}
+
+	fn parse_array(&mut self) -> Result<Val> {
+		self.pos += 1;
+		let mut out = Vec::new();
+		self.parse_items(b']', |p| {
+			out.push(p.parse_value()?);
+			Ok(())
+		})?;
+		Ok(Val::Arr(out.into()))
+	}
+
+	fn parse_object(&mut self) -> Result<Val> {
+		self.pos += 1;
+		// Keys are kept in source order, so field order doesn't depend on hasher state
+		let mut values = IndexMap::new();
+		self.parse_items(b'}', |p| {
+			p.skip_whitespace()?;
+			if p.peek() != Some(b'"') {
+				return p.error("expected string key");
+			}
+			let key_pos = p.pos;
+			let key: Rc<str> = p.parse_string()?.into();
+			p.skip_whitespace()?;
+			if !p.eat(":") {
+				return p.error("expected ':'");
+			}
+			let value = p.parse_value()?;
+			if values.insert(key.clone(), value).is_some() && !p.options.allow_duplicate_keys {
+				p.pos = key_pos;
+				return p.error(&format!("duplicate key {:?}", key));
+			}
+			Ok(())
+		})?;
+		Ok(Val::Obj(ObjValue::from_values(values)))
+	}
+
+	fn parse_string(&mut self) -> Result<String> {
+		self.pos += 1;
+		let mut out = String::new();
+		loop {
+			let rest = &self.src[self.pos..];
+			let len = match rest.find(|c| c == '"' || c == '\\' || c < ' ') {
+				Some(len) => len,
+				None => return self.error("unterminated string"),
+			};
+			out.push_str(&rest[..len]);
+			self.pos += len;
+			match self.peek() {
+				Some(b'"') => {
+					self.pos += 1;
+					return Ok(out);
+				}
+				Some(b'\\') => {
+					self.pos += 1;
+					let escape = self.peek();
+					self.pos += 1;
+					match escape {
+						Some(b'"') => out.push('"'),
+						Some(b'\\') => out.push('\\'),
+						Some(b'/') => out.push('/'),
+						Some(b'b') => out.push('\u{8}'),
+						Some(b'f') => out.push('\u{c}'),
+						Some(b'n') => out.push('\n'),
+						Some(b'r') => out.push('\r'),
+						Some(b't') => out.push('\t'),
+						Some(b'u') => out.push(self.parse_unicode_escape()?),
+						_ => return self.error("invalid escape"),
+					}
+				}
+				_ => return self.error("control character in string"),
+			}
+		}
+	}
+	fn parse_hex4(&mut self) -> Result<u32> {
+		let hex = match self.src.get(self.pos..self.pos + 4) {
+			Some(hex) if hex.bytes().all(|c| c.is_ascii_hexdigit()) => hex,
+			_ => return self.error("invalid unicode escape"),
+		};
+		self.pos += 4;
+		Ok(u32::from_str_radix(hex, 16).expect("checked hex"))
+	}
+	/// `\u` should be already consumed, surrogate pairs are combined
+	fn parse_unicode_escape(&mut self) -> Result<char> {
+		let mut code = self.parse_hex4()?;
+		if (0xd800..0xdc00).contains(&code) {
+			if !self.eat("\\u") {
+				return self.error("unpaired surrogate");
+			}
+			let low = self.parse_hex4()?;
+			if !(0xdc00..0xe000).contains(&low) {
+				return self.error("unpaired surrogate");
+			}
+			code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+		}
+		std::char::from_u32(code).map_or_else(|| self.error("unpaired surrogate"), Ok)
+	}
+
+	fn skip_digits(&mut self) -> usize {
+		let start = self.pos;
+		while matches!(self.peek(), Some(b'0'..=b'9')) {
+			self.pos += 1;
+		}
+		self.pos - start
+	}
+	fn parse_number(&mut self) -> Result<Val> {
+		let start = self.pos;
+		self.eat("-");
+		if !self.eat("0") && self.skip_digits() == 0 {
+			return self.error("invalid number");
+		}
+		if self.eat(".") && self.skip_digits() == 0 {
+			return self.error("invalid number");
+		}
+		if self.eat("e") || self.eat("E") {
+			if !self.eat("+") {
+				self.eat("-");
+			}
+			if self.skip_digits() == 0 {
+				return self.error("invalid number");
+			}
+		}
+		Val::new_checked_num(
+			self.src[start..self.pos]
+				.parse()
+				.expect("valid json number"),
+		)
+	}
+}
+
+/// Key, which values are merged into containing mapping (`<<: *anchor`)
+const YAML_MERGE_KEY: &str = "<<";
+
+/// Parses yaml stream, returning single value for single document stream,
+/// and array of documents otherwise
+///
+/// Mapping keys are kept in source order, as in [`parse_json`]
+pub fn parse_yaml(s: &str) -> Result<Val> {
+	let docs = match YamlLoader::load_from_str(s) {
+		Ok(v) => v,
+		Err(e) => throw!(RuntimeError(format!("failed to parse yaml: {}", e).into())),
+	};
+	if docs.len() == 1 {
+		return yaml_doc_to_val(&docs[0]);
+	}
+	let mut out = Vec::with_capacity(docs.len());
+	for doc in docs.iter() {
+		out.push(yaml_doc_to_val(doc)?);
+	}
+	Ok(Val::Arr(out.into()))
+}
+
+fn yaml_doc_to_val(doc: &Yaml) -> Result<Val> {
+	// Empty documents are reported as BadValue
+	if matches!(doc, Yaml::BadValue) {
+		return Ok(Val::Null);
+	}
+	yaml_to_val(doc)
+}
+
+fn yaml_key(key: &Yaml) -> Result<Rc<str>> {
+	Ok(match key {
+		Yaml::String(s) => s.as_str().into(),
+		Yaml::Integer(i) => i.to_string().into(),
+		Yaml::Real(r) => r.as_str().into(),
+		Yaml::Boolean(b) => b.to_string().into(),
+		Yaml::Null => "null".into(),
+		_ => throw!(RuntimeError(
+			"yaml mapping keys should be scalar values".into()
+		)),
+	})
+}
+
+fn yaml_to_val(yaml: &Yaml) -> Result<Val> {
+	Ok(match yaml {
+		Yaml::Null => Val::Null,
+		Yaml::Boolean(b) => Val::Bool(*b),
+		Yaml::Integer(i) => Val::Num(*i as f64),
+		Yaml::Real(_) => match yaml.as_f64() {
+			Some(n) => Val::new_checked_num(n)?,
+			None => throw!(RuntimeError("bad yaml number".into())),
+		},
+		Yaml::String(s) => Val::Str(s.as_str().into()),
+		Yaml::Array(a) => {
+			let mut out = Vec::with_capacity(a.len());
+			for item in a {
+				out.push(yaml_to_val(item)?);
+			}
+			Val::Arr(out.into())
+		}
+		Yaml::Hash(h) => {
+			let mut values = IndexMap::with_capacity(h.len());
+			let mut merged = Vec::new();
+			for (k, v) in h {
+				if matches!(k, Yaml::String(s) if s == YAML_MERGE_KEY) {
+					match v {
+						Yaml::Hash(_) => merged.push(v),
+						Yaml::Array(sources) => merged.extend(sources.iter()),
+						_ => throw!(RuntimeError(
+							"yaml merge key value should be a mapping or a sequence of mappings"
+								.into()
+						)),
+					}
+					continue;
+				}
+				values.insert(yaml_key(k)?, yaml_to_val(v)?);
+			}
+			// Explicit keys take precedence over merged ones, and earlier merge sources
+			// take precedence over later ones
+			for source in merged {
+				let source = match source {
+					Yaml::Hash(_) => yaml_to_val(source)?,
+					_ => throw!(RuntimeError(
+						"yaml merge key value should be a mapping or a sequence of mappings".into()
+					)),
+				};
+				let source = match source {
+					Val::Obj(o) => o,
+					_ => unreachable!(),
+				};
+				for field in source.visible_fields() {
+					if let Entry::Vacant(entry) = values.entry(field) {
+						let value = source.get(entry.key().clone())?.expect("field exists");
+						entry.insert(value);
+					}
+				}
+			}
+
+			Val::Obj(ObjValue::from_values(values))
+		}
+		Yaml::Alias(_) | Yaml::BadValue => throw!(RuntimeError("bad yaml value".into())),
+	})
+}
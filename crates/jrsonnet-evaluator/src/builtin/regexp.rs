@@ -0,0 +1,43 @@
+use crate::{
+	error::{Error::*, Result},
+	Val,
+};
+use regex::Regex;
+
+fn compile(pattern: &str) -> Result<Regex> {
+	Regex::new(pattern).map_err(|e| InvalidRegex(e.to_string().into()).into())
+}
+
+pub fn split_by_regex(s: &str, pattern: &str) -> Result<Val> {
+	Ok(Val::Arr(
+		compile(pattern)?
+			.split(s)
+			.map(|part| Val::Str(part.into()))
+			.collect::<Vec<_>>()
+			.into(),
+	))
+}
+
+/// Returns groups of the first match, with the whole match at index 0 and null in place of
+/// groups which didn't participate in it, or null if string doesn't match
+pub fn match_regex(s: &str, pattern: &str) -> Result<Val> {
+	Ok(compile(pattern)?.captures(s).map_or(Val::Null, |captures| {
+		Val::Arr(
+			captures
+				.iter()
+				.map(|group| group.map_or(Val::Null, |m| Val::Str(m.as_str().into())))
+				.collect::<Vec<_>>()
+				.into(),
+		)
+	}))
+}
+
+/// Replaces all matches, `$1` and `${name}` in replacement are expanded to captured groups
+pub fn replace_regex(s: &str, pattern: &str, replacement: &str) -> Result<Val> {
+	Ok(Val::Str(
+		compile(pattern)?
+			.replace_all(s, replacement)
+			.as_ref()
+			.into(),
+	))
+}
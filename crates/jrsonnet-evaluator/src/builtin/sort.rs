@@ -1,12 +1,13 @@
 use crate::{
+	compare,
 	error::{Error, LocError, Result},
 	throw, Context, FuncVal, Val,
 };
-use std::rc::Rc;
+use std::{cmp::Ordering, rc::Rc};
 
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum SortError {
-	#[error("sort key should be string or number")]
+	#[error("sort key should be string, number or array")]
 	SortKeyShouldBeStringOrNumber,
 	#[error("sort elements should have equal types")]
 	SortElementsShouldHaveEqualType,
@@ -22,6 +23,8 @@ impl From<SortError> for LocError {
 enum SortKeyType {
 	Number,
 	String,
+	/// Compared element-wise with [`compare`]
+	Array,
 	Unknown,
 }
 
@@ -54,12 +57,78 @@ fn get_sort_type<T>(
 			(Val::Str(_), _) => throw!(SortError::SortElementsShouldHaveEqualType),
 			(Val::Num(_), SortKeyType::Number) => {}
 			(Val::Num(_), _) => throw!(SortError::SortElementsShouldHaveEqualType),
+			(Val::Arr(_), SortKeyType::Unknown) => sort_type = SortKeyType::Array,
+			(Val::Arr(_), SortKeyType::Array) => {}
+			(Val::Arr(_), _) => throw!(SortError::SortElementsShouldHaveEqualType),
 			_ => throw!(SortError::SortKeyShouldBeStringOrNumber),
 		}
 	}
 	Ok(sort_type)
 }
 
+/// Stable sort with fallible comparison, first comparison error is returned
+fn try_sort_by<T>(values: &mut [T], key: impl Fn(&T) -> &Val) -> Result<()> {
+	let mut error = None;
+	values.sort_by(|a, b| {
+		if error.is_some() {
+			return Ordering::Equal;
+		}
+		compare(key(a), key(b)).unwrap_or_else(|e| {
+			error = Some(e);
+			Ordering::Equal
+		})
+	});
+	error.map_or(Ok(()), Err)
+}
+
+/// Compares strings in natural order, where runs of ASCII digits are compared by their
+/// numeric value, so `item2` goes before `item10`
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+	let mut a_chars = a.chars().peekable();
+	let mut b_chars = b.chars().peekable();
+	// Numbers with different count of leading zeroes are equal, yet strings are not
+	let mut tie_break = Ordering::Equal;
+	loop {
+		match (a_chars.peek(), b_chars.peek()) {
+			(None, None) => return tie_break.then_with(|| a.cmp(b)),
+			(None, Some(_)) => return Ordering::Less,
+			(Some(_), None) => return Ordering::Greater,
+			(Some(ac), Some(bc)) if ac.is_ascii_digit() && bc.is_ascii_digit() => {
+				let a_digits = take_digits(&mut a_chars);
+				let b_digits = take_digits(&mut b_chars);
+				let a_number = a_digits.trim_start_matches('0');
+				let b_number = b_digits.trim_start_matches('0');
+				let ordering = a_number
+					.len()
+					.cmp(&b_number.len())
+					.then_with(|| a_number.cmp(b_number));
+				if ordering != Ordering::Equal {
+					return ordering;
+				}
+				if tie_break == Ordering::Equal {
+					tie_break = a_digits.len().cmp(&b_digits.len());
+				}
+			}
+			(Some(ac), Some(bc)) => {
+				let ordering = ac.cmp(bc);
+				if ordering != Ordering::Equal {
+					return ordering;
+				}
+				a_chars.next();
+				b_chars.next();
+			}
+		}
+	}
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+	let mut out = String::new();
+	while let Some(c) = chars.next_if(char::is_ascii_digit) {
+		out.push(c);
+	}
+	out
+}
+
 pub fn sort(ctx: Context, mut values: Rc<Vec<Val>>, key_getter: &FuncVal) -> Result<Rc<Vec<Val>>> {
 	if values.len() <= 1 {
 		return Ok(values);
@@ -76,6 +145,7 @@ pub fn sort(ctx: Context, mut values: Rc<Vec<Val>>, key_getter: &FuncVal) -> Res
 				Val::Str(s) => s.clone(),
 				_ => unreachable!(),
 			}),
+			SortKeyType::Array => try_sort_by(mvalues, |v| v)?,
 			SortKeyType::Unknown => unreachable!(),
 		};
 		Ok(values)
@@ -97,6 +167,7 @@ pub fn sort(ctx: Context, mut values: Rc<Vec<Val>>, key_getter: &FuncVal) -> Res
 				Val::Str(s) => s.clone(),
 				_ => unreachable!(),
 			}),
+			SortKeyType::Array => try_sort_by(&mut vk, |v| &v.1)?,
 			SortKeyType::Unknown => unreachable!(),
 		};
 		Ok(Rc::new(vk.into_iter().map(|v| v.0).collect()))
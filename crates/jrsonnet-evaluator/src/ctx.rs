@@ -65,6 +65,20 @@ impl Context {
 			.cloned()
 			.ok_or_else(|| VariableIsNotDefined(name))?)
 	}
+	/// Names of all variables, visible in this context
+	pub fn var_names(&self) -> Vec<Rc<str>> {
+		let mut names: Vec<_> = self.0.bindings.keys().into_iter().cloned().collect();
+		names.sort();
+		names
+	}
+	/// Names of variables from `outer`, which are bound to different values in this context
+	pub fn shadowed_vars(&self, outer: &Self) -> Vec<Rc<str>> {
+		outer
+			.var_names()
+			.into_iter()
+			.filter(|name| self.0.bindings.get(name) != outer.0.bindings.get(name))
+			.collect()
+	}
 	pub fn into_future(self, ctx: FutureContext) -> Self {
 		{
 			ctx.0.borrow_mut().replace(self);
@@ -93,8 +93,12 @@ pub enum Error {
 	RuntimeError(Rc<str>),
 	#[error("stack overflow, try to reduce recursion, or set --max-stack to bigger value")]
 	StackOverflow,
+	#[error("evaluation deadline exceeded")]
+	DeadlineExceeded,
 	#[error("tried to index by fractional value")]
 	FractionalIndex,
+	#[error("slice step must be greater than 0, got {0}")]
+	InvalidSliceStep(f64),
 	#[error("attempted to divide by zero")]
 	DivisionByZero,
 
@@ -114,6 +118,8 @@ pub enum Error {
 	ImportCallbackError(String),
 	#[error("invalid unicode codepoint: {0}")]
 	InvalidUnicodeCodepointGot(u32),
+	#[error("invalid regex: {0}")]
+	InvalidRegex(Rc<str>),
 
 	#[error("format error: {0}")]
 	Format(#[from] FormatError),
@@ -150,6 +156,11 @@ impl LocError {
 	pub fn trace_mut(&mut self) -> &mut StackTrace {
 		&mut (self.0).1
 	}
+	/// Replaces error, preserving stack trace
+	pub fn map_error(self, f: impl FnOnce(Error) -> Error) -> Self {
+		let (error, trace) = *self.0;
+		Self(Box::new((f(error), trace)))
+	}
 }
 
 pub type Result<V> = std::result::Result<V, LocError>;
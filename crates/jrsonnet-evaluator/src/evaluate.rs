@@ -1,9 +1,10 @@
 use crate::{
-	context_creator, error::Error::*, future_wrapper, lazy_val, push, throw, with_state, Context,
-	ContextCreator, FuncDesc, FuncVal, LazyBinding, LazyVal, ObjMember, ObjValue, Result, Val,
-	ValType,
+	compare, context_creator, error::Error::*, future_wrapper, lazy_val, obj_assertion, push,
+	throw, with_state, ArrValue, Context, ContextCreator, FuncDesc, FuncVal, LazyBinding, LazyVal,
+	ObjMember, ObjValue, ParamSlots, Result, StrValue, Val, ValType,
 };
 use closure::closure;
+use indexmap::IndexMap;
 use jrsonnet_parser::{
 	ArgsDesc, AssertStmt, BinaryOpType, BindSpec, CompSpec, Expr, ExprLocation, FieldMember,
 	ForSpecData, IfSpecData, LiteralType, LocExpr, Member, ObjBody, ParamsDesc, UnaryOpType,
@@ -33,13 +34,16 @@ pub fn evaluate_binding(b: &BindSpec, context_creator: ContextCreator) -> (Rc<st
 		(
 			b.name.clone(),
 			LazyBinding::Bindable(Rc::new(move |this, super_obj| {
-				Ok(lazy_val!(closure!(clone context_creator, clone b, ||
+				Ok(LazyVal::new_located(
+					Box::new(closure!(clone context_creator, clone b, ||
 						evaluate_named(
 							context_creator.0(this.clone(), super_obj.clone())?,
 							&b.value,
 							b.name.clone()
 						)
-				)))
+					)),
+					b.value.1.clone(),
+				))
 			})),
 		)
 	}
@@ -51,6 +55,7 @@ pub fn evaluate_method(ctx: Context, name: Rc<str>, params: ParamsDesc, body: Lo
 		ctx,
 		params,
 		body,
+		param_slots: ParamSlots::default(),
 	})))
 }
 
@@ -84,17 +89,17 @@ pub fn evaluate_unary_op(op: UnaryOpType, b: &Val) -> Result<Val> {
 
 pub fn evaluate_add_op(a: &Val, b: &Val) -> Result<Val> {
 	Ok(match (a, b) {
-		(Val::Str(v1), Val::Str(v2)) => Val::Str(((**v1).to_owned() + v2).into()),
+		(Val::Str(v1), Val::Str(v2)) => Val::Str(StrValue::concat(v1.clone(), v2.clone())),
 
 		// Can't use generic json serialization way, because it depends on number to string concatenation (std.jsonnet:890)
-		(Val::Num(n), Val::Str(o)) => Val::Str(format!("{}{}", n, o).into()),
-		(Val::Str(o), Val::Num(n)) => Val::Str(format!("{}{}", o, n).into()),
+		(Val::Num(n), Val::Str(o)) => Val::Str(StrValue::concat(n.to_string().into(), o.clone())),
+		(Val::Str(o), Val::Num(n)) => Val::Str(StrValue::concat(o.clone(), n.to_string().into())),
 
-		(Val::Str(s), o) => Val::Str(format!("{}{}", s, o.clone().to_string()?).into()),
-		(o, Val::Str(s)) => Val::Str(format!("{}{}", o.clone().to_string()?, s).into()),
+		(Val::Str(s), o) => Val::Str(StrValue::concat(s.clone(), o.clone().to_string()?.into())),
+		(o, Val::Str(s)) => Val::Str(StrValue::concat(o.clone().to_string()?.into(), s.clone())),
 
-		(Val::Obj(v1), Val::Obj(v2)) => Val::Obj(v2.with_super(v1.clone())),
-		(Val::Arr(a), Val::Arr(b)) => Val::Arr(Rc::new([&a[..], &b[..]].concat())),
+		(Val::Obj(v1), Val::Obj(v2)) => Val::Obj(v1.merge(v2)),
+		(Val::Arr(a), Val::Arr(b)) => Val::Arr(ArrValue::concat(a.clone(), b.clone())),
 		(Val::Num(v1), Val::Num(v2)) => Val::new_checked_num(v1 + v2)?,
 		_ => throw!(BinaryOperatorDoesNotOperateOnValues(
 			BinaryOpType::Add,
@@ -153,6 +158,12 @@ pub fn evaluate_binary_op_normal(a: &Val, op: BinaryOpType, b: &Val) -> Result<V
 		(Val::Num(v1), BinaryOpType::Lte, Val::Num(v2)) => Val::Bool(v1 <= v2),
 		(Val::Num(v1), BinaryOpType::Gte, Val::Num(v2)) => Val::Bool(v1 >= v2),
 
+		// Arr X Arr
+		(a @ Val::Arr(_), BinaryOpType::Lt, b @ Val::Arr(_)) => Val::Bool(compare(a, b)?.is_lt()),
+		(a @ Val::Arr(_), BinaryOpType::Gt, b @ Val::Arr(_)) => Val::Bool(compare(a, b)?.is_gt()),
+		(a @ Val::Arr(_), BinaryOpType::Lte, b @ Val::Arr(_)) => Val::Bool(compare(a, b)?.is_le()),
+		(a @ Val::Arr(_), BinaryOpType::Gte, b @ Val::Arr(_)) => Val::Bool(compare(a, b)?.is_ge()),
+
 		(Val::Num(v1), BinaryOpType::BitAnd, Val::Num(v2)) => {
 			Val::Num(((*v1 as i32) & (*v2 as i32)) as f64)
 		}
@@ -205,7 +216,7 @@ pub fn evaluate_comp<T>(
 				Val::Arr(list) => {
 					let mut out = Vec::new();
 					for item in list.iter() {
-						let item = item.unwrap_if_lazy()?;
+						let item = item?;
 						out.push(evaluate_comp(
 							context.clone().with_var(var.clone(), item.clone()),
 							value,
@@ -248,7 +259,8 @@ pub fn evaluate_member_list_object(context: Context, members: &[Member]) -> Resu
 		new_bindings.fill(bindings);
 	}
 
-	let mut new_members = HashMap::new();
+	let mut new_members = IndexMap::new();
+	let mut assertions = Vec::new();
 	for member in members.iter() {
 		match member {
 			Member::Field(FieldMember {
@@ -312,10 +324,20 @@ pub fn evaluate_member_list_object(context: Context, members: &[Member]) -> Resu
 				);
 			}
 			Member::BindStmt(_) => {}
-			Member::AssertStmt(_) => {}
+			Member::AssertStmt(stmt) => {
+				let stmt = stmt.clone();
+				let context_creator = context_creator.clone();
+				assertions.push(obj_assertion!(move |this, super_obj| {
+					evaluate_assert(context_creator.0(Some(this), super_obj)?, &stmt)
+				}));
+			}
 		}
 	}
-	Ok(future_this.fill(ObjValue::new(None, Rc::new(new_members))))
+	Ok(future_this.fill(ObjValue::new_with_assertions(
+		None,
+		Rc::new(new_members),
+		Rc::new(assertions),
+	)))
 }
 
 pub fn evaluate_object(context: Context, object: &ObjBody) -> Result<ObjValue> {
@@ -323,7 +345,7 @@ pub fn evaluate_object(context: Context, object: &ObjBody) -> Result<ObjValue> {
 		ObjBody::MemberList(members) => evaluate_member_list_object(context, members)?,
 		ObjBody::ObjComp(obj) => {
 			let future_this = FutureObjValue::new();
-			let mut new_members = HashMap::new();
+			let mut new_members = IndexMap::new();
 			for (k, v) in evaluate_comp(
 				context.clone(),
 				&|ctx| {
@@ -366,7 +388,7 @@ pub fn evaluate_object(context: Context, object: &ObjBody) -> Result<ObjValue> {
 					Val::Null => {}
 					Val::Str(n) => {
 						new_members.insert(
-							n,
+							n.flatten(),
 							ObjMember {
 								add: false,
 								visibility: Visibility::Normal,
@@ -406,6 +428,25 @@ pub fn evaluate_apply(
 	})
 }
 
+pub fn evaluate_assert(context: Context, assertion: &AssertStmt) -> Result<()> {
+	let AssertStmt(value, msg) = assertion;
+	let assertion_result = push(
+		&value.1,
+		|| "assertion condition".to_owned(),
+		|| {
+			evaluate(context.clone(), value)?
+				.try_cast_bool("assertion condition should be of type `boolean`")
+		},
+	)?;
+	if assertion_result {
+		Ok(())
+	} else if let Some(msg) = msg {
+		throw!(AssertionFailed(evaluate(context, msg)?.to_string()?));
+	} else {
+		throw!(AssertionFailed(Val::Null.to_string()?));
+	}
+}
+
 pub fn evaluate_named(context: Context, lexpr: &LocExpr, name: Rc<str>) -> Result<Val> {
 	use Expr::*;
 	let LocExpr(expr, _loc) = lexpr;
@@ -429,7 +470,7 @@ pub fn evaluate(context: Context, expr: &LocExpr) -> Result<Val> {
 		Literal(LiteralType::False) => Val::Bool(false),
 		Literal(LiteralType::Null) => Val::Null,
 		Parened(e) => evaluate(context, e)?,
-		Str(v) => Val::Str(v.clone()),
+		Str(v) => Val::Str(v.clone().into()),
 		Num(v) => Val::new_checked_num(*v)?,
 		BinaryOp(v1, o, v2) => evaluate_binary_op_special(context, v1, *o, v2)?,
 		UnaryOp(o, v) => evaluate_unary_op(*o, &evaluate(context, v)?)?,
@@ -453,6 +494,7 @@ pub fn evaluate(context: Context, expr: &LocExpr) -> Result<Val> {
 				evaluate(context, index)?,
 			) {
 				(Val::Obj(v), Val::Str(s)) => {
+					let s = s.flatten();
 					let sn = s.clone();
 					push(
 						loc,
@@ -478,12 +520,10 @@ pub fn evaluate(context: Context, expr: &LocExpr) -> Result<Val> {
 					if n.fract() > f64::EPSILON {
 						throw!(FractionalIndex)
 					}
-					v.get(n as usize)
+					v.get(n as usize)?
 						.ok_or_else(|| ArrayBoundsError(n as usize, v.len()))?
-						.clone()
-						.unwrap_if_lazy()?
 				}
-				(Val::Arr(_), Val::Str(n)) => throw!(AttemptedIndexAnArrayWithString(n)),
+				(Val::Arr(_), Val::Str(n)) => throw!(AttemptedIndexAnArrayWithString(n.flatten())),
 				(Val::Arr(_), n) => throw!(ValueIndexMustBeTypeGot(
 					ValType::Arr,
 					ValType::Num,
@@ -529,17 +569,20 @@ pub fn evaluate(context: Context, expr: &LocExpr) -> Result<Val> {
 		Arr(items) => {
 			let mut out = Vec::with_capacity(items.len());
 			for item in items {
-				out.push(Val::Lazy(lazy_val!(
-					closure!(clone context, clone item, || {
+				out.push(LazyVal::new_located(
+					Box::new(closure!(clone context, clone item, || {
 						evaluate(context.clone(), &item)
-					})
-				)));
+					})),
+					item.1.clone(),
+				));
 			}
-			Val::Arr(Rc::new(out))
+			Val::Arr(out.into())
 		}
 		ArrComp(expr, comp_specs) => Val::Arr(
 			// First comp_spec should be for_spec, so no "None" possible here
-			Rc::new(evaluate_comp(context, &|ctx| evaluate(ctx, expr), comp_specs)?.unwrap()),
+			evaluate_comp(context, &|ctx| evaluate(ctx, expr), comp_specs)?
+				.unwrap()
+				.into(),
 		),
 		Obj(body) => Val::Obj(evaluate_object(context, body)?),
 		ObjExtend(s, t) => evaluate_add_op(
@@ -551,22 +594,9 @@ pub fn evaluate(context: Context, expr: &LocExpr) -> Result<Val> {
 			evaluate_method(context, "anonymous".into(), params.clone(), body.clone())
 		}
 		Intrinsic(name) => Val::Func(Rc::new(FuncVal::Intrinsic(name.clone()))),
-		AssertExpr(AssertStmt(value, msg), returned) => {
-			let assertion_result = push(
-				&value.1,
-				|| "assertion condition".to_owned(),
-				|| {
-					evaluate(context.clone(), value)?
-						.try_cast_bool("assertion condition should be of type `boolean`")
-				},
-			)?;
-			if assertion_result {
-				evaluate(context, returned)?
-			} else if let Some(msg) = msg {
-				throw!(AssertionFailed(evaluate(context, msg)?.to_string()?));
-			} else {
-				throw!(AssertionFailed(Val::Null.to_string()?));
-			}
+		AssertExpr(assertion, returned) => {
+			evaluate_assert(context.clone(), assertion)?;
+			evaluate(context, returned)?
 		}
 		ErrorStmt(e) => push(
 			loc,
@@ -596,6 +626,9 @@ pub fn evaluate(context: Context, expr: &LocExpr) -> Result<Val> {
 			}
 		}
 		Import(path) => {
+			if with_state(|s| s.sandbox().is_some()) {
+				throw!(RuntimeError("imports disabled in sandbox".into()));
+			}
 			let mut tmp = loc
 				.clone()
 				.expect("imports cannot be used without loc_data")
@@ -609,13 +642,16 @@ pub fn evaluate(context: Context, expr: &LocExpr) -> Result<Val> {
 			)?
 		}
 		ImportStr(path) => {
+			if with_state(|s| s.sandbox().is_some()) {
+				throw!(RuntimeError("imports disabled in sandbox".into()));
+			}
 			let mut tmp = loc
 				.clone()
 				.expect("imports cannot be used without loc_data")
 				.0;
 			let import_location = Rc::make_mut(&mut tmp);
 			import_location.pop();
-			Val::Str(with_state(|s| s.import_file_str(import_location, path))?)
+			Val::Str(with_state(|s| s.import_file_str(import_location, path))?.into())
 		}
 		Literal(LiteralType::Super) => throw!(StandaloneSuper),
 	})
@@ -1,12 +1,37 @@
-use crate::{error::Error::*, evaluate, lazy_val, resolved_lazy_val, throw, Context, Result, Val};
+use crate::{
+	error::Error::*, evaluate, lazy_val, resolved_lazy_val, throw, Context, LazyVal, Result, Val,
+};
 use closure::closure;
 use jrsonnet_parser::{ArgsDesc, ParamsDesc};
 use rustc_hash::FxHashMap;
-use std::{collections::HashMap, hash::BuildHasherDefault, rc::Rc};
+use std::{cell::OnceCell, collections::HashMap, hash::BuildHasherDefault, rc::Rc};
 
 const NO_DEFAULT_CONTEXT: &str =
 	"no default context set for call with defined default parameter value";
 
+/// Positions of function parameters by name, resolved once per function,
+/// so named arguments of repeated calls are placed without scanning parameter list
+#[derive(Debug, Default)]
+pub struct ParamSlots(OnceCell<FxHashMap<Rc<str>, usize>>);
+impl ParamSlots {
+	pub fn resolve(&self, params: &ParamsDesc) -> &FxHashMap<Rc<str>, usize> {
+		self.0.get_or_init(|| {
+			let mut slots =
+				FxHashMap::with_capacity_and_hasher(params.len(), BuildHasherDefault::default());
+			for (id, param) in params.iter().enumerate() {
+				slots.entry(param.0.clone()).or_insert(id);
+			}
+			slots
+		})
+	}
+}
+/// Slots are derived from parameters, which are compared separately
+impl PartialEq for ParamSlots {
+	fn eq(&self, _other: &Self) -> bool {
+		true
+	}
+}
+
 /// Creates correct [context](Context) for function body evaluation returning error on invalid call.
 ///
 /// ## Parameters
@@ -21,14 +46,28 @@ pub fn parse_function_call(
 	params: &ParamsDesc,
 	args: &ArgsDesc,
 	tailstrict: bool,
+) -> Result<Context> {
+	parse_function_call_slots(ctx, body_ctx, params, None, args, tailstrict)
+}
+
+/// Same as [`parse_function_call`], but named arguments are placed using resolved `slots`
+pub fn parse_function_call_slots(
+	ctx: Context,
+	body_ctx: Option<Context>,
+	params: &ParamsDesc,
+	slots: Option<&ParamSlots>,
+	args: &ArgsDesc,
+	tailstrict: bool,
 ) -> Result<Context> {
 	let mut out = HashMap::with_capacity_and_hasher(params.len(), BuildHasherDefault::default());
 	let mut positioned_args = vec![None; params.0.len()];
 	for (id, arg) in args.iter().enumerate() {
 		let idx = if let Some(name) = &arg.0 {
-			params
-				.iter()
-				.position(|p| *p.0 == *name)
+			slots
+				.map_or_else(
+					|| params.iter().position(|p| *p.0 == *name),
+					|slots| slots.resolve(params).get(name as &str).copied(),
+				)
 				.ok_or_else(|| UnknownFunctionParameter(name.clone()))?
 		} else {
 			id
@@ -54,7 +93,10 @@ pub fn parse_function_call(
 		let val = if tailstrict {
 			resolved_lazy_val!(evaluate(ctx, expr)?)
 		} else {
-			lazy_val!(closure!(clone ctx, clone expr, ||evaluate(ctx.clone(), &expr)))
+			LazyVal::new_located(
+				Box::new(closure!(clone ctx, clone expr, ||evaluate(ctx.clone(), &expr))),
+				expr.1.clone(),
+			)
 		};
 		out.insert(p.0.clone(), val);
 	}
@@ -71,7 +113,10 @@ pub fn parse_function_call_map(
 ) -> Result<Context> {
 	let mut out = FxHashMap::with_capacity_and_hasher(params.len(), BuildHasherDefault::default());
 	let mut positioned_args = vec![None; params.0.len()];
-	for (name, val) in args.iter() {
+	// Map iteration order is random, sorting makes reported error the same on every run
+	let mut args = args.iter().collect::<Vec<_>>();
+	args.sort_unstable_by_key(|(name, _)| *name);
+	for (name, val) in args {
 		let idx = params
 			.iter()
 			.position(|p| *p.0 == **name)
@@ -0,0 +1,215 @@
+//! Compact self-describing binary encoding of fully evaluated values, usable for caching
+use crate::{
+	error::{Error::*, Result},
+	throw, ObjValue, Val,
+};
+use std::{convert::TryInto, rc::Rc};
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_NUM: u8 = 3;
+const TAG_STR: u8 = 4;
+const TAG_ARR: u8 = 5;
+const TAG_OBJ: u8 = 6;
+
+/// Deeper nesting is rejected on read, so malformed input can't overflow the stack,
+/// and on write, so every written value can be read back
+const MAX_DEPTH: usize = 512;
+
+fn write_len(out: &mut Vec<u8>, mut len: usize) {
+	// LEB128
+	loop {
+		let byte = (len & 0x7f) as u8;
+		len >>= 7;
+		if len == 0 {
+			out.push(byte);
+			break;
+		}
+		out.push(byte | 0x80);
+	}
+}
+fn write_str(out: &mut Vec<u8>, s: &str) {
+	write_len(out, s.len());
+	out.extend_from_slice(s.as_bytes());
+}
+fn write_val(out: &mut Vec<u8>, val: &Val, depth: usize) -> Result<()> {
+	if depth > MAX_DEPTH {
+		throw!(RuntimeError("malformed binary value".into()))
+	}
+	match val.unwrap_if_lazy()? {
+		Val::Null => out.push(TAG_NULL),
+		Val::Bool(false) => out.push(TAG_FALSE),
+		Val::Bool(true) => out.push(TAG_TRUE),
+		Val::Num(n) => {
+			out.push(TAG_NUM);
+			out.extend_from_slice(&n.to_le_bytes());
+		}
+		Val::Str(s) => {
+			out.push(TAG_STR);
+			write_str(out, &s);
+		}
+		Val::Arr(items) => {
+			out.push(TAG_ARR);
+			write_len(out, items.len());
+			for item in items.iter() {
+				write_val(out, &item?, depth + 1)?;
+			}
+		}
+		Val::Obj(obj) => {
+			let fields = obj.visible_fields();
+			out.push(TAG_OBJ);
+			write_len(out, fields.len());
+			for field in fields {
+				write_str(out, &field);
+				write_val(out, &obj.get(field)?.unwrap(), depth + 1)?;
+			}
+		}
+		Val::Func(_) => throw!(RuntimeError("tried to serialize function".into())),
+		Val::Lazy(_) => unreachable!(),
+	}
+	Ok(())
+}
+
+struct Reader<'b>(&'b [u8]);
+impl<'b> Reader<'b> {
+	fn take(&mut self, len: usize) -> Result<&'b [u8]> {
+		if self.0.len() < len {
+			throw!(RuntimeError("malformed binary value".into()))
+		}
+		let (taken, rest) = self.0.split_at(len);
+		self.0 = rest;
+		Ok(taken)
+	}
+	fn read_len(&mut self) -> Result<usize> {
+		let mut len = 0usize;
+		let mut shift = 0;
+		loop {
+			let byte = self.take(1)?[0];
+			if shift >= usize::BITS {
+				throw!(RuntimeError("malformed binary value".into()))
+			}
+			len |= ((byte & 0x7f) as usize) << shift;
+			if byte & 0x80 == 0 {
+				return Ok(len);
+			}
+			shift += 7;
+		}
+	}
+	fn read_str(&mut self) -> Result<Rc<str>> {
+		let len = self.read_len()?;
+		match std::str::from_utf8(self.take(len)?) {
+			Ok(s) => Ok(s.into()),
+			Err(_) => throw!(RuntimeError("malformed binary value".into())),
+		}
+	}
+	fn read_val(&mut self, depth: usize) -> Result<Val> {
+		if depth > MAX_DEPTH {
+			throw!(RuntimeError("malformed binary value".into()))
+		}
+		Ok(match self.take(1)?[0] {
+			TAG_NULL => Val::Null,
+			TAG_FALSE => Val::Bool(false),
+			TAG_TRUE => Val::Bool(true),
+			TAG_NUM => Val::Num(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+			TAG_STR => Val::Str(self.read_str()?.into()),
+			TAG_ARR => {
+				let len = self.read_len()?;
+				// Length is not trusted, every element takes at least one byte
+				let mut out = Vec::with_capacity(len.min(self.0.len()));
+				for _ in 0..len {
+					out.push(self.read_val(depth + 1)?);
+				}
+				Val::Arr(out.into())
+			}
+			TAG_OBJ => {
+				let len = self.read_len()?;
+				let mut values = Vec::with_capacity(len.min(self.0.len()));
+				for _ in 0..len {
+					let key = self.read_str()?;
+					values.push((key, self.read_val(depth + 1)?));
+				}
+				Val::Obj(ObjValue::from_values(values))
+			}
+			_ => throw!(RuntimeError("malformed binary value".into())),
+		})
+	}
+}
+
+impl Val {
+	/// Serializes value with all its visible fields, functions can't be serialized
+	pub fn to_bytes(&self) -> Result<Vec<u8>> {
+		let mut out = Vec::new();
+		write_val(&mut out, self, 0)?;
+		Ok(out)
+	}
+	/// Deserializes value, serialized with [`Val::to_bytes`]
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+		let mut reader = Reader(bytes);
+		let val = reader.read_val(0)?;
+		if !reader.0.is_empty() {
+			throw!(RuntimeError("malformed binary value".into()))
+		}
+		Ok(val)
+	}
+}
+
+#[cfg(test)]
+pub mod tests {
+	use super::{MAX_DEPTH, TAG_ARR, TAG_NULL};
+	use crate::{equals, error::Error::*, EvaluationState, Val};
+	use std::{path::PathBuf, rc::Rc};
+
+	#[test]
+	fn roundtrip() {
+		let state = EvaluationState::default();
+		state.with_stdlib();
+		let val = state
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{a: [1, 2.5, null, true, {b: 'ü'}], c: {d: [[]], e: {}}, h:: 1}".into(),
+			)
+			.unwrap();
+		state.run_in_state(|| {
+			let bytes = val.to_bytes().unwrap();
+			let restored = Val::from_bytes(&bytes).unwrap();
+			assert!(equals(&val, &restored).unwrap());
+			assert_eq!(restored.to_json(0).unwrap(), val.to_json(0).unwrap());
+			assert!(Val::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+		});
+	}
+
+	#[test]
+	fn function_is_not_serializable() {
+		let state = EvaluationState::default();
+		state.with_stdlib();
+		let val = state
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{a: [function(x) x]}".into(),
+			)
+			.unwrap();
+		let err = state.run_in_state(|| val.to_bytes()).unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if &**e == "tried to serialize function"));
+	}
+
+	#[test]
+	fn deeply_nested_is_rejected() {
+		let bytes = [TAG_ARR, 1].repeat(1_000_000);
+		let err = Val::from_bytes(&bytes).unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if &**e == "malformed binary value"));
+
+		let nested = |depth| (0..depth).fold(Val::Null, |inner, _| Val::Arr(vec![inner].into()));
+		let mut bytes = [TAG_ARR, 1].repeat(MAX_DEPTH);
+		bytes.push(TAG_NULL);
+		assert_eq!(nested(MAX_DEPTH).to_bytes().unwrap(), bytes);
+		Val::from_bytes(&bytes).unwrap();
+
+		let mut bytes = [TAG_ARR, 1].repeat(MAX_DEPTH + 1);
+		bytes.push(TAG_NULL);
+		let err = Val::from_bytes(&bytes).unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if &**e == "malformed binary value"));
+		let err = nested(MAX_DEPTH + 1).to_bytes().unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if &**e == "malformed binary value"));
+	}
+}
@@ -1,2 +1,3 @@
+pub mod binary;
 #[cfg(feature = "serde-json")]
 pub mod serde;
@@ -2,10 +2,10 @@ use crate::{
 	error::{Error::*, LocError, Result},
 	throw, LazyBinding, LazyVal, ObjMember, ObjValue, Val,
 };
+use indexmap::IndexMap;
 use jrsonnet_parser::Visibility;
 use serde_json::{Map, Number, Value};
 use std::{
-	collections::HashMap,
 	convert::{TryFrom, TryInto},
 	rc::Rc,
 };
@@ -17,16 +17,19 @@ impl TryFrom<&Val> for Value {
 			Val::Bool(b) => Self::Bool(*b),
 			Val::Null => Self::Null,
 			Val::Str(s) => Self::String((s as &str).into()),
-			Val::Num(n) => Self::Number(if n.fract() <= f64::EPSILON {
-				(*n as i64).into()
-			} else {
-				Number::from_f64(*n).expect("to json number")
-			}),
+			Val::Num(n) if !n.is_finite() => throw!(RuntimeError(
+				format!("tried to manifest non-finite number {}", n).into()
+			)),
+			// Integers are kept as integers, unless they don't fit into i64
+			Val::Num(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+				Self::Number((*n as i64).into())
+			}
+			Val::Num(n) => Self::Number(Number::from_f64(*n).expect("number is finite")),
 			Val::Lazy(v) => (&v.evaluate()?).try_into()?,
 			Val::Arr(a) => {
 				let mut out = Vec::with_capacity(a.len());
 				for item in a.iter() {
-					out.push(item.try_into()?);
+					out.push((&item?).try_into()?);
 				}
 				Self::Array(out)
 			}
@@ -53,14 +56,14 @@ impl From<&Value> for Val {
 			Value::Number(n) => Self::Num(n.as_f64().expect("as f64")),
 			Value::String(s) => Self::Str((s as &str).into()),
 			Value::Array(a) => {
-				let mut out = Vec::with_capacity(a.len());
+				let mut out: Vec<Self> = Vec::with_capacity(a.len());
 				for v in a {
 					out.push(v.into());
 				}
-				Self::Arr(Rc::new(out))
+				Self::Arr(out.into())
 			}
 			Value::Object(o) => {
-				let mut entries = HashMap::with_capacity(o.len());
+				let mut entries = IndexMap::with_capacity(o.len());
 				for (k, v) in o {
 					entries.insert(
 						(k as &str).into(),
@@ -77,3 +80,72 @@ impl From<&Value> for Val {
 		}
 	}
 }
+
+#[cfg(test)]
+pub mod tests {
+	use crate::{equals, error::Error::*, EvaluationState, Val};
+	use serde_json::{json, Value};
+	use std::{convert::TryFrom, path::PathBuf, rc::Rc};
+
+	#[test]
+	fn roundtrip() {
+		let state = EvaluationState::default();
+		state.with_stdlib();
+		let val = state
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{b: [1, -1.5, 1e100, null, [true, {}]], a: {d: 'é', c: [[]]}, h:: 1}".into(),
+			)
+			.unwrap();
+		state.run_in_state(|| {
+			let value = Value::try_from(&val).unwrap();
+			assert_eq!(
+				value,
+				json!({"a": {"c": [[]], "d": "é"}, "b": [1, -1.5, 1e100, null, [true, {}]]})
+			);
+			assert_eq!(
+				value.as_object().unwrap().keys().collect::<Vec<_>>(),
+				vec!["a", "b"]
+			);
+			assert!(equals(&val, &Val::from(&value)).unwrap());
+		});
+	}
+
+	#[test]
+	fn unrepresentable_values() {
+		let err = Value::try_from(&Val::Num(f64::INFINITY)).unwrap_err();
+		assert!(
+			matches!(err.error(), RuntimeError(e) if &**e == "tried to manifest non-finite number inf")
+		);
+
+		let state = EvaluationState::default();
+		state.with_stdlib();
+		let val = state
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{a: [function(x) x]}".into(),
+			)
+			.unwrap();
+		let err = state.run_in_state(|| Value::try_from(&val)).unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if &**e == "tried to manifest function"));
+	}
+
+	#[test]
+	fn data_var() {
+		let state = EvaluationState::default();
+		state.with_stdlib();
+		state.add_data_var(
+			"config".into(),
+			Val::from(&json!({"name": "web", "ports": [80, 443], "tls": {"enabled": true}})),
+		);
+		let val = state
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"[config.name, config.ports[1], config.tls.enabled, std.length(config.ports)]"
+					.into(),
+			)
+			.unwrap();
+		let value = state.run_in_state(|| Value::try_from(&val)).unwrap();
+		assert_eq!(value, json!(["web", 443, true, 2]));
+	}
+}
@@ -2,7 +2,7 @@
 #![allow(macro_expanded_macro_exports_accessed_by_absolute_paths)]
 #![warn(clippy::all, clippy::nursery)]
 
-mod builtin;
+pub mod builtin;
 mod ctx;
 mod dynamic;
 pub mod error;
@@ -11,26 +11,32 @@ mod function;
 mod import;
 mod integrations;
 mod map;
+pub mod memo;
 pub mod native;
 mod obj;
+mod profile;
+pub mod schema;
 pub mod trace;
 mod val;
 
 pub use ctx::*;
 pub use dynamic::*;
-use error::{Error::*, LocError, Result, StackTraceElement};
+use error::{Error, Error::*, LocError, Result, StackTraceElement};
 pub use evaluate::*;
-pub use function::parse_function_call;
+pub use function::{parse_function_call, parse_function_call_slots, ParamSlots};
 pub use import::*;
 use jrsonnet_parser::*;
 use native::NativeCallback;
 pub use obj::*;
+use profile::ThunkProfiler;
+pub use profile::ThunkProfile;
 use std::{
 	cell::{Ref, RefCell, RefMut},
 	collections::HashMap,
 	fmt::Debug,
 	path::PathBuf,
 	rc::Rc,
+	time::{Duration, Instant},
 };
 use trace::{offset_to_location, CodeLocation, CompactFormat, TraceFormat};
 pub use val::*;
@@ -56,6 +62,15 @@ impl LazyBinding {
 	}
 }
 
+/// Restrictions for evaluation of untrusted code
+///
+/// Imports are always disabled in sandbox, pure stdlib functions are still usable
+#[derive(Default, Clone, Copy)]
+pub struct SandboxSettings {
+	/// Disables `std.native`
+	pub disable_natives: bool,
+}
+
 pub struct EvaluationSettings {
 	/// Limits recursion by limiting the number of stack frames
 	pub max_stack: usize,
@@ -65,6 +80,16 @@ pub struct EvaluationSettings {
 	pub ext_vars: HashMap<Rc<str>, Val>,
 	/// Used for ext.native
 	pub ext_natives: HashMap<Rc<str>, Rc<NativeCallback>>,
+	/// Time budget for a single native function call
+	///
+	/// Natives are synchronous, and can't be interrupted, so budget is checked after native
+	/// returns. Long-running natives should check deadline, passed to natives created with
+	/// [`NativeCallback::new_with_deadline`], cooperatively
+	pub native_timeout: Option<Duration>,
+	/// Enables sandbox mode, if set
+	pub sandbox: Option<SandboxSettings>,
+	/// Transforms errors returned from top-level evaluation, i.e to make them more user-friendly
+	pub error_hook: Option<Box<dyn Fn(Error) -> Error>>,
 	/// TLA vars
 	pub tla_vars: HashMap<Rc<str>, Val>,
 	/// Global variables are inserted in default context
@@ -75,6 +100,13 @@ pub struct EvaluationSettings {
 	pub manifest_format: ManifestFormat,
 	/// Used for bindings
 	pub trace_format: Box<dyn TraceFormat>,
+	/// Seed for intrinsics, which would otherwise be nondeterministic, evaluation result only
+	/// depends on code, inputs and this seed
+	///
+	/// Current intrinsics are all deterministic, and don't use it: field order of objects,
+	/// parsed by `std.parseJson`/`std.parseYaml`, follows the source, and named arguments are
+	/// bound in sorted order
+	pub seed: u64,
 }
 impl Default for EvaluationSettings {
 	fn default() -> Self {
@@ -84,6 +116,9 @@ impl Default for EvaluationSettings {
 			globals: Default::default(),
 			ext_vars: Default::default(),
 			ext_natives: Default::default(),
+			native_timeout: None,
+			sandbox: None,
+			error_hook: None,
 			tla_vars: Default::default(),
 			import_resolver: Box::new(DummyImportResolver),
 			manifest_format: ManifestFormat::Json(4),
@@ -91,6 +126,7 @@ impl Default for EvaluationSettings {
 				padding: 4,
 				resolver: trace::PathResolver::Absolute,
 			}),
+			seed: 0,
 		}
 	}
 }
@@ -102,6 +138,10 @@ struct EvaluationData {
 	/// Contains file source codes and evaluation results for imports and pretty-printed stacktraces
 	files: HashMap<Rc<PathBuf>, FileData>,
 	str_files: HashMap<Rc<PathBuf>, Rc<str>>,
+	/// Only set when thunk profiling is enabled
+	thunk_profiler: Option<ThunkProfiler>,
+	/// Checked on every stack frame push, see [`EvaluationState::run_with_deadline`]
+	deadline: Option<Instant>,
 }
 
 pub struct FileData {
@@ -280,8 +320,11 @@ impl EvaluationState {
 				// Error creation uses data, so i drop guard here
 				drop(data);
 				throw!(StackOverflow);
+			} else if matches!(data.deadline, Some(deadline) if Instant::now() >= deadline) {
+				drop(data);
+				throw!(DeadlineExceeded);
 			} else {
-				*stack_depth += 1;
+				data.stack_depth += 1;
 			}
 		}
 		let result = f();
@@ -296,6 +339,24 @@ impl EvaluationState {
 		result
 	}
 
+	/// Runs passed function in state, failing with [`Error::DeadlineExceeded`] once `deadline` passes
+	///
+	/// Deadline is checked cooperatively on every stack frame push, so native calls are not
+	/// interrupted. Nested deadlines can only shorten the outer one
+	pub fn run_with_deadline<T>(
+		&self,
+		deadline: Instant,
+		f: impl FnOnce() -> Result<T>,
+	) -> Result<T> {
+		self.run_in_state(|| {
+			let outer = self.data().deadline;
+			self.data_mut().deadline = Some(outer.map_or(deadline, |outer| outer.min(deadline)));
+			let result = f();
+			self.data_mut().deadline = outer;
+			result
+		})
+	}
+
 	/// Runs passed function in state (required if function needs to modify stack trace)
 	pub fn run_in_state<T>(&self, f: impl FnOnce() -> T) -> T {
 		EVAL_STATE.with(|v| {
@@ -311,6 +372,23 @@ impl EvaluationState {
 		})
 	}
 
+	/// Starts collecting evaluation times of thunks (locals, array elements and function arguments),
+	/// previously collected data is discarded
+	pub fn enable_thunk_profiling(&self) {
+		self.data_mut().thunk_profiler = Some(ThunkProfiler::default());
+	}
+	pub fn disable_thunk_profiling(&self) {
+		self.data_mut().thunk_profiler = None;
+	}
+	/// Returns up to `limit` expressions, which thunks took the most time to evaluate,
+	/// sorted by descending self time
+	pub fn thunk_profile_report(&self, limit: usize) -> Vec<ThunkProfile> {
+		self.data()
+			.thunk_profiler
+			.as_ref()
+			.map_or_else(Vec::new, |p| p.report(limit))
+	}
+
 	pub fn stringify_err(&self, e: &LocError) -> String {
 		let mut out = String::new();
 		self.settings()
@@ -359,15 +437,30 @@ impl EvaluationState {
 	pub fn settings_mut(&self) -> RefMut<EvaluationSettings> {
 		self.0.settings.borrow_mut()
 	}
+	fn apply_error_hook<T>(&self, result: Result<T>) -> Result<T> {
+		match (result, &self.settings().error_hook) {
+			(Err(e), Some(hook)) => Err(e.map_error(hook)),
+			(result, _) => result,
+		}
+	}
 }
 
 /// Raw methods evaluate passed values but don't perform TLA execution
 impl EvaluationState {
 	pub fn evaluate_file_raw(&self, name: &PathBuf) -> Result<Val> {
-		self.run_in_state(|| self.import_file(&std::env::current_dir().expect("cwd"), name))
+		self.apply_error_hook(
+			self.run_in_state(|| self.import_file(&std::env::current_dir().expect("cwd"), name)),
+		)
 	}
 	pub fn evaluate_file_raw_nocwd(&self, name: &PathBuf) -> Result<Val> {
-		self.run_in_state(|| self.import_file(&PathBuf::from("."), name))
+		self.apply_error_hook(self.run_in_state(|| self.import_file(&PathBuf::from("."), name)))
+	}
+	/// Evaluates every passed file, files imported by multiple of them are parsed and evaluated only once
+	pub fn evaluate_files_raw(&self, names: &[PathBuf]) -> HashMap<PathBuf, Result<Val>> {
+		names
+			.iter()
+			.map(|name| (name.clone(), self.evaluate_file_raw(name)))
+			.collect()
 	}
 	/// Parses and evaluates the given snippet
 	pub fn evaluate_snippet_raw(&self, source: Rc<PathBuf>, code: Rc<str>) -> Result<Val> {
@@ -384,7 +477,7 @@ impl EvaluationState {
 	}
 	/// Evaluates the parsed expression
 	pub fn evaluate_expr_raw(&self, code: LocExpr) -> Result<Val> {
-		self.run_in_state(|| evaluate(self.create_default_context()?, &code))
+		self.apply_error_hook(self.run_in_state(|| evaluate(self.create_default_context()?, &code)))
 	}
 }
 
@@ -394,7 +487,7 @@ impl EvaluationState {
 		self.settings_mut().ext_vars.insert(name, value);
 	}
 	pub fn add_ext_str(&self, name: Rc<str>, value: Rc<str>) {
-		self.add_ext_var(name, Val::Str(value));
+		self.add_ext_var(name, Val::Str(value.into()));
 	}
 	pub fn add_ext_code(&self, name: Rc<str>, code: Rc<str>) -> Result<()> {
 		let value =
@@ -407,7 +500,7 @@ impl EvaluationState {
 		self.settings_mut().tla_vars.insert(name, value);
 	}
 	pub fn add_tla_str(&self, name: Rc<str>, value: Rc<str>) {
-		self.add_tla(name, Val::Str(value));
+		self.add_tla(name, Val::Str(value.into()));
 	}
 	pub fn add_tla_code(&self, name: Rc<str>, code: Rc<str>) -> Result<()> {
 		let value =
@@ -416,6 +509,13 @@ impl EvaluationState {
 		Ok(())
 	}
 
+	/// Makes already built value available as a variable named `name` in default context,
+	/// unlike ext vars, value is accessed directly, without `std.extVar`.
+	/// Variables with name `std` replace standard library
+	pub fn add_data_var(&self, name: Rc<str>, value: Val) {
+		self.settings_mut().globals.insert(name, value);
+	}
+
 	pub fn resolve_file(&self, from: &PathBuf, path: &PathBuf) -> Result<Rc<PathBuf>> {
 		Ok(self.settings().import_resolver.resolve_file(from, path)?)
 	}
@@ -434,6 +534,24 @@ impl EvaluationState {
 		self.settings_mut().ext_natives.insert(name, cb);
 	}
 
+	pub fn native_timeout(&self) -> Option<Duration> {
+		self.settings().native_timeout
+	}
+	pub fn set_native_timeout(&self, timeout: Option<Duration>) {
+		self.settings_mut().native_timeout = timeout;
+	}
+
+	pub fn sandbox(&self) -> Option<SandboxSettings> {
+		self.settings().sandbox
+	}
+	pub fn set_sandbox(&self, sandbox: Option<SandboxSettings>) {
+		self.settings_mut().sandbox = sandbox;
+	}
+
+	pub fn set_error_hook(&self, hook: Option<Box<dyn Fn(Error) -> Error>>) {
+		self.settings_mut().error_hook = hook;
+	}
+
 	pub fn manifest_format(&self) -> ManifestFormat {
 		self.settings().manifest_format.clone()
 	}
@@ -461,14 +579,21 @@ impl EvaluationState {
 	pub fn set_max_stack(&self, trace: usize) {
 		self.settings_mut().max_stack = trace;
 	}
+
+	pub fn seed(&self) -> u64 {
+		self.settings().seed
+	}
+	pub fn set_seed(&self, seed: u64) {
+		self.settings_mut().seed = seed;
+	}
 }
 
 #[cfg(test)]
 pub mod tests {
 	use super::Val;
-	use crate::{error::Error::*, primitive_equals, EvaluationState};
+	use crate::{error::Error::*, lazy_val, primitive_equals, Context, EvaluationState};
 	use jrsonnet_parser::*;
-	use std::{path::PathBuf, rc::Rc};
+	use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc};
 
 	#[test]
 	#[should_panic]
@@ -765,158 +890,2096 @@ pub mod tests {
 	}
 
 	#[test]
-	fn utf8_chars() {
-		assert_json!(
-			r#"local c="😎";{c:std.codepoint(c),l:std.length(c)}"#,
-			r#"{"c": 128526,"l": 1}"#
-		)
+	fn parse_yaml() {
+		assert_eval!(r#"std.parseYaml("a: 1\nb: [true, null, 'c']") == {a: 1, b: [true, null, 'c']}"#);
+		assert_eval!(r#"std.parseYaml("--- 1\n--- 2\n") == [1, 2]"#);
 	}
 
 	#[test]
-	fn json() {
-		assert_json!(
-			r#"std.manifestJsonEx({a:3, b:4, c:6},"")"#,
-			r#""{\n\"a\": 3,\n\"b\": 4,\n\"c\": 6\n}""#
+	fn object_assertions() {
+		assert_eval!("{a: 1, assert self.a == 1}.a == 1");
+		assert_eval!("local base = {a: 1, assert self.a > 0 : 'positive'}; (base + {a: 2}).a == 2");
+		assert_eval!("std.objectFields({assert false}) == []");
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let eval = |code: &str| {
+			evaluator.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+		};
+		// Assertions of super objects are checked with final self
+		let err = eval("local base = {a: 1, assert self.a > 0 : 'positive'}; (base + {a: -1}).a")
+			.unwrap_err();
+		assert!(matches!(err.error(), AssertionFailed(msg) if &**msg == "positive"));
+		assert!(eval("{a: 1, assert false}.a").is_err());
+	}
+
+	#[test]
+	fn compare_values() {
+		use crate::{builtin::sort::SortError, compare, ValType};
+		assert_eval!("std.__compare(1, 2) == -1 && std.__compare('b', 'a') == 1");
+		assert_eval!("std.__compare([1, 2], [1, 2]) == 0");
+		assert_eval!("std.__compare([1, 2], [1, 3]) == -1 && std.__compare([1], [1, 0]) == -1");
+		assert_eval!("[1, 2] < [1, 3] && [[1], 'a'] > [[1]] && [] <= [] && ['b'] >= ['a', 'z']");
+		assert_eval!("std.sort([[1, 3], [1, 2], [1], []]) == [[], [1], [1, 2], [1, 3]]");
+		assert_eval!(
+			"std.sort([{k: [2]}, {k: [1, 'x']}], function(o) o.k) == [{k: [1, 'x']}, {k: [2]}]"
 		);
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let eval = |code: &str| {
+			evaluator.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+		};
+		let err = eval("std.__compare('a', 1)").unwrap_err();
+		assert!(matches!(
+			err.error(),
+			BinaryOperatorDoesNotOperateOnValues(BinaryOpType::Lt, ValType::Str, ValType::Num)
+		));
+		let err = eval("[1] < ['a']").unwrap_err();
+		assert!(matches!(
+			err.error(),
+			BinaryOperatorDoesNotOperateOnValues(BinaryOpType::Lt, ValType::Num, ValType::Str)
+		));
+		assert!(eval("std.__compare({}, {})").is_err());
+		assert!(eval("std.sort([[1], ['a']])").is_err());
+		let err = eval("std.sort([[1], 'a'])").unwrap_err();
+		assert!(matches!(
+			err.error(),
+			Sort(SortError::SortElementsShouldHaveEqualType)
+		));
+		assert!(compare(&Val::Num(f64::NAN), &Val::Num(1.0)).is_err());
 	}
 
 	#[test]
-	fn test() {
-		assert_json!(
-			r#"[[a, b] for a in [1,2,3] for b in [4,5,6]]"#,
-			"[[1,4],[1,5],[1,6],[2,4],[2,5],[2,6],[3,4],[3,5],[3,6]]"
+	fn safe_div() {
+		assert_eval!("std.safeDiv(6, 4) == 1.5");
+		assert_eval!("std.safeDiv(1, 0) == null");
+		assert_eval!("std.safeDiv(1, 0, default=0) == 0");
+		assert_eval!("std.safeDiv(1, -0, 'n/a') == 'n/a'");
+	}
+
+	#[test]
+	fn string_concat_chain() {
+		assert_eval!(
+			r#"
+				local s = std.foldl(function(acc, i) acc + "ab" + i, std.range(0, 99999), "");
+				s == std.join("", ["ab" + i for i in std.range(0, 99999)])
+				&& std.substr(s, 0, 7) == "ab0ab1a"
+			"#
 		);
+		assert_eval!("('a' + 'b') + ('c' + ('d' + '')) + 1 == 'abcd1'");
 	}
 
 	#[test]
-	fn sjsonnet() {
-		eval!(
+	fn repeated_named_calls() {
+		assert_eval!(
 			r#"
-			local x0 = {k: 1};
-			local x1 = {k: x0.k + x0.k};
-			local x2 = {k: x1.k + x1.k};
-			local x3 = {k: x2.k + x2.k};
-			local x4 = {k: x3.k + x3.k};
-			local x5 = {k: x4.k + x4.k};
-			local x6 = {k: x5.k + x5.k};
-			local x7 = {k: x6.k + x6.k};
-			local x8 = {k: x7.k + x7.k};
-			local x9 = {k: x8.k + x8.k};
-			local x10 = {k: x9.k + x9.k};
-			local x11 = {k: x10.k + x10.k};
-			local x12 = {k: x11.k + x11.k};
-			local x13 = {k: x12.k + x12.k};
-			local x14 = {k: x13.k + x13.k};
-			local x15 = {k: x14.k + x14.k};
-			local x16 = {k: x15.k + x15.k};
-			local x17 = {k: x16.k + x16.k};
-			local x18 = {k: x17.k + x17.k};
-			local x19 = {k: x18.k + x18.k};
-			local x20 = {k: x19.k + x19.k};
-			local x21 = {k: x20.k + x20.k};
-			x21.k
-		"#
+				local f(a, b=2, c=3) = a + b * c;
+				std.foldl(function(acc, i) acc + f(c=i, a=1), std.range(1, 100000), 0) == 10000200000
+			"#
 		);
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let err = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"local f(a) = a; f(a=1) + f(b=1)".into(),
+			)
+			.unwrap_err();
+		assert!(matches!(err.error(), UnknownFunctionParameter(p) if p == "b"));
 	}
 
-	// This test is commented out by default, because of huge compilation slowdown
-	// #[bench]
-	// fn bench_codegen(b: &mut Bencher) {
-	// 	b.iter(|| {
-	// 		#[allow(clippy::all)]
-	// 		let stdlib = {
-	// 			use jrsonnet_parser::*;
-	// 			include!(concat!(env!("OUT_DIR"), "/stdlib.rs"))
-	// 		};
-	// 		stdlib
-	// 	})
-	// }
+	#[test]
+	#[cfg(not(feature = "faster"))]
+	fn manifest_many_values() {
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let values = (0..10_000)
+			.map(|i| Val::Arr(vec![Val::Num(i as f64), Val::Bool(true)].into()))
+			.collect::<Vec<_>>();
+		evaluator.run_in_state(|| {
+			for (i, value) in values.iter().enumerate() {
+				assert_eq!(
+					&*value.to_std_json(0).unwrap(),
+					format!("[\n{},\ntrue\n]", i)
+				);
+				assert_eq!(&*value.to_yaml(2).unwrap(), format!("- {}\n- true", i));
+			}
+		});
+	}
 
-	/*
-	#[bench]
-	fn bench_serialize(b: &mut Bencher) {
-		b.iter(|| {
-			bincode::deserialize::<jrsonnet_parser::LocExpr>(include_bytes!(concat!(
-				env!("OUT_DIR"),
-				"/stdlib.bincode"
-			)))
-			.expect("deserialize stdlib")
-		})
+	#[test]
+	fn equals_set() {
+		assert_eval!("std.equalsSet([1, 2, 3], [3, 2, 1])");
+		assert_eval!("[1, 2, 3] != [3, 2, 1]");
+		assert_eval!("std.equalsSet([1, 1, {a: [2]}], [{a: [2]}, 1])");
+		assert_eval!("std.equalsSet([0], [-0])");
+		assert_eval!("!std.equalsSet([1, 2], [1, 2, 3])");
+		assert_eval!("!std.equalsSet([[1, 2]], [[2, 1]])");
 	}
 
-	#[bench]
-	fn bench_parse(b: &mut Bencher) {
-		b.iter(|| {
-			jrsonnet_parser::parse(
-				jrsonnet_stdlib::STDLIB_STR,
-				&jrsonnet_parser::ParserSettings {
-					loc_data: true,
-					file_name: Rc::new(PathBuf::from("std.jsonnet")),
-				},
-			)
-		})
+	#[test]
+	fn parse_json() {
+		assert_eval!(
+			r#"std.parseJson('{"a": [1, -2.5e1, true, null], "b": "\\u00e9\\ud83d\\ude00\\n"}') == {a: [1, -25, true, null], b: "é😀\n"}"#
+		);
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let eval = |code: &str| {
+			evaluator.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+		};
+		assert!(eval("std.parseJson('[1] 2')").is_err());
+		assert!(eval("std.parseJson('01')").is_err());
+		assert!(eval(r#"std.parseJson('"\\ud83d"')"#).is_err());
 	}
-	*/
 
 	#[test]
-	fn equality() {
-		println!(
-			"{:?}",
-			jrsonnet_parser::parse(
-				"{ x: 1, y: 2 } == { x: 1, y: 2 }",
-				&ParserSettings::default()
-			)
+	fn parse_structured_values() {
+		assert_eval!(r#"std.parseJson('{"a": {"b": [{"c": 1}]}}') == {a: {b: [{c: 1}]}}"#);
+		assert_eval!(r#"std.parseYaml("a:\n  b:\n  - c: 1\n") == {a: {b: [{c: 1}]}}"#);
+		// Numbers are not kept as strings
+		assert_eval!(r#"std.type(std.parseJson('{"a": 1.0}').a) == 'number'"#);
+		assert_eval!(r#"std.parseJson('[1.5e3]')[0] + 1 == 1501"#);
+		assert_eval!(r#"std.parseYaml('a: 1.0').a + 1 == 2"#);
+		assert_eval!(r#"std.parseYaml("a: 1\n---\nb: 2\n") == [{a: 1}, {b: 2}]"#);
+
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let eval = |code: &str| {
+			evaluator.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+		};
+		let err = eval(r#"std.parseJson('{"a": 1')"#).unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if e.starts_with("failed to parse json: ")));
+		let err = eval("std.parseYaml('a: [1')").unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if e.starts_with("failed to parse yaml: ")));
+	}
+	#[test]
+	fn object_keys_values() {
+		assert_eval!(
+			"std.objectKeysValues({b: 2, a: 1, h:: 3}) == [{key: 'a', value: 1}, {key: 'b', value: 2}]"
 		);
-		assert_eval!("{ x: 1, y: 2 } == { x: 1, y: 2 }")
+		assert_eval!(
+			"std.objectKeysValuesAll({b: 2, a: 1, h:: 3}) == [{key: 'a', value: 1}, {key: 'b', value: 2}, {key: 'h', value: 3}]"
+		);
+		assert_eval!("std.objectKeysValues({}) == []");
+		// Values are not evaluated unless used
+		assert_eval!("[kv.key for kv in std.objectKeysValues({a: error 'a', b: 1})] == ['a', 'b']");
+		assert_eval!("std.objectKeysValues({a: error 'a', b: 1})[1].value == 1");
+		assert_eval!("std.objectKeysValues({a: 1} + {a: super.a + 1})[0].value == 2");
 	}
 
 	#[test]
-	fn native_ext() -> crate::error::Result<()> {
-		use super::native::NativeCallback;
+	fn object_pick() {
+		use crate::ObjValue;
+		let obj = ObjValue::from_lazy_values(vec![
+			("a".into(), lazy_val!(|| Ok(Val::Num(1.0)))),
+			("b".into(), lazy_val!(|| panic!("omitted field forced"))),
+			("c".into(), lazy_val!(|| Ok(Val::Num(3.0)))),
+			("d".into(), lazy_val!(|| panic!("omitted field forced"))),
+		]);
+		let picked = obj.pick(&["c", "a", "missing"]);
+		assert_eq!(
+			picked.visible_fields(),
+			vec!["a".into(), "c".into()] as Vec<Rc<str>>
+		);
+		assert!(matches!(picked.get("c".into()).unwrap(), Some(Val::Num(n)) if n == 3.0));
+
+		assert_eval!(
+			"std.objectPick({a: 1, b: 2, c:: 3, d: error 'lazy'}, ['a', 'c', 'e']) == {a: 1}"
+		);
+		assert_eval!("std.objectFieldsAll(std.objectPick({a: 1, b:: 2}, ['a', 'b'])) == ['a']");
+	}
+
+	#[test]
+	fn object_remove_key() {
+		use crate::ObjValue;
+		let obj = ObjValue::from_lazy_values(vec![
+			("b".into(), lazy_val!(|| Ok(Val::Num(2.0)))),
+			("a".into(), lazy_val!(|| panic!("remaining field forced"))),
+			("c".into(), lazy_val!(|| panic!("removed field forced"))),
+		]);
+		let removed = obj.remove_key("c");
+		assert_eq!(
+			removed.visible_fields(),
+			vec!["a".into(), "b".into()] as Vec<Rc<str>>
+		);
+		assert!(matches!(removed.get("b".into()).unwrap(), Some(Val::Num(n)) if n == 2.0));
+		assert_eq!(
+			obj.remove_key("missing").visible_fields(),
+			vec!["a".into(), "b".into(), "c".into()] as Vec<Rc<str>>
+		);
+
+		assert_eval!("std.objectRemoveKey({a: 1, b: 2, c: error 'lazy'}, 'c') == {a: 1, b: 2}");
+		assert_eval!("std.objectRemoveKey({a: 1, b: 2}, 'missing') == {a: 1, b: 2}");
+		assert_eval!("std.objectRemoveKey({a: 1, h:: 2}, 'h') == {a: 1}");
+		assert_eval!("std.objectFieldsAll(std.objectRemoveKey({a: 1, h:: 2, b: 3}, 'a')) == ['b']");
+		assert_eval!("std.length(std.objectRemoveKey({a: error 'lazy', b: 1}, 'b')) == 1");
+	}
+
+	#[test]
+	fn object_merge_order() {
+		use crate::{
+			builtin::manifest::{manifest_json_ex, ManifestJsonOptions, ManifestType},
+			FieldOrder,
+		};
 		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let eval = |code: &str| {
+			evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+				.unwrap()
+		};
+		let manifest = |val: &Val| {
+			evaluator
+				.run_in_state(|| {
+					manifest_json_ex(
+						val,
+						&ManifestJsonOptions {
+							mtype: ManifestType::ToString,
+							field_order: FieldOrder::AsDefined,
+							..Default::default()
+						},
+					)
+				})
+				.unwrap()
+		};
+		let expected = r#"{"z": 1, "h": 6, "a": 5, "y": {"q": 1, "p": 2}, "d": 4, "b": 7}"#;
+		let left = "{z: 1, h:: 2, a: 3, y: {q: 1}}";
+		let right = "{d: 4, a: 5, h::: 6, y+: {p: 2}, b: 7}";
+		for _ in 0..10 {
+			assert_eq!(manifest(&eval(&format!("{} + {}", left, right))), expected);
+			let merged = match (eval(left), eval(right)) {
+				(Val::Obj(left), Val::Obj(right)) => left.merge(&right),
+				_ => panic!("objects expected"),
+			};
+			assert_eq!(manifest(&Val::Obj(merged)), expected);
+		}
+		assert_eq!(
+			manifest(&eval(&format!("({} + {}) + {{b: 8, e: 9}}", left, right))),
+			r#"{"z": 1, "h": 6, "a": 5, "y": {"q": 1, "p": 2}, "d": 4, "b": 8, "e": 9}"#
+		);
+	}
+
+	#[test]
+	fn object_get() {
+		assert_eval!("std.get({a: 1}, 'a') == 1");
+		assert_eval!("std.get({a: 1}, 'b') == null");
+		assert_eval!("std.get({a: 1}, 'b', 2) == 2");
+		assert_eval!("std.get({a: 1}, 'a', error 'default is lazy') == 1");
+		assert_eval!("std.get({a:: 1}, 'a', 2) == 1");
+		assert_eval!("std.get({a:: 1}, 'a', 2, false) == 2");
+		assert_eval!("std.get({a:: 1}, 'a', inc_hidden=false) == null");
+		assert_eval!("std.get({a: 1} + {a+: 2}, 'a', 0, false) == 3");
+	}
+
 
+	#[test]
+	fn parse_json_duplicate_keys() {
+		let evaluator = EvaluationState::default();
 		evaluator.with_stdlib();
-		evaluator.settings_mut().ext_natives.insert(
-			"native_add".into(),
-			Rc::new(NativeCallback::new(
-				ParamsDesc(Rc::new(vec![
-					Param("a".into(), None),
-					Param("b".into(), None),
-				])),
-				|args| match (&args[0], &args[1]) {
-					(Val::Num(a), Val::Num(b)) => Ok(Val::Num(a + b)),
-					(_, _) => todo!(),
-				},
-			)),
+		let err = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				r#"std.parseJson('{"a": 1, "a": 2}')"#.into(),
+			)
+			.unwrap_err();
+		assert!(
+			matches!(err.error(), RuntimeError(e) if &**e == "failed to parse json: duplicate key \"a\" at offset 9")
 		);
-		evaluator.evaluate_snippet_raw(
-			Rc::new(PathBuf::from("test.jsonnet")),
-			"std.assertEqual(std.native(\"native_add\")(1, 2), 3)".into(),
-		)?;
-		Ok(())
+		assert_eval!(r#"std.parseJson('{"a": 1, "a": 2}', allowDuplicateKeys=true) == {a: 2}"#);
 	}
 
 	#[test]
-	fn constant_intrinsic() -> crate::error::Result<()> {
+	fn parse_json_lenient() {
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let eval = |code: String| {
+			evaluator.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+		};
+		let json5 = r#"'{\n  // comment\n  "a": [1, 2,],\n  /* another */ "b": {"c": 3,},\n}'"#;
+		assert!(eval(format!("std.parseJson({})", json5)).is_err());
+		assert!(eval(format!("std.parseJson({}, allowComments=true)", json5)).is_err());
+		assert!(eval(format!(
+			"std.assertEqual(std.parseJson({}, allowComments=true, allowTrailingCommas=true), {{a: [1, 2], b: {{c: 3}}}})",
+			json5
+		))
+		.is_ok());
+	}
+
+	#[test]
+	fn parse_yaml_merge_keys() {
 		assert_eval!(
-			"local std2 = std; local std = std2 { primitiveEquals(a, b):: false }; 1 == 1"
+			r#"std.parseYaml("base: &base {a: 1, b: 2}\nchild:\n  <<: *base\n  b: 3\n").child == {a: 1, b: 3}"#
+		);
+		assert_eval!(
+			r#"std.parseYaml("x: &x {a: 1, b: 1}\ny: &y {b: 2, c: 2}\nz:\n  <<: [*x, *y]\n  c: 3\n").z == {a: 1, b: 1, c: 3}"#
+		);
+		assert_eval!(
+			r#"std.parseYaml("a: &a {x: 1}\nb: &b {<<: *a, y: 2}\nc: {<<: *b}\n").c == {x: 1, y: 2}"#
 		);
-		Ok(())
 	}
 
-	struct TestImportResolver(Rc<str>);
-	impl crate::import::ImportResolver for TestImportResolver {
-		fn resolve_file(&self, _: &PathBuf, _: &PathBuf) -> crate::error::Result<Rc<PathBuf>> {
-			Ok(Rc::new(PathBuf::from("/test")))
-		}
+	#[test]
+	fn parse_yaml_numeric_strings_roundtrip() {
+		use crate::builtin::manifest::{manifest_yaml_ex, ManifestYamlOptions};
+		assert_json!(
+			r#"std.manifestYamlDoc(std.parseYaml('a: "1.0"\nb: 1.0'))"#,
+			r#""\"a\": \"1.0\"\n\"b\": 1""#
+		);
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let val = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				r#"std.parseYaml('a: "1.0"\nb: 1.0')"#.into(),
+			)
+			.unwrap();
+		let yaml = evaluator
+			.run_in_state(|| manifest_yaml_ex(&val, &ManifestYamlOptions::default()))
+			.unwrap();
+		assert_eq!(yaml, "\"a\": \"1.0\"\n\"b\": 1");
+	}
 
-		fn load_file_contents(&self, _: &PathBuf) -> crate::error::Result<Rc<str>> {
-			Ok(self.0.clone())
-		}
+	#[test]
+	fn utf8_chars() {
+		assert_json!(
+			r#"local c="😎";{c:std.codepoint(c),l:std.length(c)}"#,
+			r#"{"c": 128526,"l": 1}"#
+		)
+	}
 
-		unsafe fn as_any(&self) -> &dyn std::any::Any {
-			panic!()
-		}
+	#[test]
+	fn json() {
+		assert_json!(
+			r#"std.manifestJsonEx({a:3, b:4, c:6},"")"#,
+			r#""{\n\"a\": 3,\n\"b\": 4,\n\"c\": 6\n}""#
+		);
+	}
+
+	#[test]
+	fn json_custom_renderers() {
+		use crate::builtin::manifest::{manifest_json_ex, ManifestJsonOptions, ManifestType};
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let val = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{price: 3, items: [1.5, 'a']}".into(),
+			)
+			.unwrap();
+		let render_number = |n: f64| format!("{:.2}", n);
+		let render_string = |s: &str| format!("'{}'", s);
+		let json = evaluator
+			.run_in_state(|| {
+				manifest_json_ex(
+					&val,
+					&ManifestJsonOptions {
+						mtype: ManifestType::ToString,
+						render_number: Some(&render_number),
+						render_string: Some(&render_string),
+						..Default::default()
+					},
+				)
+			})
+			.unwrap();
+		assert_eq!(json, r#"{"items": [1.50, 'a'], "price": 3.00}"#);
+	}
+
+	#[test]
+	fn empty_yaml_stream() {
+		use crate::{EmptyYamlStream, ManifestFormat};
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let manifest = |code: &str, empty| {
+			evaluator.set_manifest_format(ManifestFormat::YamlStream(
+				Box::new(ManifestFormat::Json(0)),
+				empty,
+			));
+			let val = evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+				.unwrap();
+			evaluator.manifest(val).unwrap()
+		};
+		assert_eq!(&*manifest("[]", EmptyYamlStream::Empty), "");
+		assert_eq!(&*manifest("[]", EmptyYamlStream::Document), "---\n");
+		assert_eq!(
+			&*manifest("[1, 2]", EmptyYamlStream::Document),
+			"---\n1\n---\n2\n..."
+		);
+	}
+
+	#[test]
+	fn thunk_profile_report() {
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		evaluator.enable_thunk_profiling();
+		let source = PathBuf::from("raw.jsonnet");
+		let val = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(source.clone()),
+				r#"
+					local slow(n) = std.length(std.foldl(function(acc, x) acc + [x], std.range(1, n), []));
+					local expensive = slow(2000);
+					local cheap = 1 + 1;
+					[expensive, cheap]
+				"#
+				.into(),
+			)
+			.unwrap();
+		evaluator.run_in_state(|| val.to_json(0)).unwrap();
+
+		let report = evaluator.thunk_profile_report(3);
+		assert_eq!(report.len(), 3);
+		let code = evaluator.get_source(&source).unwrap();
+		let top = &report[0];
+		assert_eq!(&code[top.location.1..top.location.2], "slow(2000)");
+		assert_eq!(top.count, 1);
+		assert!(report.windows(2).all(|w| w[0].self_time >= w[1].self_time));
+
+		evaluator.disable_thunk_profiling();
+		assert!(evaluator.thunk_profile_report(3).is_empty());
+	}
+
+	#[test]
+	fn to_string_with_hidden() {
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let val = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{a: 1, b:: 2, c: {d:: [3]}}".into(),
+			)
+			.unwrap();
+		evaluator.run_in_state(|| {
+			assert_eq!(&*val.to_string().unwrap(), r#"{"a": 1, "c": { }}"#);
+			assert_eq!(
+				&*val.to_string_with_hidden().unwrap(),
+				r#"{"a": 1, "b": 2, "c": {"d": [3]}}"#
+			);
+		});
+	}
+
+	#[test]
+	fn to_string_conformance() {
+		assert_eval!(r#"std.toString("a\nb") == "a\nb""#);
+		assert_eval!(
+			r#"std.toString(null) + std.toString(true) + std.toString(1.5) == "nulltrue1.5""#
+		);
+		assert_eval!(r#"std.toString([[], {}, [[1]], "a"]) == '[[ ], { }, [[1]], "a"]'"#);
+		assert_eval!(
+			r#"std.toString({b: [1, {c: "x\n"}], a: null, h:: 1}) == '{"a": null, "b": [1, {"c": "x\\n"}]}'"#
+		);
+		assert_eval!(r#"std.toString([1, 2]) == "" + [1, 2]"#);
+
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let eval = |code: &str| {
+			evaluator.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+		};
+		for code in &[
+			"std.toString(function() 1)",
+			"std.toString([1, function() 1])",
+			"'' + {a: {b: std.length}}",
+		] {
+			let err = eval(code).unwrap_err();
+			assert!(
+				matches!(err.error(), RuntimeError(e) if &**e == "couldn't manifest function as JSON")
+			);
+		}
+	}
+
+	#[test]
+	fn json_special_keys() {
+		assert_eval!(r#"std.manifestJsonEx({'a"b': 1}, '') == '{\n"a\\"b": 1\n}'"#);
+		assert_eval!(r#"std.manifestJsonEx({'a\\b': 1}, '') == '{\n"a\\\\b": 1\n}'"#);
+		assert_eval!(r#"std.manifestJsonEx({'a\nb': 1}, '') == '{\n"a\\nb": 1\n}'"#);
+		assert_json!(r#"{'a"\\\n': 1}"#, r#"{"a\"\\\n": 1}"#);
+		assert_eval!(r#"std.manifestJsonEx({'': 1}, '') == '{\n"": 1\n}'"#);
+		assert_eval!(r#"std.manifestJsonEx({'': {'': ''}}, '  ') == '{\n  "": {\n    "": ""\n  }\n}'"#);
+		assert_eval!(
+			r#"local o = {'': [{'': null}], a: {'': 1}}; std.parseJson(std.manifestJsonEx(o, ' ')) == o"#
+		);
+		assert_eval!(r#"std.objectFields(std.parseJson('{"": 1, "a": 2}')) == ['', 'a']"#);
+		assert_json!(r#"{'': {'': 1}}"#, r#"{"": {"": 1}}"#);
+	}
+
+	#[test]
+	fn manifest_json_key_order() {
+		assert_eval!(
+			r#"std.manifestJsonExOrdered({spec: {}, kind: "Pod", apiVersion: "v1", data: 1}, "", ["apiVersion", "kind", "missing"]) == '{\n"apiVersion": "v1",\n"kind": "Pod",\n"data": 1,\n"spec": {\n\n}\n}'"#
+		);
+	}
+
+	#[test]
+	fn deep_merge() {
+		assert_eval!(
+			"std.deepMerge({a: {b: 1, c: {d: 2}}, e: 3}, {a: {c: {f: 4}}, e: 5}) == {a: {b: 1, c: {d: 2, f: 4}}, e: 5}"
+		);
+		assert_eval!("std.deepMerge({a: {b: [1, 2]}}, {a: {b: [3]}}) == {a: {b: [3]}}");
+		assert_eval!(
+			"std.deepMerge({a: {b: [1, 2]}}, {a: {b: [3]}}, concatArrays=true) == {a: {b: [1, 2, 3]}}"
+		);
+		assert_eval!("std.deepMerge({a: [1]}, {a: {b: 2}}, true) == {a: {b: 2}}");
+	}
+
+	#[test]
+	fn merge_patch() {
+		assert_eval!(
+			"std.mergePatch({a: 1, b: {c: 2, d: 3}}, {b: {d: null, e: 4}}) == {a: 1, b: {c: 2, e: 4}}"
+		);
+		assert_eval!("std.mergePatch({a: {b: {c: 1}}}, {a: {b: {c: null}}}) == {a: {b: {}}}");
+		assert_eval!("std.mergePatch({a: 1}, {b: null}) == {a: 1}");
+		assert_eval!("std.mergePatch({a: [1, 2]}, {a: [3]}) == {a: [3]}");
+		assert_eval!("std.mergePatch({a: 1}, [1]) == [1]");
+		assert_eval!("std.mergePatch([1], {a: 1, b: {c: null}}) == {a: 1, b: {}}");
+		assert_eval!("std.mergePatch({a: 1, h:: 2}, {}) == {a: 1}");
+	}
+
+	#[test]
+	fn natural_sort() {
+		assert_json!(
+			r#"std.naturalSort(["item1", "item10", "item2"])"#,
+			r#"["item1","item2","item10"]"#
+		);
+		assert_json!(
+			r#"std.naturalSort(["a10b2", "a10b10", "a9", "b", "a"])"#,
+			r#"["a","a9","a10b2","a10b10","b"]"#
+		);
+
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		assert!(evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"std.naturalSort([1, 2])".into()
+			)
+			.is_err());
+	}
+
+	#[test]
+	fn ini() {
+		assert_eval!(
+			r#"std.manifestIni({main: {a: 1}, sections: {s: {b: ['x', 'y'], c: true}}}) == 'a = 1\n[s]\nb = x\nb = y\nc = true\n'"#
+		);
+	}
+
+	#[test]
+	fn ini_array_join() {
+		use crate::builtin::manifest::{manifest_ini_ex, IniArrayMode, ManifestIniOptions};
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let ini = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{sections: {s: {a: [1, 'b', 3]}}}".into(),
+			)
+			.unwrap();
+		let manifest = |array_mode| {
+			evaluator
+				.run_in_state(|| manifest_ini_ex(&ini, &ManifestIniOptions { array_mode }))
+				.unwrap()
+		};
+		assert_eq!(
+			manifest(IniArrayMode::Repeat),
+			"[s]\na = 1\na = b\na = 3\n"
+		);
+		assert_eq!(manifest(IniArrayMode::Join(",")), "[s]\na = 1,b,3\n");
+	}
+
+	#[test]
+	fn ini_manifest_format() {
+		use crate::ManifestFormat;
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let manifest = |code: &str| {
+			let val = evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+				.unwrap();
+			evaluator.run_in_state(|| val.manifest(&ManifestFormat::Ini))
+		};
+		// Same as output of reference std.manifestIni
+		assert_eq!(
+			&*manifest(
+				r#"{
+					main: {b: "x", a: 1, h:: "hidden"},
+					sections: {
+						server: {port: 8080, hosts: ["a", "b"], tls: false},
+						empty: {},
+						db: {url: null, opts: {x: 1}},
+					},
+				}"#
+			)
+			.unwrap(),
+			"a = 1\nb = x\n[db]\nopts = {\"x\": 1}\nurl = null\n[empty]\n[server]\nhosts = a\nhosts = b\nport = 8080\ntls = false\n"
+		);
+		assert_eq!(&*manifest("{sections: {}}").unwrap(), "");
+
+		let err = manifest("{main: {a: 1}}").unwrap_err();
+		assert!(matches!(err.error(), NoSuchField(f) if &**f == "sections"));
+		let err = manifest("{sections: {s: [1]}}").unwrap_err();
+		assert!(matches!(
+			err.error(),
+			TypeMismatch("manifestIni section", _, crate::ValType::Arr)
+		));
+		assert!(manifest("[]").is_err());
+	}
+
+	#[test]
+	fn csv_manifest_format() {
+		use crate::ManifestFormat;
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let manifest = |code: &str, delimiter: char, header: bool| {
+			let val = evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+				.unwrap();
+			evaluator.run_in_state(|| val.manifest(&ManifestFormat::Csv { delimiter, header }))
+		};
+		let rows = r#"[
+			{name: "plain", note: "a,b", n: 1.5, h:: [1]},
+			{name: 'say "hi"', note: "line\nbreak", n: null, h:: {}},
+			{name: "tab\there", note: "", n: true},
+		]"#;
+		assert_eq!(
+			&*manifest(rows, ',', true).unwrap(),
+			"n,name,note\n1.5,plain,\"a,b\"\nnull,\"say \"\"hi\"\"\",\"line\nbreak\"\ntrue,tab\there,\n"
+		);
+		assert_eq!(
+			&*manifest(rows, '\t', false).unwrap(),
+			"1.5\tplain\ta,b\nnull\t\"say \"\"hi\"\"\"\t\"line\nbreak\"\ntrue\t\"tab\there\"\t\n"
+		);
+		assert_eq!(&*manifest("[]", ',', true).unwrap(), "");
+
+		let err = manifest("[{a: 1, b: 2}, {a: 3}]", ',', true).unwrap_err();
+		assert_eq!(
+			err.error().to_string(),
+			"runtime error: csv row 1 has no field b"
+		);
+		let err = manifest("[{a: 1}, {a: 3, c: 4}]", ',', true).unwrap_err();
+		assert_eq!(
+			err.error().to_string(),
+			"runtime error: csv row 1 has unexpected field c"
+		);
+		assert!(manifest("[{a: [1]}]", ',', true).is_err());
+		assert!(manifest("[{a: {}}]", ',', true).is_err());
+		assert!(manifest("{a: 1}", ',', true).is_err());
+	}
+
+	#[test]
+	fn context_shadowing() {
+		let outer = Context::new()
+			.with_var("a".into(), Val::Num(1.0))
+			.with_var("b".into(), Val::Num(2.0));
+		let inner = outer
+			.clone()
+			.with_var("a".into(), Val::Num(3.0))
+			.with_var("c".into(), Val::Num(4.0));
+		assert_eq!(inner.var_names(), vec!["a".into(), "b".into(), "c".into()] as Vec<Rc<str>>);
+		assert_eq!(inner.shadowed_vars(&outer), vec!["a".into()] as Vec<Rc<str>>);
+		assert!(outer.shadowed_vars(&outer).is_empty());
+	}
+
+	#[test]
+	fn object_get_lazy() {
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let val = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				r#"{a: error "a", b: 2, c: error "c"}"#.into(),
+			)
+			.unwrap();
+		let obj = match val {
+			Val::Obj(obj) => obj,
+			_ => panic!("expected object"),
+		};
+		let b = obj.get_lazy("b".into()).unwrap();
+		let a = obj.get_lazy("a".into()).unwrap();
+		assert!(obj.get_lazy("d".into()).is_none());
+		evaluator.run_in_state(|| {
+			assert!(primitive_equals(&b.evaluate().unwrap(), &Val::Num(2.0)).unwrap());
+			assert!(a.evaluate().is_err());
+		});
+	}
+
+	#[test]
+	fn sum_stable() {
+		assert_eval!("std.sum([1, 2, 3.5]) == 6.5 && std.sumStable([1, 2, 3.5]) == 6.5");
+		assert_eval!(
+			"local arr = std.makeArray(100000, function(i) 0.1); local err(x) = std.abs(x - 10000); err(std.sumStable(arr)) < err(std.sum(arr))"
+		);
+	}
+
+	#[test]
+	fn array_iter_indexed_lazy() {
+		use crate::{lazy_val, ArrValue};
+		let arr: ArrValue = (0..3)
+			.map(|_| lazy_val!(|| panic!("should not be evaluated")))
+			.collect::<Vec<_>>()
+			.into();
+		let indices: Vec<_> = arr.iter_indexed_lazy().map(|(i, _)| i).collect();
+		assert_eq!(indices, vec![0, 1, 2]);
+	}
+
+	#[test]
+	fn array_comprehension_filter() {
+		assert_json!(
+			"[x for x in std.range(0, 9) if std.modulo(x, 2) == 1]",
+			"[1,3,5,7,9]"
+		);
+		assert_json!(
+			"[[x, y] for x in [1, 2, 3] if x != 2 for y in ['a', null] if y != null]",
+			r#"[[1,"a"],[3,"a"]]"#
+		);
+		assert_json!("[x for x in [1, 2] if false]", "[]");
+		assert_json!(
+			"std.reverse([{v: x} for x in std.range(0, 6) if std.modulo(x, 2) == 0])",
+			r#"[{"v": 6},{"v": 4},{"v": 2},{"v": 0}]"#
+		);
+		assert_eval!(
+			"local a = [x for x in std.range(0, 9) if std.modulo(x, 2) == 1]; std.length(a) == 5 && a[4] == 9"
+		);
+		assert_eval!(
+			"std.manifestJsonEx([x for x in [null, 1, null] if x != null], '') == '[\\n1\\n]'"
+		);
+	}
+
+	#[test]
+	fn array_range() {
+		use crate::ArrValue;
+		let num = |v: Option<Val>| match v {
+			Some(Val::Num(n)) => Some(n),
+			_ => None,
+		};
+		let range = ArrValue::new_range(-1, 2);
+		assert_eq!(range.len(), 4);
+		assert_eq!(num(range.get(0).unwrap()), Some(-1.0));
+		assert_eq!(num(range.get(3).unwrap()), Some(2.0));
+		assert!(range.get(4).unwrap().is_none());
+		assert!(range.get_lazy(4).is_none());
+
+		let reversed = range.reversed();
+		assert_eq!(reversed.len(), 4);
+		let items: Vec<_> = reversed.iter().map(|v| num(Some(v.unwrap()))).collect();
+		assert_eq!(items, vec![Some(2.0), Some(1.0), Some(0.0), Some(-1.0)]);
+		assert_eq!(reversed.evaluated().unwrap().len(), 4);
+		assert_eq!(reversed.reversed().len(), 4);
+
+		let empty = ArrValue::new_range(3, 2);
+		assert!(empty.is_empty());
+		assert!(empty.get(0).unwrap().is_none());
+		assert!(empty.clone().reversed().is_empty());
+		assert!(empty.evaluated().unwrap().is_empty());
+
+		assert_eval!("std.range(0, 1000000)[500000] == 500000");
+		assert_eval!("std.range(-2, 1) == [-2, -1, 0, 1]");
+		assert_eval!("std.range(5, 4) == []");
+		assert_eval!("std.reverse(std.range(1, 3)) == [3, 2, 1]");
+		assert_eval!("std.range(1, 3) + [4] == [1, 2, 3, 4]");
+
+		// Full i64 span doesn't overflow length computation
+		let huge = ArrValue::new_range(i64::MIN, i64::MAX);
+		assert_eq!(huge.len(), usize::MAX);
+		assert_eq!(num(huge.get(0).unwrap()), Some(i64::MIN as f64));
+
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let eval = |code: &str| {
+			evaluator.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+		};
+		assert!(eval("std.range(-1e300, 1e300)").is_err());
+		assert_eval!("std.length(std.range(-9007199254740991, -9007199254740990)) == 2");
+	}
+	#[test]
+	fn array_concat() {
+		use crate::{ArrValue, LazyVal, MAX_CONCAT_DEPTH};
+		let num = |v: Option<Val>| match v {
+			Some(Val::Num(n)) => Some(n),
+			_ => None,
+		};
+		// [0, 1, 2, 3, 4, ...], built from single-element arrays
+		let mut arr = ArrValue::new_eager();
+		for i in 0..100 {
+			let item = ArrValue::Lazy(Rc::new(vec![LazyVal::new_resolved(Val::Num(i as f64))]));
+			arr = if i % 2 == 0 {
+				ArrValue::concat(arr, item)
+			} else {
+				ArrValue::concat(arr, ArrValue::concat(item, ArrValue::new_eager()))
+			};
+			match &arr {
+				ArrValue::Concat { depth, .. } => assert!(*depth <= MAX_CONCAT_DEPTH),
+				ArrValue::Lazy(_) => {}
+				_ => panic!("unexpected array kind"),
+			}
+		}
+		assert_eq!(arr.len(), 100);
+		for i in [0, 31, 32, 33, 64, 99].iter() {
+			assert_eq!(num(arr.get(*i).unwrap()), Some(*i as f64));
+			assert!(arr.get_lazy(*i).is_some());
+		}
+		assert!(arr.get(100).unwrap().is_none());
+		assert!(arr.get_lazy(100).is_none());
+
+		let nested = ArrValue::concat(
+			ArrValue::concat(ArrValue::new_range(0, 2), ArrValue::new_range(3, 4)),
+			ArrValue::concat(ArrValue::new_range(5, 5), ArrValue::new_range(6, 9)),
+		);
+		assert!(matches!(
+			nested,
+			ArrValue::Concat {
+				len: 10,
+				depth: 2,
+				..
+			}
+		));
+		let items: Vec<_> = nested.iter().map(|v| num(Some(v.unwrap()))).collect();
+		assert_eq!(items, (0..10).map(|i| Some(i as f64)).collect::<Vec<_>>());
+		assert_eq!(nested.evaluated().unwrap().len(), 10);
+		assert_eq!(num(nested.get(4).unwrap()), Some(4.0));
+		let reversed = nested.reversed();
+		assert_eq!(reversed.len(), 10);
+		assert_eq!(num(reversed.get(0).unwrap()), Some(9.0));
+		assert_eq!(num(reversed.get(6).unwrap()), Some(3.0));
+
+		// Elements are not evaluated by concatenation
+		assert_eval!("([error 'a'] + [1] + [error 'b'])[1] == 1");
+		assert_eval!("std.length([error 'a'] + [error 'b']) == 2");
+		assert_eval!(
+			"std.foldl(function(a, x) a + [x], std.range(0, 999), []) == std.range(0, 999)"
+		);
+		assert_eval!("std.reverse([1, 2] + [3] + []) == [3, 2, 1]");
+	}
+
+	#[test]
+	fn array_flatten() {
+		use crate::{ArrValue, LazyVal, ValType};
+		let arr = ArrValue::Eager(Rc::new(vec![
+			Val::Arr(ArrValue::Lazy(Rc::new(vec![
+				LazyVal::new_resolved(Val::Num(1.0)),
+				lazy_val!(|| panic!("inner element should not be evaluated")),
+			]))),
+			Val::Arr(ArrValue::new_range(3, 3)),
+		]));
+		let flat = arr.flatten().unwrap();
+		assert_eq!(flat.len(), 3);
+		assert!(matches!(flat.get(0).unwrap(), Some(Val::Num(n)) if n == 1.0));
+		assert!(matches!(flat.get(2).unwrap(), Some(Val::Num(n)) if n == 3.0));
+
+		let err = ArrValue::Eager(Rc::new(vec![Val::Num(1.0)]))
+			.flatten()
+			.unwrap_err();
+		assert!(matches!(err.error(), TypeMismatch(_, _, ValType::Num)));
+
+		assert_json!("std.flattenArrays([[1, 2], [3]])", "[1,2,3]");
+		assert_json!("std.flattenArrays([[], [[1]], []])", "[[1]]");
+		assert_eval!("std.length(std.flattenArrays([[error 'lazy'], [2]])) == 2");
+	}
+
+	#[test]
+	fn array_slice() {
+		use crate::ArrValue;
+		assert_json!("std.range(0, 10)[1:8:3]", "[1,4,7]");
+		assert_json!("std.slice(std.range(0, 10), 1, 8, 3)", "[1,4,7]");
+		assert_json!("[1, 2, 3][5:]", "[]");
+		assert_json!("[1, 2, 3][-2:]", "[2,3]");
+		assert_json!("[1, 2, 3][:-5]", "[]");
+		assert_json!(r#""hello"[1::2]"#, r#""el""#);
+		// Elements are not evaluated unless accessed
+		assert_json!("[error 'a', 1, error 'b', 2][1::2]", "[1,2]");
+		// Slices of reversed arrays go in the reversed order
+		assert_json!("std.reverse(std.range(0, 9))[::4]", "[9,5,1]");
+		assert_json!("std.reverse([1, 2, 3] + [4, 5])[1:4]", "[4,3,2]");
+
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let err = |code: &str| {
+			evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+				.unwrap_err()
+				.error()
+				.clone()
+		};
+		assert!(matches!(err("[1, 2, 3][::0]"), InvalidSliceStep(n) if n == 0.0));
+		assert!(matches!(err("[1, 2, 3][::-1]"), InvalidSliceStep(n) if n == -1.0));
+		assert!(matches!(err("'abc'[::0]"), InvalidSliceStep(_)));
+		assert!(matches!(err("[1, 2, 3][0.5:]"), FractionalIndex));
+
+		// Nested slices refer to the original array
+		assert_json!(
+			"std.foldl(function(a, _) a[1::1], std.range(1, 10000), std.range(0, 10002))",
+			"[10000,10001,10002]"
+		);
+		let arr = ArrValue::new_range(0, 99)
+			.slice(1, 100, 2)
+			.unwrap()
+			.slice(1, 40, 3)
+			.unwrap();
+		match &arr {
+			ArrValue::Slice {
+				inner, start, step, ..
+			} => {
+				assert!(matches!(**inner, ArrValue::Range { .. }));
+				assert_eq!((*start, *step), (3, 6));
+			}
+			_ => panic!("slice expected"),
+		}
+		assert_eq!(arr.len(), 13);
+		assert!(primitive_equals(&arr.get(12).unwrap().unwrap(), &Val::Num(75.0)).unwrap());
+		assert!(arr.get(13).unwrap().is_none());
+	}
+
+	#[test]
+	fn fold() {
+		assert_eval!("std.foldl(function(acc, x) error 'unreachable', [], 'init') == 'init'");
+		assert_eval!("std.foldr(function(x, acc) error 'unreachable', [], 'init') == 'init'");
+		assert_json!(
+			"std.foldl(function(acc, x) acc + x, ['a', 'b', 'c'], '')",
+			r#""abc""#
+		);
+		// foldr passes element first, and starts from the end
+		assert_json!(
+			"std.foldr(function(x, acc) acc + x, ['a', 'b', 'c'], '')",
+			r#""cba""#
+		);
+		assert_json!(
+			"std.foldr(function(key, acc) {[key]: acc}, ['a', 'b', 'c'], 1)",
+			r#"{"a": {"b": {"c": 1}}}"#
+		);
+		assert_json!(
+			"std.foldl(function(acc, key) {[key]: acc}, ['a', 'b', 'c'], 1)",
+			r#"{"c": {"b": {"a": 1}}}"#
+		);
+
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		for (code, frame) in [
+			(
+				"std.foldl(function(acc, x) if x == 2 then error 'bad' else acc, [1, 2, 3], 0)",
+				"foldl element 1",
+			),
+			(
+				"std.foldr(function(x, acc) if x == 3 then error 'bad' else acc, [1, 2, 3, 4], 0)",
+				"foldr element 2",
+			),
+		]
+		.iter()
+		{
+			let err = evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), (*code).into())
+				.unwrap_err();
+			assert!(matches!(err.error(), RuntimeError(e) if &**e == "bad"));
+			assert!(err.trace().0.iter().any(|f| f.desc == *frame));
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "regex-intrinsics")]
+	fn regex() {
+		assert_json!(
+			r#"std.splitByRegex("a1b22c,d", "[0-9]+|,")"#,
+			r#"["a","b","c","d"]"#
+		);
+		assert_json!(r#"std.splitByRegex("", ",")"#, r#"[""]"#);
+		assert_json!(
+			r#"std.matchRegex("v1.22", "^v([0-9]+)\\.([0-9]+)(-rc)?$")"#,
+			r#"["v1.22","1","22",null]"#
+		);
+		assert_json!(r#"std.matchRegex("abc", "[0-9]")"#, "null");
+		assert_json!(
+			r#"std.replaceRegex("john smith, jane doe", "(\\w+) (?P<last>\\w+)", "${last} $1")"#,
+			r#""smith john, doe jane""#
+		);
+
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let err = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				r#"std.splitByRegex("a", "(")"#.into(),
+			)
+			.unwrap_err();
+		assert!(matches!(err.error(), InvalidRegex(e) if e.contains("unclosed group")));
+	}
+	#[test]
+	fn array_builder() {
+		use crate::{ArrValue, ArrValueBuilder, LazyVal};
+		let mut builder = ArrValueBuilder::with_capacity(100_010);
+		assert!(builder.is_empty());
+		for i in 0..100_000 {
+			if i % 2 == 0 {
+				builder.push_val(Val::Num(i as f64));
+			} else {
+				builder.push(LazyVal::new_resolved(Val::Num(i as f64)));
+			}
+		}
+		builder.extend(&ArrValue::new_range(100_000, 100_009));
+		assert_eq!(builder.len(), 100_010);
+		let arr = builder.build();
+		match &arr {
+			// Only reference to backing vec, which was not reallocated
+			ArrValue::Lazy(items) => {
+				assert_eq!(Rc::strong_count(items), 1);
+				assert_eq!(items.capacity(), 100_010);
+			}
+			_ => panic!("lazy array expected"),
+		}
+		for (i, item) in arr.iter().enumerate() {
+			match item.unwrap() {
+				Val::Num(n) => assert_eq!(n, i as f64),
+				_ => panic!("number expected"),
+			}
+		}
+
+		assert_eval!("std.filter(function(x) x > 4, std.range(0, 9)) == [5, 6, 7, 8, 9]");
+		assert_eval!("std.filter(function(x) x != null, [1, null, 2]) == [1, 2]");
+		assert_eval!("std.join([0], [[1], [2, 3], []]) == [1, 0, 2, 3, 0]");
+		assert_eval!("std.join([], [[1], [error 'lazy']])[0] == 1");
+	}
+
+	#[test]
+	fn display() {
+		use crate::{error::Error, ArrValue, LazyVal, LocError};
+		assert_eq!(format!("{}", Val::Bool(true)), "true");
+		assert_eq!(format!("{}", Val::Null), "null");
+		assert_eq!(format!("{}", Val::Str("a\"b".into())), r#""a\"b""#);
+		assert_eq!(format!("{}", Val::Num(1.0)), "1");
+		assert_eq!(format!("{}", Val::Num(-0.5)), "-0.5");
+		assert_eq!(format!("{}", Val::Arr(ArrValue::new_eager())), "[]");
+		assert_eq!(
+			format!("{}", Val::Lazy(LazyVal::new_resolved(Val::Num(2.0)))),
+			"2"
+		);
+
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let val = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{a: [1, 'x', error 'e'], b: {}, f: function() 1, h:: 2, e: error 'e'}".into(),
+			)
+			.unwrap();
+		evaluator.run_in_state(|| {
+			assert_eq!(
+				format!("{}", val),
+				r#"{"a": [1, "x", <error>], "b": {}, "e": <error>, "f": <function>}"#
+			);
+			let arr = Val::Arr(ArrValue::Lazy(Rc::new(vec![
+				LazyVal::new_resolved(Val::Null),
+				LazyVal::new(Box::new(|| {
+					Err(LocError::new(Error::RuntimeError("failed".into())))
+				})),
+			])));
+			assert_eq!(format!("{}", arr), "[null, <error>]");
+			let failed = Val::Lazy(LazyVal::new(Box::new(|| {
+				Err(LocError::new(Error::RuntimeError("failed".into())))
+			})));
+			assert_eq!(format!("{}", failed), "<error>");
+		});
+	}
+
+	#[test]
+	fn type_name() {
+		use crate::{error::Error, LazyVal, LocError};
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let val = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"[true, null, 'a', 1, [], {}, function() 1, std.length]".into(),
+			)
+			.unwrap();
+		evaluator.run_in_state(|| {
+			let names = match &val {
+				Val::Arr(a) => a.iter().map(|v| v.unwrap().type_name()).collect::<Vec<_>>(),
+				_ => panic!("array expected"),
+			};
+			assert_eq!(
+				names,
+				vec![
+					"boolean", "null", "string", "number", "array", "object", "function",
+					"function"
+				]
+			);
+		});
+		assert_eq!(
+			Val::Lazy(LazyVal::new_resolved(Val::Num(2.0))).type_name(),
+			"number"
+		);
+		let failed = Val::Lazy(LazyVal::new(Box::new(|| {
+			Err(LocError::new(Error::RuntimeError("failed".into())))
+		})));
+		assert_eq!(failed.type_name(), "<error>");
+
+		assert_json!(
+			"[std.type(x) for x in [true, null, 'a', 1, [], {}, function() 1]]",
+			r#"["boolean","null","string","number","array","object","function"]"#
+		);
+	}
+
+	#[test]
+	fn hash_structural() {
+		use std::{collections::hash_map::DefaultHasher, hash::Hasher};
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let hash = |code: &str| {
+			let val = evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+				.unwrap();
+			evaluator.run_in_state(|| {
+				let mut hasher = DefaultHasher::new();
+				val.hash_structural(&mut hasher).map(|()| hasher.finish())
+			})
+		};
+		let h = |code: &str| hash(code).unwrap();
+		assert_eq!(h("{a: 1, b: [1, 'x']}"), h("{b: [1, 'x']} + {a: 1, c:: 2}"));
+		assert_eq!(h("-0"), h("0"));
+		assert_eq!(h("'ab'"), h("'a' + 'b'"));
+		assert_eq!(h("[{a: {b: null}}]"), h("[{a: {b: null}}]"));
+		assert_ne!(h("[1, 2]"), h("[2, 1]"));
+		assert_ne!(h("{a: {b: 1, c: 2}}"), h("{a: {b: 2, c: 1}}"));
+		assert_ne!(h("{a: 1}"), h("{b: 1}"));
+		assert_ne!(h("[[1], []]"), h("[[], [1]]"));
+		assert_ne!(h("null"), h("false"));
+		assert_ne!(h("'1'"), h("1"));
+
+		assert!(hash("[function() 1]").is_err());
+		let err = hash("[1, error 'lazy']").unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if &**e == "lazy"));
+	}
+
+
+
+	#[test]
+	fn error_hook() {
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		evaluator.set_error_hook(Some(Box::new(|e| match e {
+			RuntimeError(msg) => RuntimeError(format!("friendly: {}", msg).into()),
+			e => e,
+		})));
+		let err = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"error 'boom'".into(),
+			)
+			.unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if &**e == "friendly: boom"));
+	}
+
+	#[test]
+	fn for_each_leaf() {
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let val = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{a: 1, b: [true, {c: 'x'}], d: {}, e:: 5, f(x): x}".into(),
+			)
+			.unwrap();
+		let mut paths = Vec::new();
+		evaluator
+			.run_in_state(|| {
+				val.for_each_leaf(|path, _| {
+					paths.push(path.join("."));
+					Ok(())
+				})
+			})
+			.unwrap();
+		assert_eq!(paths, vec!["a", "b.0", "b.1.c"]);
+	}
+
+	#[test]
+	fn merge_into() {
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let eval = |code: &str| {
+			evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+				.unwrap()
+		};
+		let defaults = eval("{name: 'app', replicas: 1, labels: {tier: 'web'}}");
+		let env = eval("{replicas: 3, labels+: {env: 'prod'}, port: 80}");
+		let local_overrides = Val::Obj(crate::ObjValue::from_values(vec![
+			("name".into(), Val::Str("app-local".into())),
+			("debug".into(), Val::Bool(true)),
+		]));
+		evaluator.run_in_state(|| {
+			let merged = defaults.merge_into(vec![env, local_overrides]).unwrap();
+			assert_eq!(
+				&*merged.to_string().unwrap(),
+				r#"{"debug": true, "labels": {"env": "prod", "tier": "web"}, "name": "app-local", "port": 80, "replicas": 3}"#
+			);
+			assert!(Val::Num(1.0).merge_into(vec![]).is_err());
+		});
+	}
+
+	#[test]
+	fn group_by() {
+		assert_eval!(
+			"std.groupBy([1, 2, 3, 4, 5], function(x) std.toString(std.floor(x / 2) * 2 == x)) == {'false': [1, 3, 5], 'true': [2, 4]}"
+		);
+		assert_eval!(
+			"std.groupBy(['b1', 'a1', 'b2', 'a2', 'c1'], function(s) s[0]) == {a: ['a1', 'a2'], b: ['b1', 'b2'], c: ['c1']}"
+		);
+		assert_eval!("std.groupBy([], function(x) error 'not called') == {}");
+	}
+
+	#[test]
+	fn find_first() {
+		assert_eval!(r#"std.findFirst(function(x) x == 2, [1, 2, error "not evaluated"]) == 1"#);
+		assert_eval!(r#"std.findFirst(function(x) x > 2, [1, 2]) == null"#);
+	}
+
+	#[test]
+	fn array_position() {
+		use crate::{throw, ArrValue, LazyVal};
+		let is_two = |v: &Val| Ok(matches!(v, Val::Num(n) if *n == 2.0));
+		let lazy = ArrValue::Lazy(Rc::new(vec![
+			LazyVal::new_resolved(Val::Num(2.0)),
+			lazy_val!(|| panic!("tail should not be evaluated")),
+		]));
+		assert_eq!(lazy.position(is_two).unwrap(), Some(0));
+		let eager = ArrValue::Eager(Rc::new(vec![Val::Num(1.0), Val::Num(3.0)]));
+		assert_eq!(eager.position(is_two).unwrap(), None);
+		assert_eq!(
+			ArrValue::new_range(0, 5).position_from(3, is_two).unwrap(),
+			None
+		);
+
+		let failing = ArrValue::Lazy(Rc::new(vec![
+			LazyVal::new_resolved(Val::Num(1.0)),
+			lazy_val!(|| throw!(RuntimeError("scan failed".into()))),
+			LazyVal::new_resolved(Val::Num(2.0)),
+		]));
+		let err = failing.position(is_two).unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if &**e == "scan failed"));
+
+		assert_json!("std.find(2, [1, 2, 3, 2])", "[1,3]");
+		assert_json!("std.find(1, [1, 2])", "[0]");
+		assert_json!("std.find(4, [1, 2])", "[]");
+		assert_json!("std.find({a: [1]}, [{a: [1]}, [1]])", "[0]");
+
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let err = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"std.findFirst(function(x) if x > 1 then error 'failed' else false, [1, 2, 3])"
+					.into(),
+			)
+			.unwrap_err();
+		assert!(matches!(err.error(), RuntimeError(e) if &**e == "failed"));
+	}
+
+	#[test]
+	fn toml_datetime() {
+		assert_json!(
+			r#"std.manifestToml({created: {__toml_datetime__: "2023-01-01T00:00:00Z"}, name: "x", t: {a: 1}})"#,
+			r#""created = 2023-01-01T00:00:00Z\nname = \"x\"\n\n[t]\n  a = 1""#
+		);
+	}
+
+	#[test]
+	fn toml_manifest_format() {
+		use crate::ManifestFormat;
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let manifest = |code: &str| {
+			let val = evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+				.unwrap();
+			evaluator.run_in_state(|| val.manifest(&ManifestFormat::Toml(2)))
+		};
+		assert_eq!(
+			&*manifest(
+				r#"{
+					title: "TOML \"Example\"",
+					owner: {name: "Tom", dob: {__toml_datetime__: "1979-05-27T07:32:00-08:00"}},
+					database: {ports: [8000, 8001], enabled: true, limits: {cpu: 0.5}},
+					products: [{name: "Hammer", sku: 738594937}, {}, {name: "Nail", color: "gray"}],
+					notes: "first line\nsecond \"line\"\\",
+				}"#
+			)
+			.unwrap(),
+			r#"notes = """
+first line
+second \"line\"\\"""
+title = "TOML \"Example\""
+
+[database]
+  enabled = true
+  ports = [
+    8000,
+    8001
+  ]
+
+  [database.limits]
+    cpu = 0.5
+
+[owner]
+  dob = 1979-05-27T07:32:00-08:00
+  name = "Tom"
+
+[[products]]
+  name = "Hammer"
+  sku = 738594937
+
+[[products]]
+
+[[products]]
+  color = "gray"
+  name = "Nail""#
+		);
+		assert_eq!(
+			&*manifest(r#"{"a.b": {"": [{x: [1, "y"]}, 2]}}"#).unwrap(),
+			r#"["a.b"]
+  "" = [
+    { x = [ 1, "y" ] },
+    2
+  ]"#
+		);
+		let err = manifest("{a: {b: function(x) x}}").unwrap_err();
+		assert!(
+			matches!(err.error(), RuntimeError(e) if &**e == "tried to manifest function at a.b")
+		);
+	}
+	#[test]
+	fn manifest_custom_indent() {
+		use crate::ManifestFormat;
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let val = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{a: {b: [1, {c: null}]}, d: 'e'}".into(),
+			)
+			.unwrap();
+		let manifest = |format: ManifestFormat| {
+			evaluator
+				.run_in_state(|| val.manifest(&format))
+				.unwrap()
+				.to_string()
+		};
+		assert_eq!(
+			manifest(ManifestFormat::JsonIndent("\t".into())),
+			"{\n\t\"a\": {\n\t\t\"b\": [\n\t\t\t1,\n\t\t\t{\n\t\t\t\t\"c\": null\n\t\t\t}\n\t\t]\n\t},\n\t\"d\": \"e\"\n}"
+		);
+		assert_eq!(
+			manifest(ManifestFormat::JsonIndent("".into())),
+			r#"{"a": {"b": [1,{"c": null}]},"d": "e"}"#
+		);
+		assert_eq!(
+			manifest(ManifestFormat::JsonIndent("  ".into())),
+			manifest(ManifestFormat::Json(2))
+		);
+		assert_eq!(
+			manifest(ManifestFormat::YamlIndent("\t".into())),
+			"\"a\":\n\t\"b\":\n\t\t- 1\n\t\t- \"c\": null\n\"d\": \"e\""
+		);
+		assert_eq!(
+			manifest(ManifestFormat::YamlIndent("  ".into())),
+			manifest(ManifestFormat::Yaml(2))
+		);
+	}
+
+
+	#[test]
+	fn toml_escaping() {
+		assert_json!(
+			r#"std.manifestToml({a: 'say "hi"', b: 'back\\slash'})"#,
+			r#""a = \"say \\\"hi\\\"\"\nb = \"back\\\\slash\"""#
+		);
+		assert_json!(
+			r#"std.manifestToml({a: 'line1\n"""\nline3'})"#,
+			r#""a = \"\"\"\nline1\n\\\"\\\"\\\"\nline3\"\"\"""#
+		);
+		assert_json!(
+			r#"std.manifestToml({a: '\u0001\u007f\t', b: 'x\ry'})"#,
+			r#""a = \"\\u0001\\u007f\\t\"\nb = \"x\\ry\"""#
+		);
+		assert_json!(
+			r#"std.manifestToml({a: 'x\n\u0001\r'})"#,
+			r#""a = \"\"\"\nx\n\\u0001\\r\"\"\"""#
+		);
+		assert_json!(
+			r#"std.manifestToml({a: 1e21, b: -1e300, c: 42})"#,
+			r#""a = 1e21\nb = -1e300\nc = 42""#
+		);
+	}
+
+	#[test]
+	fn yaml() {
+		assert_json!(
+			r#"std.manifestYamlDoc({a: [1, {b: 2}], c: "d", e: "f\n"})"#,
+			r#""\"a\":\n- 1\n- \"b\": 2\n\"c\": \"d\"\n\"e\": |\n  f""#
+		);
+		assert_json!(
+			r#"std.manifestYamlDoc({a: "007", b: "1.0"})"#,
+			r#""\"a\": \"007\"\n\"b\": \"1.0\"""#
+		);
+		assert_eval!(
+			r#"std.parseYaml(std.manifestYamlDoc({a: "007", b: "1.0"})) == {a: "007", b: "1.0"}"#
+		);
+	}
+
+	#[test]
+	fn yaml_multiline_strings_in_array() {
+		assert_json!(
+			r#"std.manifestYamlDoc({a: ["l1\nl2\n", "x\ny\n"]}, true)"#,
+			r#""\"a\":\n  - |\n    l1\n    l2\n  - |\n    x\n    y""#
+		);
+		assert_eval!(
+			r#"local v = {a: ["l1\nl2\n", "x\ny\n"], b: [["p\nq\n"]], c: 1}; std.parseYaml(std.manifestYamlDoc(v)) == v"#
+		);
+	}
+
+	#[test]
+	fn debug_json() {
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let json = evaluator.run_in_state(|| {
+			evaluator
+				.evaluate_snippet_raw(
+					Rc::new(PathBuf::from("raw.jsonnet")),
+					"{n: 5, f: function(x, y) x, h:: 1}".into(),
+				)
+				.unwrap()
+				.to_debug_json()
+				.unwrap()
+		});
+		assert_eq!(
+			&json as &str,
+			r#"{"$type": "object", "value": {"f": {"$type": "function", "name": "anonymous", "params": ["x", "y"]}, "n": {"$type": "number", "value": 5}}}"#
+		);
+	}
+
+	#[test]
+	fn test() {
+		assert_json!(
+			r#"[[a, b] for a in [1,2,3] for b in [4,5,6]]"#,
+			"[[1,4],[1,5],[1,6],[2,4],[2,5],[2,6],[3,4],[3,5],[3,6]]"
+		);
+	}
+
+	#[test]
+	fn sjsonnet() {
+		eval!(
+			r#"
+			local x0 = {k: 1};
+			local x1 = {k: x0.k + x0.k};
+			local x2 = {k: x1.k + x1.k};
+			local x3 = {k: x2.k + x2.k};
+			local x4 = {k: x3.k + x3.k};
+			local x5 = {k: x4.k + x4.k};
+			local x6 = {k: x5.k + x5.k};
+			local x7 = {k: x6.k + x6.k};
+			local x8 = {k: x7.k + x7.k};
+			local x9 = {k: x8.k + x8.k};
+			local x10 = {k: x9.k + x9.k};
+			local x11 = {k: x10.k + x10.k};
+			local x12 = {k: x11.k + x11.k};
+			local x13 = {k: x12.k + x12.k};
+			local x14 = {k: x13.k + x13.k};
+			local x15 = {k: x14.k + x14.k};
+			local x16 = {k: x15.k + x15.k};
+			local x17 = {k: x16.k + x16.k};
+			local x18 = {k: x17.k + x17.k};
+			local x19 = {k: x18.k + x18.k};
+			local x20 = {k: x19.k + x19.k};
+			local x21 = {k: x20.k + x20.k};
+			x21.k
+		"#
+		);
+	}
+
+	// This test is commented out by default, because of huge compilation slowdown
+	// #[bench]
+	// fn bench_codegen(b: &mut Bencher) {
+	// 	b.iter(|| {
+	// 		#[allow(clippy::all)]
+	// 		let stdlib = {
+	// 			use jrsonnet_parser::*;
+	// 			include!(concat!(env!("OUT_DIR"), "/stdlib.rs"))
+	// 		};
+	// 		stdlib
+	// 	})
+	// }
+
+	/*
+	#[bench]
+	fn bench_serialize(b: &mut Bencher) {
+		b.iter(|| {
+			bincode::deserialize::<jrsonnet_parser::LocExpr>(include_bytes!(concat!(
+				env!("OUT_DIR"),
+				"/stdlib.bincode"
+			)))
+			.expect("deserialize stdlib")
+		})
+	}
+
+	#[bench]
+	fn bench_parse(b: &mut Bencher) {
+		b.iter(|| {
+			jrsonnet_parser::parse(
+				jrsonnet_stdlib::STDLIB_STR,
+				&jrsonnet_parser::ParserSettings {
+					loc_data: true,
+					file_name: Rc::new(PathBuf::from("std.jsonnet")),
+				},
+			)
+		})
+	}
+	*/
+
+	#[test]
+	fn equality() {
+		println!(
+			"{:?}",
+			jrsonnet_parser::parse(
+				"{ x: 1, y: 2 } == { x: 1, y: 2 }",
+				&ParserSettings::default()
+			)
+		);
+		assert_eval!("{ x: 1, y: 2 } == { x: 1, y: 2 }")
+	}
+
+	#[test]
+	fn number_equality() {
+		use crate::equals;
+		assert_eval!("-0 == 0");
+		assert_eval!("std.equals([-0], [0])");
+		assert_eval_neg!("1 == 1 + 2.220446049250313e-16");
+		assert_eval_neg!("[1] == [1 + 2.220446049250313e-16]");
+		assert_eval!("9007199254740992 == 9007199254740993");
+		assert_eval_neg!("9007199254740992 == 9007199254740994");
+		assert_eval_neg!("{a: 9007199254740992} == {a: 9007199254740994}");
+
+		let nan = Val::Num(f64::NAN);
+		assert!(!primitive_equals(&nan, &nan).unwrap());
+		assert!(!primitive_equals(&nan, &Val::Num(0.0)).unwrap());
+		let arr = Val::Arr(vec![nan.clone()].into());
+		assert!(!equals(&arr, &arr).unwrap());
+		let obj = Val::Obj(crate::ObjValue::from_values(vec![("a".into(), nan)]));
+		assert!(!equals(&obj, &obj).unwrap());
+	}
+
+	#[test]
+	fn native_ext() -> crate::error::Result<()> {
+		use super::native::NativeCallback;
+		let evaluator = EvaluationState::default();
+
+		evaluator.with_stdlib();
+		evaluator.settings_mut().ext_natives.insert(
+			"native_add".into(),
+			Rc::new(NativeCallback::new(
+				ParamsDesc(Rc::new(vec![
+					Param("a".into(), None),
+					Param("b".into(), None),
+				])),
+				|args| match (&args[0], &args[1]) {
+					(Val::Num(a), Val::Num(b)) => Ok(Val::Num(a + b)),
+					(_, _) => todo!(),
+				},
+			)),
+		);
+		evaluator.evaluate_snippet_raw(
+			Rc::new(PathBuf::from("test.jsonnet")),
+			"std.assertEqual(std.native(\"native_add\")(1, 2), 3)".into(),
+		)?;
+		Ok(())
+	}
+
+	#[test]
+	fn native_lazy() {
+		use super::native::NativeCallback;
+		use crate::{LazyVal, ObjValue};
+		use std::cell::Cell;
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let computed = Rc::new(Cell::new(0));
+		let counter = computed.clone();
+		evaluator.add_native(
+			"native_lazy".into(),
+			Rc::new(NativeCallback::new_lazy(
+				ParamsDesc(Rc::new(vec![Param("a".into(), None)])),
+				move |args| {
+					let a = args[0].clone();
+					let counter = counter.clone();
+					Ok(LazyVal::new_resolved(Val::Obj(ObjValue::from_lazy_values(
+						vec![
+							("cheap".into(), LazyVal::new_resolved(a)),
+							(
+								"expensive".into(),
+								LazyVal::new(Box::new(move || {
+									counter.set(counter.get() + 1);
+									Ok(Val::Num(42.0))
+								})),
+							),
+						],
+					))))
+				},
+			)),
+		);
+		let eval = |code: &str| {
+			evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("test.jsonnet")), code.into())
+				.unwrap()
+		};
+		assert!(
+			primitive_equals(&eval("std.native('native_lazy')(1).cheap"), &Val::Num(1.0)).unwrap()
+		);
+		assert_eq!(computed.get(), 0);
+		assert!(primitive_equals(
+			&eval("local o = std.native('native_lazy')(1); o.expensive + o.expensive"),
+			&Val::Num(84.0)
+		)
+		.unwrap());
+		assert_eq!(computed.get(), 1);
+	}
+
+	#[test]
+	fn lazy_val_caches_error() {
+		use crate::{error::Error::*, throw, LazyVal};
+		use std::cell::Cell;
+		let calls = Rc::new(Cell::new(0));
+		let counter = calls.clone();
+		let lazy = LazyVal::new(Box::new(move || {
+			counter.set(counter.get() + 1);
+			throw!(RuntimeError("expensive failure".into()))
+		}));
+		for _ in 0..3 {
+			let err = lazy.evaluate().unwrap_err();
+			assert!(matches!(err.error(), RuntimeError(e) if &**e == "expensive failure"));
+		}
+		assert_eq!(calls.get(), 1);
+	}
+
+	#[test]
+	fn lazy_val_retries_stack_overflow() {
+		use crate::{error::Result, LazyVal};
+		fn at_depth(
+			state: &EvaluationState,
+			depth: usize,
+			f: &dyn Fn() -> Result<Val>,
+		) -> Result<Val> {
+			if depth == 0 {
+				return f();
+			}
+			state.push(
+				&ExprLocation(Rc::new(PathBuf::from("raw.jsonnet")), 0, 0),
+				|| "frame".to_owned(),
+				|| at_depth(state, depth - 1, f),
+			)
+		}
+		let state = EvaluationState::default();
+		state.set_max_stack(10);
+		let thunk_state = state.clone();
+		let lazy = LazyVal::new(Box::new(move || {
+			at_depth(&thunk_state, 1, &|| Ok(Val::Num(1.0)))
+		}));
+		state.run_in_state(|| {
+			// Thunk itself needs one more frame, which doesn't fit at the stack limit
+			let err = at_depth(&state, state.max_stack() + 1, &|| lazy.evaluate()).unwrap_err();
+			assert!(matches!(err.error(), StackOverflow));
+			assert!(matches!(lazy.evaluate().unwrap(), Val::Num(n) if n == 1.0));
+		});
+	}
+
+	#[test]
+	fn call_with_values() {
+		use super::native::NativeCallback;
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		evaluator.add_native(
+			"sub".into(),
+			Rc::new(NativeCallback::new(
+				ParamsDesc(Rc::new(vec![
+					Param("a".into(), None),
+					Param("b".into(), None),
+				])),
+				|args| match (&args[0], &args[1]) {
+					(Val::Num(a), Val::Num(b)) => Ok(Val::Num(a - b)),
+					_ => unreachable!(),
+				},
+			)),
+		);
+		let func = |code: &str| match evaluator
+			.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+			.unwrap()
+		{
+			Val::Func(f) => f,
+			_ => panic!("function expected"),
+		};
+		let length = func("std.length");
+		let sub = func("std.native('sub')");
+		let arr = Val::Arr(vec![Val::Null, Val::Null].into());
+		let num = |v: crate::error::Result<Val>| match v.unwrap() {
+			Val::Num(n) => n,
+			_ => panic!("number expected"),
+		};
+		evaluator.run_in_state(|| {
+			assert_eq!(
+				num(length.evaluate_values(Context::new(), std::slice::from_ref(&arr))),
+				2.0
+			);
+			let mut args = HashMap::new();
+			args.insert("x".into(), arr.clone());
+			assert_eq!(num(length.evaluate_map(Context::new(), &args, true)), 2.0);
+			assert_eq!(
+				num(sub.evaluate_values(Context::new(), &[Val::Num(5.0), Val::Num(3.0)])),
+				2.0
+			);
+			let mut args = HashMap::new();
+			args.insert("b".into(), Val::Num(5.0));
+			args.insert("a".into(), Val::Num(3.0));
+			assert_eq!(num(sub.evaluate_map(Context::new(), &args, true)), -2.0);
+
+			let err = length
+				.evaluate_values(Context::new(), &[arr.clone(), arr.clone()])
+				.unwrap_err();
+			assert!(matches!(err.error(), TooManyArgsFunctionHas(1)));
+			let err = length.evaluate_values(Context::new(), &[]).unwrap_err();
+			assert!(matches!(err.error(), FunctionParameterNotBoundInCall(p) if &**p == "x"));
+			let err = sub
+				.evaluate_values(Context::new(), &[Val::Num(1.0)])
+				.unwrap_err();
+			assert!(matches!(err.error(), FunctionParameterNotBoundInCall(p) if &**p == "b"));
+			let mut args = HashMap::new();
+			args.insert("c".into(), Val::Num(1.0));
+			let err = sub.evaluate_map(Context::new(), &args, true).unwrap_err();
+			assert!(matches!(err.error(), UnknownFunctionParameter(p) if p == "c"));
+		});
+		// Intrinsics, passed to other functions, are called with values
+		assert_eval!("std.filter(std.isString, [1, 'a', null]) == ['a']");
+	}
+
+	#[test]
+	fn named_args_deterministic() {
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		evaluator.set_seed(1);
+		assert_eq!(evaluator.seed(), 1);
+		let func = match evaluator
+			.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), "function(a) a".into())
+			.unwrap()
+		{
+			Val::Func(f) => f,
+			_ => panic!("function expected"),
+		};
+		evaluator.run_in_state(|| {
+			// Every map gets its own hasher keys, so iteration order differs between them
+			for _ in 0..20 {
+				let mut args = HashMap::new();
+				for name in &["z", "y", "a", "x"] {
+					args.insert((*name).into(), Val::Null);
+				}
+				let err = func.evaluate_map(Context::new(), &args, true).unwrap_err();
+				assert!(matches!(err.error(), UnknownFunctionParameter(p) if p == "x"));
+			}
+		});
+	}
+
+	#[test]
+	fn native_typed_args() {
+		use super::native::{NativeArgs, NativeCallback};
+		use crate::ValType;
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		evaluator.add_native(
+			"repeat".into(),
+			Rc::new(NativeCallback::new(
+				ParamsDesc(Rc::new(vec![
+					Param("n".into(), None),
+					Param("s".into(), None),
+				])),
+				|args| {
+					let n: f64 = args.get_typed(0)?;
+					let s: String = args.get_typed(1)?;
+					Ok(Val::Str(s.repeat(n as usize).into()))
+				},
+			)),
+		);
+		let eval = |code: &str| {
+			evaluator.evaluate_snippet_raw(Rc::new(PathBuf::from("raw.jsonnet")), code.into())
+		};
+		assert!(primitive_equals(
+			&eval("std.native('repeat')(3, 'ab')").unwrap(),
+			&Val::Str("ababab".into())
+		)
+		.unwrap());
+		let err = eval("std.native('repeat')('3', 'ab')").unwrap_err();
+		assert!(matches!(err.error(), TypeMismatch("native argument", expected, ValType::Str)
+			if expected == &vec![ValType::Num]));
+		let err = eval("std.native('repeat')(3, [])").unwrap_err();
+		assert!(matches!(err.error(), TypeMismatch("native argument", expected, ValType::Arr)
+			if expected == &vec![ValType::Str]));
+		evaluator.run_in_state(|| {
+			let args = [Val::Null];
+			assert!(args.get_typed::<Option<f64>>(0).unwrap().is_none());
+			let err = args.get_typed::<f64>(1).unwrap_err();
+			assert!(
+				matches!(err.error(), RuntimeError(e) if &**e == "native argument 1 is not passed")
+			);
+		});
+	}
+	#[test]
+	fn native_error_location() {
+		use super::native::NativeCallback;
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		evaluator.add_native(
+			"fail".into(),
+			Rc::new(NativeCallback::new(
+				ParamsDesc(Rc::new(vec![Param("a".into(), None)])),
+				|_| Err(RuntimeError("bad input".into()).into()),
+			)),
+		);
+		let err = |code: &str| {
+			let err = evaluator
+				.evaluate_snippet_raw(Rc::new(PathBuf::from("native.jsonnet")), code.into())
+				.unwrap_err();
+			assert!(matches!(err.error(), RuntimeError(e) if &**e == "bad input"));
+			// Call frame is not duplicated
+			let call_frames = err.trace().0.iter();
+			assert_eq!(
+				call_frames
+					.filter(|f| f.desc == "function <native.fail> call")
+					.count(),
+				1
+			);
+			evaluator.stringify_err(&err)
+		};
+		for code in [
+			"local f = std.native('fail');\nf(1)\n",
+			"local f = std.native('fail');\nf(1) tailstrict\n",
+		]
+		.iter()
+		{
+			let message = err(code);
+			assert!(message.contains("bad input"), "{}", message);
+			assert!(message.contains("native.jsonnet:2:"), "{}", message);
+			assert!(
+				message.contains("function <native.fail> call"),
+				"{}",
+				message
+			);
+		}
+	}
+
+
+	#[test]
+	fn native_timeout() {
+		use super::native::NativeCallback;
+		use std::time::Duration;
+		let evaluator = EvaluationState::default();
+
+		evaluator.with_stdlib();
+		evaluator.add_native(
+			"native_sleep".into(),
+			Rc::new(NativeCallback::new(ParamsDesc(Rc::new(vec![])), |_| {
+				std::thread::sleep(Duration::from_millis(50));
+				Ok(Val::Null)
+			})),
+		);
+		evaluator.set_native_timeout(Some(Duration::from_millis(5)));
+		let err = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("test.jsonnet")),
+				"std.native(\"native_sleep\")()".into(),
+			)
+			.unwrap_err();
+		assert!(
+			matches!(err.error(), RuntimeError(e) if &**e == "native function timed out")
+		);
+
+		evaluator.set_native_timeout(Some(Duration::from_secs(60)));
+		evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("test.jsonnet")),
+				"std.native(\"native_sleep\")()".into(),
+			)
+			.unwrap();
+
+		// Cooperative native stops on its own once deadline passes, instead of running to completion
+		evaluator.add_native(
+			"native_wait".into(),
+			Rc::new(NativeCallback::new_with_deadline(
+				ParamsDesc(Rc::new(vec![])),
+				|_, deadline| {
+					let deadline = match deadline {
+						Some(deadline) => deadline,
+						None => return Ok(Val::Bool(false)),
+					};
+					while std::time::Instant::now() <= deadline {
+						std::thread::sleep(Duration::from_millis(1));
+					}
+					Err(RuntimeError("interrupted".into()).into())
+				},
+			)),
+		);
+		evaluator.set_native_timeout(Some(Duration::from_millis(5)));
+		let err = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("test.jsonnet")),
+				"std.native(\"native_wait\")()".into(),
+			)
+			.unwrap_err();
+		assert!(
+			matches!(err.error(), RuntimeError(e) if &**e == "native function timed out")
+		);
+		evaluator.set_native_timeout(None);
+		let val = evaluator
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("test.jsonnet")),
+				"std.native(\"native_wait\")()".into(),
+			)
+			.unwrap();
+		assert!(matches!(val, Val::Bool(false)));
+	}
+
+	#[test]
+	fn lazy_val_deadline() {
+		use std::time::{Duration, Instant};
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		let state = evaluator.clone();
+		let thunk = lazy_val!(move || {
+			state.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("slow.jsonnet")),
+				"std.foldl(function(a, x) a + x, std.range(0, 20000), 0)".into(),
+			)
+		});
+		evaluator.run_in_state(|| {
+			let err = thunk
+				.evaluate_with_deadline(Instant::now() + Duration::from_millis(1))
+				.unwrap_err();
+			assert!(matches!(err.error(), DeadlineExceeded));
+			// Timeout is not cached, and deadline is not kept after the call
+			let sum = Val::Num(200_010_000.0);
+			assert!(primitive_equals(&thunk.evaluate().unwrap(), &sum).unwrap());
+			let forced = thunk
+				.evaluate_with_deadline(Instant::now() - Duration::from_secs(1))
+				.unwrap();
+			assert!(primitive_equals(&forced, &sum).unwrap());
+		});
+	}
+
+	#[test]
+	fn sandbox() {
+		use super::{native::NativeCallback, SandboxSettings};
+		let evaluator = EvaluationState::default();
+		evaluator.with_stdlib();
+		evaluator.set_import_resolver(Box::new(TestImportResolver("1".into())));
+		evaluator.add_native(
+			"native_one".into(),
+			Rc::new(NativeCallback::new(ParamsDesc(Rc::new(vec![])), |_| {
+				Ok(Val::Num(1.0))
+			})),
+		);
+		let eval = |code: &str| {
+			evaluator.evaluate_snippet_raw(Rc::new(PathBuf::from("test.jsonnet")), code.into())
+		};
+		assert!(eval("import 'a.jsonnet'").is_ok());
+
+		evaluator.set_sandbox(Some(SandboxSettings::default()));
+		for code in &["import 'a.jsonnet'", "importstr 'a.txt'"] {
+			let err = eval(code).unwrap_err();
+			assert!(
+				matches!(err.error(), RuntimeError(e) if &**e == "imports disabled in sandbox")
+			);
+		}
+		assert!(eval("std.assertEqual(std.foldl(function(a, b) a + b, [1, 2, 3], 0), 6)").is_ok());
+		assert!(eval("std.native('native_one')()").is_ok());
+
+		evaluator.set_sandbox(Some(SandboxSettings {
+			disable_natives: true,
+		}));
+		let err = eval("std.native('native_one')()").unwrap_err();
+		assert!(
+			matches!(err.error(), RuntimeError(e) if &**e == "native functions disabled in sandbox")
+		);
+	}
+
+	#[test]
+	fn constant_intrinsic() -> crate::error::Result<()> {
+		assert_eval!(
+			"local std2 = std; local std = std2 { primitiveEquals(a, b):: false }; 1 == 1"
+		);
+		Ok(())
+	}
+
+	struct TestImportResolver(Rc<str>);
+	impl crate::import::ImportResolver for TestImportResolver {
+		fn resolve_file(&self, _: &PathBuf, _: &PathBuf) -> crate::error::Result<Rc<PathBuf>> {
+			Ok(Rc::new(PathBuf::from("/test")))
+		}
+
+		fn load_file_contents(&self, _: &PathBuf) -> crate::error::Result<Rc<str>> {
+			Ok(self.0.clone())
+		}
+
+		unsafe fn as_any(&self) -> &dyn std::any::Any {
+			panic!()
+		}
+	}
+
+	struct CountingImportResolver {
+		files: HashMap<PathBuf, Rc<str>>,
+		loads: RefCell<HashMap<PathBuf, usize>>,
+	}
+	impl crate::import::ImportResolver for CountingImportResolver {
+		fn resolve_file(&self, _: &PathBuf, path: &PathBuf) -> crate::error::Result<Rc<PathBuf>> {
+			Ok(Rc::new(path.clone()))
+		}
+
+		fn load_file_contents(&self, resolved: &PathBuf) -> crate::error::Result<Rc<str>> {
+			*self
+				.loads
+				.borrow_mut()
+				.entry(resolved.clone())
+				.or_default() += 1;
+			Ok(self.files[resolved].clone())
+		}
+
+		unsafe fn as_any(&self) -> &dyn std::any::Any {
+			self
+		}
+	}
+
+	#[test]
+	fn batch_evaluation_shares_imports() {
+		let mut files = HashMap::new();
+		files.insert(PathBuf::from("/lib.libsonnet"), "{ value: 2 }".into());
+		files.insert(
+			PathBuf::from("/a.jsonnet"),
+			"(import '/lib.libsonnet').value + 1".into(),
+		);
+		files.insert(
+			PathBuf::from("/b.jsonnet"),
+			"(import '/lib.libsonnet').value * 3".into(),
+		);
+		files.insert(PathBuf::from("/c.jsonnet"), "error 'c'".into());
+
+		let state = EvaluationState::default();
+		state.with_stdlib();
+		state.set_import_resolver(Box::new(CountingImportResolver {
+			files,
+			loads: RefCell::new(HashMap::new()),
+		}));
+		let names = vec![
+			PathBuf::from("/a.jsonnet"),
+			PathBuf::from("/b.jsonnet"),
+			PathBuf::from("/c.jsonnet"),
+		];
+		let results = state.evaluate_files_raw(&names);
+		assert_eq!(results.len(), 3);
+		assert!(matches!(results[&names[0]], Ok(Val::Num(n)) if n == 3.0));
+		assert!(matches!(results[&names[1]], Ok(Val::Num(n)) if n == 6.0));
+		assert!(results[&names[2]].is_err());
+
+		let resolver = state.import_resolver();
+		let resolver = unsafe { resolver.as_any() }
+			.downcast_ref::<CountingImportResolver>()
+			.unwrap();
+		assert_eq!(resolver.loads.borrow()[&PathBuf::from("/lib.libsonnet")], 1);
 	}
 
 	#[test]
@@ -1,4 +1,4 @@
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::{borrow::Borrow, hash::Hash, rc::Rc};
 
 #[derive(Default, Debug)]
@@ -34,6 +34,13 @@ impl<K: Hash + Eq, V> LayeredHashMap<K, V> {
 			.get(key)
 			.or_else(|| self.0.parent.as_ref().and_then(|p| p.get(key)))
 	}
+
+	/// Keys of all layers, keys defined in multiple layers are returned once
+	pub fn keys(&self) -> FxHashSet<&K> {
+		let mut out = self.0.parent.as_ref().map(|p| p.keys()).unwrap_or_default();
+		out.extend(self.0.current.keys());
+		out
+	}
 }
 
 impl<K: Hash, V> Clone for LayeredHashMap<K, V> {
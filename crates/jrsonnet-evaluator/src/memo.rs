@@ -0,0 +1,102 @@
+//! Memoization of pure functions, for hosts which repeatedly call expensive jsonnet functions
+use crate::{error::Result, Context, FuncVal, Val};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/// Function wrapper, caching results by [hash key](Val::hash_key) of evaluated arguments.
+/// Function should be pure, as its body is only executed once per distinct argument set
+pub struct MemoizedFunc {
+	func: Rc<FuncVal>,
+	cache: RefCell<HashMap<Rc<str>, Val>>,
+}
+impl MemoizedFunc {
+	pub fn new(func: Rc<FuncVal>) -> Self {
+		Self {
+			func,
+			cache: RefCell::new(HashMap::new()),
+		}
+	}
+
+	/// Calls function with positional arguments, failed calls are not cached.
+	/// Fails if arguments contain functions, as they have no hash key
+	pub fn call(&self, args: &[Val]) -> Result<Val> {
+		let key = Val::Arr(args.to_vec().into()).hash_key()?;
+		if let Some(cached) = self.cache.borrow().get(&key) {
+			return Ok(cached.clone());
+		}
+		let result = self
+			.func
+			.evaluate_values(Context::new(), args)?
+			.unwrap_if_lazy()?;
+		self.cache.borrow_mut().insert(key, result.clone());
+		Ok(result)
+	}
+
+	/// Number of cached results
+	pub fn cached(&self) -> usize {
+		self.cache.borrow().len()
+	}
+	pub fn clear(&self) {
+		self.cache.borrow_mut().clear();
+	}
+}
+
+#[cfg(test)]
+pub mod tests {
+	use super::*;
+	use crate::{error::Error::*, native::NativeCallback, primitive_equals, EvaluationState};
+	use jrsonnet_parser::{Param, ParamsDesc};
+	use std::{cell::Cell, path::PathBuf};
+
+	#[test]
+	fn calls_once_per_arguments() {
+		let state = EvaluationState::default();
+		state.with_stdlib();
+		let calls = Rc::new(Cell::new(0));
+		let counter = calls.clone();
+		state.add_native(
+			"count".into(),
+			Rc::new(NativeCallback::new(
+				ParamsDesc(Rc::new(vec![Param("v".into(), None)])),
+				move |args| {
+					counter.set(counter.get() + 1);
+					Ok(args[0].clone())
+				},
+			)),
+		);
+		let func = match state
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"function(a, b=0) std.native('count')(std.length(a) + b)".into(),
+			)
+			.unwrap()
+		{
+			Val::Func(f) => f,
+			_ => panic!("function expected"),
+		};
+		let memo = MemoizedFunc::new(func);
+		state.run_in_state(|| {
+			let arr = |items: Vec<Val>| Val::Arr(items.into());
+			for _ in 0..3 {
+				let result = memo.call(&[arr(vec![Val::Null, Val::Null])]).unwrap();
+				assert!(primitive_equals(&result, &Val::Num(2.0)).unwrap());
+				let result = memo
+					.call(&[arr(vec![Val::Num(0.0)]), Val::Num(5.0)])
+					.unwrap();
+				assert!(primitive_equals(&result, &Val::Num(6.0)).unwrap());
+				// Equal values share cache entry
+				memo.call(&[arr(vec![Val::Num(-0.0)]), Val::Num(5.0)])
+					.unwrap();
+			}
+			assert_eq!(calls.get(), 2);
+			assert_eq!(memo.cached(), 2);
+
+			let err = memo.call(&[Val::Num(1.0)]).unwrap_err();
+			assert!(matches!(err.error(), TypeMismatch(..)));
+			assert_eq!(memo.cached(), 2);
+
+			memo.clear();
+			memo.call(&[arr(vec![])]).unwrap();
+			assert_eq!(calls.get(), 3);
+		});
+	}
+}
@@ -1,20 +1,44 @@
-use crate::{error::Result, Val};
+use crate::{
+	error::{Error::*, Result},
+	throw, LazyVal, ObjValue, Val, ValType,
+};
 use jrsonnet_parser::ParamsDesc;
-use std::fmt::Debug;
+use std::{fmt::Debug, rc::Rc, time::Instant};
 
 pub struct NativeCallback {
 	pub params: ParamsDesc,
-	handler: Box<dyn Fn(&[Val]) -> Result<Val>>,
+	handler: Box<dyn Fn(&[Val], Option<Instant>) -> Result<Val>>,
 }
 impl NativeCallback {
 	pub fn new(params: ParamsDesc, handler: impl Fn(&[Val]) -> Result<Val> + 'static) -> Self {
+		Self::new_with_deadline(params, move |args, _| handler(args))
+	}
+	/// Handler also receives deadline of the call, set by
+	/// [`crate::EvaluationState::set_native_timeout`]. Natives are synchronous, so long-running
+	/// ones should check it periodically, and return early once it passes
+	pub fn new_with_deadline(
+		params: ParamsDesc,
+		handler: impl Fn(&[Val], Option<Instant>) -> Result<Val> + 'static,
+	) -> Self {
 		Self {
 			params,
 			handler: Box::new(handler),
 		}
 	}
+	/// Returned value is only evaluated when used, for natives producing large values,
+	/// objects with lazily computed fields can be returned with [`crate::ObjValue::from_lazy_values`].
+	/// Native timeout doesn't cover deferred computations
+	pub fn new_lazy(
+		params: ParamsDesc,
+		handler: impl Fn(&[Val]) -> Result<LazyVal> + 'static,
+	) -> Self {
+		Self::new(params, move |args| Ok(Val::Lazy(handler(args)?)))
+	}
 	pub fn call(&self, args: &[Val]) -> Result<Val> {
-		(self.handler)(args)
+		(self.handler)(args, None)
+	}
+	pub fn call_with_deadline(&self, args: &[Val], deadline: Option<Instant>) -> Result<Val> {
+		(self.handler)(args, deadline)
 	}
 }
 impl Debug for NativeCallback {
@@ -22,3 +46,84 @@ impl Debug for NativeCallback {
 		f.debug_struct("NativeCallback").finish()
 	}
 }
+
+/// Conversion of jsonnet values into rust types, used to extract native callback arguments
+pub trait FromVal: Sized {
+	fn from_val(val: &Val) -> Result<Self>;
+}
+impl FromVal for Val {
+	fn from_val(val: &Val) -> Result<Self> {
+		val.unwrap_if_lazy()
+	}
+}
+impl FromVal for f64 {
+	fn from_val(val: &Val) -> Result<Self> {
+		val.clone().try_cast_num("native argument")
+	}
+}
+impl FromVal for bool {
+	fn from_val(val: &Val) -> Result<Self> {
+		val.clone().try_cast_bool("native argument")
+	}
+}
+impl FromVal for Rc<str> {
+	fn from_val(val: &Val) -> Result<Self> {
+		val.clone().try_cast_str("native argument")
+	}
+}
+impl FromVal for String {
+	fn from_val(val: &Val) -> Result<Self> {
+		Ok(Rc::<str>::from_val(val)?.to_string())
+	}
+}
+impl FromVal for Vec<Val> {
+	fn from_val(val: &Val) -> Result<Self> {
+		match val.unwrap_if_lazy()? {
+			Val::Arr(arr) => Ok(arr.evaluated()?.to_vec()),
+			v => throw!(TypeMismatch(
+				"native argument",
+				vec![ValType::Arr],
+				v.value_type()?
+			)),
+		}
+	}
+}
+impl FromVal for ObjValue {
+	fn from_val(val: &Val) -> Result<Self> {
+		match val.unwrap_if_lazy()? {
+			Val::Obj(obj) => Ok(obj),
+			v => throw!(TypeMismatch(
+				"native argument",
+				vec![ValType::Obj],
+				v.value_type()?
+			)),
+		}
+	}
+}
+/// `null` is converted to `None`
+impl<T: FromVal> FromVal for Option<T> {
+	fn from_val(val: &Val) -> Result<Self> {
+		match val.unwrap_if_lazy()? {
+			Val::Null => Ok(None),
+			v => Ok(Some(T::from_val(&v)?)),
+		}
+	}
+}
+
+/// Typed access to native callback arguments:
+/// ```ignore
+/// let x: f64 = args.get_typed(0)?;
+/// ```
+pub trait NativeArgs {
+	fn get_typed<T: FromVal>(&self, index: usize) -> Result<T>;
+}
+impl NativeArgs for [Val] {
+	fn get_typed<T: FromVal>(&self, index: usize) -> Result<T> {
+		match self.get(index) {
+			Some(val) => T::from_val(val),
+			None => throw!(RuntimeError(
+				format!("native argument {} is not passed", index).into()
+			)),
+		}
+	}
+}
@@ -1,7 +1,14 @@
-use crate::{evaluate_add_op, LazyBinding, Result, Val};
+use crate::{
+	builtin::sort::natural_cmp, evaluate_add_op, rc_fn_helper, LazyBinding, LazyVal, Result, Val,
+};
 use indexmap::IndexMap;
 use jrsonnet_parser::{ExprLocation, Visibility};
-use std::{cell::RefCell, collections::HashMap, fmt::Debug, rc::Rc};
+use std::{
+	cell::{Cell, RefCell},
+	collections::HashMap,
+	fmt::Debug,
+	rc::Rc,
+};
 
 #[derive(Debug)]
 pub struct ObjMember {
@@ -11,12 +18,35 @@ pub struct ObjMember {
 	pub location: Option<ExprLocation>,
 }
 
+rc_fn_helper!(
+	ObjAssertion,
+	obj_assertion,
+	dyn Fn(ObjValue, Option<ObjValue>) -> Result<()>
+);
+
+/// Order, in which object fields are listed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FieldOrder {
+	/// Sorted by name, as jsonnet spec requires
+	#[default]
+	Sorted,
+	/// In order of definition, fields of super object go first,
+	/// overridden fields keep position of the first definition, see [`ObjValue::merge`]
+	AsDefined,
+	/// Sorted by name, numbers embedded in names are compared by value,
+	/// so `item2` goes before `item10`
+	Natural,
+}
+
 // Field => This
 type CacheKey = (Rc<str>, usize);
 #[derive(Debug)]
 pub struct ObjValueInternals {
 	super_obj: Option<ObjValue>,
-	this_entries: Rc<HashMap<Rc<str>, ObjMember>>,
+	this_entries: Rc<IndexMap<Rc<str>, ObjMember>>,
+	assertions: Rc<Vec<ObjAssertion>>,
+	/// Set once assertions were checked with this object as `self`
+	assertions_checked: Cell<bool>,
 	value_cache: RefCell<HashMap<CacheKey, Option<Val>>>,
 }
 #[derive(Clone)]
@@ -47,21 +77,69 @@ impl Debug for ObjValue {
 }
 
 impl ObjValue {
-	pub fn new(super_obj: Option<Self>, this_entries: Rc<HashMap<Rc<str>, ObjMember>>) -> Self {
+	pub fn new(super_obj: Option<Self>, this_entries: Rc<IndexMap<Rc<str>, ObjMember>>) -> Self {
+		Self::new_with_assertions(super_obj, this_entries, Rc::new(Vec::new()))
+	}
+	pub fn new_with_assertions(
+		super_obj: Option<Self>,
+		this_entries: Rc<IndexMap<Rc<str>, ObjMember>>,
+		assertions: Rc<Vec<ObjAssertion>>,
+	) -> Self {
 		Self(Rc::new(ObjValueInternals {
 			super_obj,
 			this_entries,
+			assertions,
+			assertions_checked: Cell::new(false),
 			value_cache: RefCell::new(HashMap::new()),
 		}))
 	}
 	pub fn new_empty() -> Self {
-		Self::new(None, Rc::new(HashMap::new()))
+		Self::new(None, Rc::new(IndexMap::new()))
+	}
+	/// Creates object, consisting of visible fields with already evaluated values
+	pub fn from_values(values: impl IntoIterator<Item = (Rc<str>, Val)>) -> Self {
+		Self::from_lazy_values(
+			values
+				.into_iter()
+				.map(|(k, v)| (k, LazyVal::new_resolved(v))),
+		)
+	}
+	/// Same as [`ObjValue::from_values`], but fields are evaluated on first access
+	pub fn from_lazy_values(values: impl IntoIterator<Item = (Rc<str>, LazyVal)>) -> Self {
+		let entries = values
+			.into_iter()
+			.map(|(k, v)| {
+				(
+					k,
+					ObjMember {
+						add: false,
+						visibility: Visibility::Normal,
+						invoke: LazyBinding::Bound(v),
+						location: None,
+					},
+				)
+			})
+			.collect();
+		Self::new(None, Rc::new(entries))
+	}
+	/// Same as `self + other` in jsonnet.
+	///
+	/// With [`FieldOrder::AsDefined`], fields of `self` are listed first, in their order,
+	/// followed by fields only defined in `other`. Fields of `self` overridden by `other`
+	/// (including changes of visibility) keep their position
+	pub fn merge(&self, other: &Self) -> Self {
+		other.with_super(self.clone())
 	}
 	pub fn with_super(&self, super_obj: Self) -> Self {
-		match &self.0.super_obj {
-			None => Self::new(Some(super_obj), self.0.this_entries.clone()),
-			Some(v) => Self::new(Some(v.with_super(super_obj)), self.0.this_entries.clone()),
-		}
+		let super_obj = match &self.0.super_obj {
+			None => super_obj,
+			Some(v) => v.with_super(super_obj),
+		};
+		Self::new_with_assertions(
+			Some(super_obj),
+			self.0.this_entries.clone(),
+			self.0.assertions.clone(),
+		)
 	}
 	pub fn enum_fields(&self, handler: &impl Fn(&Rc<str>, &Visibility)) {
 		if let Some(s) = &self.0.super_obj {
@@ -91,19 +169,90 @@ impl ObjValue {
 		});
 		Rc::try_unwrap(out).unwrap().into_inner()
 	}
+	/// All fields, including hidden ones, sorted by name
+	pub fn fields(&self) -> Vec<Rc<str>> {
+		self.fields_ex(true, FieldOrder::Sorted)
+	}
 	pub fn visible_fields(&self) -> Vec<Rc<str>> {
-		let mut visible_fields: Vec<_> = self
+		self.fields_ex(false, FieldOrder::Sorted)
+	}
+	pub fn fields_ex(&self, include_hidden: bool, order: FieldOrder) -> Vec<Rc<str>> {
+		let mut fields: Vec<_> = self
 			.fields_visibility()
 			.into_iter()
-			.filter(|(_k, v)| *v)
+			.filter(|(_k, v)| include_hidden || *v)
 			.map(|(k, _)| k)
 			.collect();
-		visible_fields.sort();
-		visible_fields
+		match order {
+			FieldOrder::Sorted => fields.sort(),
+			FieldOrder::AsDefined => {}
+			FieldOrder::Natural => fields.sort_by(|a, b| natural_cmp(a, b)),
+		}
+		fields
 	}
 	pub fn get(&self, key: Rc<str>) -> Result<Option<Val>> {
+		self.run_assertions()?;
 		Ok(self.get_raw(key, self)?)
 	}
+	/// Checks assertions of this object and its super objects, only done once per object
+	pub fn run_assertions(&self) -> Result<()> {
+		if self.0.assertions_checked.get() {
+			return Ok(());
+		}
+		// Marked as checked beforehand, so assertions are able to access fields of this object
+		self.0.assertions_checked.set(true);
+		let result = self.run_assertions_raw(self);
+		if result.is_err() {
+			self.0.assertions_checked.set(false);
+		}
+		result
+	}
+	fn run_assertions_raw(&self, real_this: &Self) -> Result<()> {
+		for assertion in self.0.assertions.iter() {
+			(assertion.0)(real_this.clone(), self.0.super_obj.clone())?;
+		}
+		if let Some(super_obj) = &self.0.super_obj {
+			super_obj.run_assertions_raw(real_this)?;
+		}
+		Ok(())
+	}
+	/// Returns thunk of field value, which is only evaluated when forced
+	pub fn get_lazy(&self, key: Rc<str>) -> Option<LazyVal> {
+		if !self.has_field(&key) {
+			return None;
+		}
+		let obj = self.clone();
+		Some(LazyVal::new(Box::new(move || {
+			Ok(obj.get(key.clone())?.expect("field exists"))
+		})))
+	}
+	/// Object with only listed visible fields of this object, absent and hidden fields are skipped.
+	/// Values of picked fields are not evaluated
+	pub fn pick(&self, keys: &[&str]) -> Self {
+		let visibility = self.fields_visibility();
+		Self::from_lazy_values(
+			keys.iter()
+				.filter(|key| visibility.get(**key) == Some(&true))
+				.map(|key| {
+					let key: Rc<str> = (*key).into();
+					(key.clone(), self.get_lazy(key).expect("field exists"))
+				}),
+		)
+	}
+	/// Object with visible fields of this object except `key`, which is removed regardless of its
+	/// visibility. Fields are kept in order of [`ObjValue::visible_fields`] and are not evaluated
+	pub fn remove_key(&self, key: &str) -> Self {
+		Self::from_lazy_values(
+			self.visible_fields()
+				.into_iter()
+				.filter(|field| &**field != key)
+				.map(|field| (field.clone(), self.get_lazy(field).expect("field exists"))),
+		)
+	}
+	fn has_field(&self, key: &str) -> bool {
+		self.0.this_entries.contains_key(key)
+			|| matches!(&self.0.super_obj, Some(s) if s.has_field(key))
+	}
 	pub(crate) fn get_raw(&self, key: Rc<str>, real_this: &Self) -> Result<Option<Val>> {
 		let cache_key = (key.clone(), Rc::as_ptr(&real_this.0) as usize);
 
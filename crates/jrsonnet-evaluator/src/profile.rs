@@ -0,0 +1,67 @@
+//! Collects evaluation time of thunks, to find slow parts of configs
+use crate::{EvaluationState, Result, Val, EVAL_STATE};
+use jrsonnet_parser::ExprLocation;
+use std::{
+	cmp::Reverse,
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone)]
+pub struct ThunkProfile {
+	pub location: ExprLocation,
+	/// How many times thunks of this expression were evaluated
+	pub count: usize,
+	/// Time spent in evaluation, excluding time of nested thunks
+	pub self_time: Duration,
+}
+
+#[derive(Default)]
+pub struct ThunkProfiler {
+	/// Time spent in nested thunks, for every thunk being evaluated
+	nested: Vec<Duration>,
+	thunks: HashMap<ExprLocation, ThunkProfile>,
+}
+impl ThunkProfiler {
+	fn enter(&mut self) {
+		self.nested.push(Duration::default());
+	}
+	fn exit(&mut self, location: &ExprLocation, elapsed: Duration) {
+		let nested = self.nested.pop().unwrap_or_default();
+		if let Some(parent) = self.nested.last_mut() {
+			*parent += elapsed;
+		}
+		let profile = self
+			.thunks
+			.entry(location.clone())
+			.or_insert_with(|| ThunkProfile {
+				location: location.clone(),
+				count: 0,
+				self_time: Duration::default(),
+			});
+		profile.count += 1;
+		profile.self_time += elapsed.saturating_sub(nested);
+	}
+	pub fn report(&self, limit: usize) -> Vec<ThunkProfile> {
+		let mut out: Vec<_> = self.thunks.values().cloned().collect();
+		out.sort_by_key(|p| Reverse(p.self_time));
+		out.truncate(limit);
+		out
+	}
+}
+
+fn with_profiler<T>(state: &EvaluationState, f: impl FnOnce(&mut ThunkProfiler) -> T) -> Option<T> {
+	state.data_mut().thunk_profiler.as_mut().map(f)
+}
+
+pub fn profile_thunk(location: &ExprLocation, f: impl FnOnce() -> Result<Val>) -> Result<Val> {
+	let state = EVAL_STATE.with(|s| s.borrow().clone());
+	let state = match state {
+		Some(state) if with_profiler(&state, ThunkProfiler::enter).is_some() => state,
+		_ => return f(),
+	};
+	let started = Instant::now();
+	let result = f();
+	with_profiler(&state, |p| p.exit(location, started.elapsed()));
+	result
+}
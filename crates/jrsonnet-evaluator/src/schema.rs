@@ -0,0 +1,185 @@
+//! Shape validation of evaluated values, for hosts which need to check output of evaluation
+use crate::{error::Result, Val, ValType};
+use std::{
+	fmt::{self, Display},
+	rc::Rc,
+};
+
+#[derive(Debug, Clone)]
+pub enum Schema {
+	/// Any value is accepted
+	Any,
+	/// Value should have specified type, nested values are not checked
+	Type(ValType),
+	/// Value should be array, every element of which matches schema
+	Array(Box<Self>),
+	/// Value should be object with specified fields, other fields are not checked
+	Object(Vec<SchemaField>),
+}
+impl Schema {
+	pub fn array(items: Self) -> Self {
+		Self::Array(Box::new(items))
+	}
+	pub const fn object() -> Self {
+		Self::Object(Vec::new())
+	}
+	/// Adds field to object schema
+	///
+	/// # Panics
+	/// If schema is not [`Schema::Object`]
+	pub fn field(mut self, name: impl Into<Rc<str>>, required: bool, schema: Self) -> Self {
+		match &mut self {
+			Self::Object(fields) => fields.push(SchemaField {
+				name: name.into(),
+				required,
+				schema,
+			}),
+			_ => panic!("fields can only be added to object schema"),
+		}
+		self
+	}
+	pub fn required(self, name: impl Into<Rc<str>>, schema: Self) -> Self {
+		self.field(name, true, schema)
+	}
+	pub fn optional(self, name: impl Into<Rc<str>>, schema: Self) -> Self {
+		self.field(name, false, schema)
+	}
+
+	/// Checks value against this schema, collecting all found violations.
+	/// Errors are only returned if value evaluation fails
+	pub fn validate(&self, val: &Val) -> Result<Vec<Violation>> {
+		let mut out = Vec::new();
+		self.validate_at(val, &mut String::from("$"), &mut out)?;
+		Ok(out)
+	}
+
+	fn validate_at(&self, val: &Val, path: &mut String, out: &mut Vec<Violation>) -> Result<()> {
+		let expected = match self {
+			Self::Any => return Ok(()),
+			Self::Type(ty) => *ty,
+			Self::Array(_) => ValType::Arr,
+			Self::Object(_) => ValType::Obj,
+		};
+		let val = val.unwrap_if_lazy()?;
+		let got = val.value_type()?;
+		if got != expected {
+			out.push(Violation {
+				path: path.clone(),
+				kind: ViolationKind::TypeMismatch { expected, got },
+			});
+			return Ok(());
+		}
+		let prefix_len = path.len();
+		match (self, &val) {
+			(Self::Array(items), Val::Arr(arr)) => {
+				for (i, item) in arr.iter().enumerate() {
+					path.push_str(&format!("[{}]", i));
+					items.validate_at(&item?, path, out)?;
+					path.truncate(prefix_len);
+				}
+			}
+			(Self::Object(fields), Val::Obj(obj)) => {
+				for field in fields {
+					path.push('.');
+					path.push_str(&field.name);
+					match obj.get(field.name.clone())? {
+						Some(value) => field.schema.validate_at(&value, path, out)?,
+						None if field.required => out.push(Violation {
+							path: path.clone(),
+							kind: ViolationKind::MissingField,
+						}),
+						None => {}
+					}
+					path.truncate(prefix_len);
+				}
+			}
+			_ => {}
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SchemaField {
+	pub name: Rc<str>,
+	pub required: bool,
+	pub schema: Schema,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViolationKind {
+	MissingField,
+	TypeMismatch { expected: ValType, got: ValType },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+	/// Path to offending value, i.e `$.a[0].b`
+	pub path: String,
+	pub kind: ViolationKind,
+}
+impl Display for Violation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.kind {
+			ViolationKind::MissingField => write!(f, "{}: missing required field", self.path),
+			ViolationKind::TypeMismatch { expected, got } => {
+				write!(f, "{}: expected {}, got {}", self.path, expected, got)
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+pub mod tests {
+	use super::*;
+	use crate::EvaluationState;
+	use std::path::PathBuf;
+
+	#[test]
+	fn violations() {
+		let state = EvaluationState::default();
+		state.with_stdlib();
+		let val = state
+			.evaluate_snippet_raw(
+				Rc::new(PathBuf::from("raw.jsonnet")),
+				"{a: {name: 1}, items: [{name: 'x'}, {}], extra: null}".into(),
+			)
+			.unwrap();
+		let named = Schema::object().required("name", Schema::Type(ValType::Str));
+		let schema = Schema::object()
+			.required("a", named.clone())
+			.required("items", Schema::array(named))
+			.optional("tags", Schema::array(Schema::Type(ValType::Str)))
+			.required("extra", Schema::Any);
+		let violations = state.run_in_state(|| schema.validate(&val)).unwrap();
+		assert_eq!(
+			violations,
+			vec![
+				Violation {
+					path: "$.a.name".into(),
+					kind: ViolationKind::TypeMismatch {
+						expected: ValType::Str,
+						got: ValType::Num,
+					},
+				},
+				Violation {
+					path: "$.items[1].name".into(),
+					kind: ViolationKind::MissingField,
+				},
+			]
+		);
+		assert_eq!(
+			violations[0].to_string(),
+			"$.a.name: expected string, got number"
+		);
+		assert_eq!(
+			violations[1].to_string(),
+			"$.items[1].name: missing required field"
+		);
+
+		let violations = state
+			.run_in_state(|| Schema::array(Schema::Any).validate(&val))
+			.unwrap();
+		assert_eq!(violations[0].path, "$");
+	}
+}
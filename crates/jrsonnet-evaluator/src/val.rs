@@ -1,31 +1,80 @@
 use crate::{
 	builtin::{
 		call_builtin,
-		manifest::{manifest_json_ex, ManifestJsonOptions, ManifestType},
+		manifest::{
+			escape_string_json, manifest_csv_ex, manifest_debug_json, manifest_ini_ex,
+			manifest_json_ex, manifest_json_ex_to, manifest_toml_ex, manifest_yaml_ex,
+			IniArrayMode, ManifestCsvOptions, ManifestIniOptions, ManifestJsonOptions,
+			ManifestTomlOptions, ManifestType, ManifestYamlOptions, QuoteStrings,
+		},
 	},
-	error::Error::*,
+	error::{Error, Error::*, StackTraceElement},
 	evaluate,
-	function::{parse_function_call, parse_function_call_map, place_args},
+	function::{
+		parse_function_call, parse_function_call_map, parse_function_call_slots, place_args,
+		ParamSlots,
+	},
 	native::NativeCallback,
-	throw, with_state, Context, ObjValue, Result,
+	profile::profile_thunk,
+	throw, with_state, Context, LocError, ObjValue, Result,
 };
-use jrsonnet_parser::{el, Arg, ArgsDesc, Expr, ExprLocation, LiteralType, LocExpr, ParamsDesc};
+#[cfg(not(feature = "faster"))]
+use jrsonnet_parser::LiteralType;
+use indexmap::IndexMap;
+use jrsonnet_parser::{el, Arg, ArgsDesc, BinaryOpType, Expr, ExprLocation, LocExpr, ParamsDesc};
+use rustc_hash::FxHashMap;
 use std::{
-	cell::RefCell,
-	collections::HashMap,
+	cell::{OnceCell, RefCell},
+	cmp::Ordering,
+	collections::{HashMap, HashSet},
+	convert::TryFrom,
 	fmt::{Debug, Display},
+	hash::{Hash, Hasher},
+	ops::Deref,
 	rc::Rc,
+	time::Instant,
 };
 
+#[cfg(not(feature = "faster"))]
+thread_local! {
+	/// Calls of std manifestification functions, see [`Val::call_std_manifest`]
+	///
+	/// Exprs are not bound to evaluation state, so they are safely shared between states of one thread
+	static STD_MANIFEST_CALLS: RefCell<HashMap<(&'static str, usize), LocExpr>> =
+		RefCell::new(HashMap::new());
+}
+
 enum LazyValInternals {
 	Computed(Val),
-	Waiting(Box<dyn Fn() -> Result<Val>>),
+	/// Thunk failed, error is returned for every next evaluation without running it again.
+	/// Errors, which depend on where or when thunk was forced, are not cached, see
+	/// [`is_transient_error`]
+	Failed(LocError),
+	/// Location is only used for profiling
+	Waiting(Box<dyn Fn() -> Result<Val>>, Option<ExprLocation>),
+}
+/// Returned by native call, which takes longer than [`crate::EvaluationSettings::native_timeout`]
+const NATIVE_TIMEOUT_ERROR: &str = "native function timed out";
+
+/// Errors, caused by evaluation limits, rather than by evaluated code itself
+fn is_transient_error(error: &Error) -> bool {
+	match error {
+		StackOverflow | DeadlineExceeded => true,
+		RuntimeError(e) => &**e == NATIVE_TIMEOUT_ERROR,
+		_ => false,
+	}
 }
+
 #[derive(Clone)]
 pub struct LazyVal(Rc<RefCell<LazyValInternals>>);
 impl LazyVal {
 	pub fn new(f: Box<dyn Fn() -> Result<Val>>) -> Self {
-		Self(Rc::new(RefCell::new(LazyValInternals::Waiting(f))))
+		Self(Rc::new(RefCell::new(LazyValInternals::Waiting(f, None))))
+	}
+	/// Same as [`LazyVal::new`], but evaluation time is attributed to expression at `location`
+	/// when thunk profiling is enabled
+	pub fn new_located(f: Box<dyn Fn() -> Result<Val>>, location: Option<ExprLocation>) -> Self {
+		Self(Rc::new(RefCell::new(LazyValInternals::Waiting(f, location))))
 	}
 	pub fn new_resolved(val: Val) -> Self {
 		Self(Rc::new(RefCell::new(LazyValInternals::Computed(val))))
@@ -33,10 +82,23 @@ impl LazyVal {
 	pub fn evaluate(&self) -> Result<Val> {
 		let new_value = match &*self.0.borrow() {
 			LazyValInternals::Computed(v) => return Ok(v.clone()),
-			LazyValInternals::Waiting(f) => f()?,
+			LazyValInternals::Failed(e) => return Err(e.clone()),
+			LazyValInternals::Waiting(f, None) => f(),
+			LazyValInternals::Waiting(f, Some(location)) => profile_thunk(location, f),
 		};
-		*self.0.borrow_mut() = LazyValInternals::Computed(new_value.clone());
-		Ok(new_value)
+		*self.0.borrow_mut() = match &new_value {
+			Ok(v) => LazyValInternals::Computed(v.clone()),
+			// Thunk may be forced again from shallower stack, or with a bigger time budget
+			Err(e) if is_transient_error(e.error()) => return new_value,
+			Err(e) => LazyValInternals::Failed(e.clone()),
+		};
+		new_value
+	}
+	/// Forces this value, failing with [`DeadlineExceeded`] if computation takes past `deadline`
+	///
+	/// Failure is not cached, so value can be forced again later
+	pub fn evaluate_with_deadline(&self, deadline: Instant) -> Result<Val> {
+		with_state(|s| s.clone()).run_with_deadline(deadline, || self.evaluate())
 	}
 }
 
@@ -69,6 +131,7 @@ pub struct FuncDesc {
 	pub ctx: Context,
 	pub params: ParamsDesc,
 	pub body: LocExpr,
+	pub param_slots: ParamSlots,
 }
 
 #[derive(Debug)]
@@ -111,27 +174,71 @@ impl FuncVal {
 	) -> Result<Val> {
 		match self {
 			Self::Normal(func) => {
-				let ctx = parse_function_call(
+				let ctx = parse_function_call_slots(
 					call_ctx,
 					Some(func.ctx.clone()),
 					&func.params,
+					Some(&func.param_slots),
 					args,
 					tailstrict,
 				)?;
 				evaluate(ctx, &func.body)
 			}
 			Self::Intrinsic(name) => call_builtin(call_ctx, loc, name, args),
-			Self::NativeExt(_name, handler) => {
+			Self::NativeExt(name, handler) => {
 				let args = parse_function_call(call_ctx, None, &handler.params, args, true)?;
-				let mut out_args = Vec::with_capacity(handler.params.len());
-				for p in handler.params.0.iter() {
-					out_args.push(args.binding(p.0.clone())?.evaluate()?);
+				match (Self::call_native(handler, &args), loc) {
+					// Non-tailstrict calls get this frame from `evaluate_apply`, which pushes call frame
+					(Err(mut err), Some(loc)) if tailstrict => {
+						err.trace_mut().0.push(StackTraceElement {
+							location: loc.clone(),
+							desc: format!("function <native.{}> call", name),
+						});
+						Err(err)
+					}
+					(result, _) => result,
 				}
-				Ok(handler.call(&out_args)?)
 			}
 		}
 	}
 
+	/// Calls native with arguments, bound in `args` context
+	fn call_native(handler: &NativeCallback, args: &Context) -> Result<Val> {
+		let mut out_args = Vec::with_capacity(handler.params.len());
+		for p in handler.params.0.iter() {
+			out_args.push(args.binding(p.0.clone())?.evaluate()?);
+		}
+		let deadline = with_state(|s| s.native_timeout()).map(|timeout| Instant::now() + timeout);
+		let result = handler.call_with_deadline(&out_args, deadline);
+		// Result of native, which returned past its deadline, i.e interruption error, is replaced
+		if matches!(deadline, Some(deadline) if Instant::now() > deadline) {
+			throw!(RuntimeError(NATIVE_TIMEOUT_ERROR.into()));
+		}
+		result
+	}
+
+	/// Intrinsics only accept argument expressions, so values are bound in context
+	/// under generated names, and referenced by them
+	fn call_intrinsic_values<'a>(
+		call_ctx: Context,
+		name: &str,
+		args: impl Iterator<Item = (Option<&'a str>, &'a Val)>,
+	) -> Result<Val> {
+		let mut bindings = FxHashMap::default();
+		let mut args_desc = Vec::new();
+		for (i, (arg_name, value)) in args.enumerate() {
+			let var: Rc<str> = format!("__intrinsic_arg{}", i).into();
+			bindings.insert(var.clone(), resolved_lazy_val!(value.clone()));
+			args_desc.push(Arg(arg_name.map(ToOwned::to_owned), el!(Expr::Var(var))));
+		}
+		call_builtin(
+			call_ctx.extend(bindings, None, None, None),
+			&None,
+			name,
+			&ArgsDesc(args_desc),
+		)
+	}
+
 	pub fn evaluate_map(
 		&self,
 		call_ctx: Context,
@@ -149,8 +256,22 @@ impl FuncVal {
 				)?;
 				evaluate(ctx, &func.body)
 			}
-			Self::Intrinsic(_) => todo!(),
-			Self::NativeExt(_, _) => todo!(),
+			Self::Intrinsic(name) => {
+				// Intrinsics can't reorder named arguments, and map has no order
+				if args.len() > 1 {
+					throw!(IntrinsicArgumentReorderingIsNotSupportedYet);
+				}
+				Self::call_intrinsic_values(
+					call_ctx,
+					name,
+					args.iter().map(|(name, value)| (Some(name as &str), value)),
+				)
+			}
+			Self::NativeExt(_, handler) => {
+				let args =
+					parse_function_call_map(call_ctx, None, &handler.params, args, tailstrict)?;
+				Self::call_native(handler, &args)
+			}
 		}
 	}
 
@@ -160,8 +281,13 @@ impl FuncVal {
 				let ctx = place_args(call_ctx, Some(func.ctx.clone()), &func.params, args)?;
 				evaluate(ctx, &func.body)
 			}
-			Self::Intrinsic(_) => todo!(),
-			Self::NativeExt(_, _) => todo!(),
+			Self::Intrinsic(name) => {
+				Self::call_intrinsic_values(call_ctx, name, args.iter().map(|value| (None, value)))
+			}
+			Self::NativeExt(_, handler) => {
+				let args = place_args(call_ctx, None, &handler.params, args)?;
+				Self::call_native(handler, &args)
+			}
 		}
 	}
 }
@@ -196,23 +322,622 @@ impl Display for ValType {
 	}
 }
 
+/// Output of YAML stream without any documents
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmptyYamlStream {
+	/// Nothing is written
+	Empty,
+	/// Single empty document is written, i.e `---`
+	Document,
+}
+
 #[derive(Clone)]
 pub enum ManifestFormat {
-	YamlStream(Box<ManifestFormat>),
+	YamlStream(Box<ManifestFormat>, EmptyYamlStream),
 	Yaml(usize),
+	/// Same as [`ManifestFormat::Yaml`], but every nesting level is indented with specified string
+	YamlIndent(String),
 	Json(usize),
+	/// Same as [`ManifestFormat::Json`], but every nesting level is indented with specified string,
+	/// i.e `"\t"`. Output is minified when it is empty
+	JsonIndent(String),
+	Toml(usize),
+	/// Expects object with optional `main` and required `sections` fields, same as `std.manifestIni`
+	Ini,
+	/// Expects array of objects with the same fields, every object is written as a record
+	Csv {
+		delimiter: char,
+		/// Write row of field names before records
+		header: bool,
+	},
 	ToString,
 	String,
 }
 
+#[derive(Debug, Clone)]
+pub enum ArrValue {
+	/// Elements are evaluated on first access, used for array literals
+	Lazy(Rc<Vec<LazyVal>>),
+	/// Elements are already evaluated
+	Eager(Rc<Vec<Val>>),
+	/// Inclusive range of integers, elements are computed on access, used by `std.range`
+	///
+	/// When reversed, range goes down from `start` to `end`
+	Range {
+		start: i64,
+		end: i64,
+		reversed: bool,
+	},
+	/// Concatenation of two arrays, used by `+` operator, elements of both sides are not copied.
+	/// Length and nesting depth are cached, nesting depth is limited by [`MAX_CONCAT_DEPTH`]
+	Concat {
+		left: Rc<Self>,
+		right: Rc<Self>,
+		len: usize,
+		depth: usize,
+	},
+	/// Every `step`th element of `inner` in `start..end`, used by `std.slice`, elements are not copied.
+	/// Slice of a slice refers to the original array, so nested slicing doesn't increase nesting
+	Slice {
+		inner: Rc<Self>,
+		start: usize,
+		end: usize,
+		step: usize,
+	},
+}
+
+/// Deeper concatenations are flattened, so element access doesn't degrade on long `a + b + c + ...` chains
+pub const MAX_CONCAT_DEPTH: usize = 32;
+
+impl ArrValue {
+	pub fn new_eager() -> Self {
+		Self::Eager(Rc::new(Vec::new()))
+	}
+
+	pub const fn new_range(start: i64, end: i64) -> Self {
+		Self::Range {
+			start,
+			end,
+			reversed: false,
+		}
+	}
+
+	/// Concatenates arrays without evaluating or copying their elements
+	pub fn concat(left: Self, right: Self) -> Self {
+		if left.is_empty() {
+			return right;
+		}
+		if right.is_empty() {
+			return left;
+		}
+		let depth = left.concat_depth().max(right.concat_depth()) + 1;
+		if depth > MAX_CONCAT_DEPTH {
+			let mut out = Vec::with_capacity(left.len() + right.len());
+			out.extend(left.iter_lazy());
+			out.extend(right.iter_lazy());
+			return Self::Lazy(Rc::new(out));
+		}
+		Self::Concat {
+			len: left.len() + right.len(),
+			depth,
+			left: Rc::new(left),
+			right: Rc::new(right),
+		}
+	}
+
+	/// Every `step`th element in `start..end`, bounds are clamped to array length.
+	/// Elements are not copied, see [`ArrValue::Slice`]
+	pub fn slice(&self, start: usize, end: usize, step: usize) -> Result<Self> {
+		if step == 0 {
+			throw!(InvalidSliceStep(0.0));
+		}
+		let end = end.min(self.len());
+		let start = start.min(end);
+		if start == 0 && end == self.len() && step == 1 {
+			return Ok(self.clone());
+		}
+		Ok(match self {
+			Self::Slice {
+				inner,
+				start: inner_start,
+				step: inner_step,
+				..
+			} => Self::Slice {
+				inner: inner.clone(),
+				start: inner_start + start * inner_step,
+				end: inner_start + end * inner_step,
+				step: inner_step * step,
+			},
+			_ => Self::Slice {
+				inner: Rc::new(self.clone()),
+				start,
+				end,
+				step,
+			},
+		})
+	}
+
+	/// Slices are transparent for depth, as concatenation of slices of deep concatenations
+	/// is also slow to access
+	fn concat_depth(&self) -> usize {
+		match self {
+			Self::Concat { depth, .. } => *depth,
+			Self::Slice { inner, .. } => inner.concat_depth(),
+			_ => 0,
+		}
+	}
+	/// Finds non-concatenated and non-sliced array, containing element with specified index,
+	/// and index in it. Index should be in bounds
+	fn locate(&self, mut index: usize) -> (&Self, usize) {
+		let mut arr = self;
+		loop {
+			match arr {
+				Self::Concat { left, right, .. } => {
+					if index < left.len() {
+						arr = left;
+					} else {
+						index -= left.len();
+						arr = right;
+					}
+				}
+				Self::Slice {
+					inner, start, step, ..
+				} => {
+					index = start + index * step;
+					arr = inner;
+				}
+				_ => return (arr, index),
+			}
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		match self {
+			Self::Lazy(l) => l.len(),
+			Self::Eager(e) => e.len(),
+			Self::Range {
+				start,
+				end,
+				reversed,
+			} => {
+				let (low, high) = if *reversed {
+					(*end, *start)
+				} else {
+					(*start, *end)
+				};
+				if low > high {
+					0
+				} else {
+					// Difference of i64 bounds always fits in u64
+					usize::try_from(high.wrapping_sub(low) as u64)
+						.unwrap_or(usize::MAX)
+						.saturating_add(1)
+				}
+			}
+			Self::Concat { len, .. } => *len,
+			Self::Slice {
+				start, end, step, ..
+			} => {
+				if end > start {
+					(end - start - 1) / step + 1
+				} else {
+					0
+				}
+			}
+		}
+	}
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn get(&self, index: usize) -> Result<Option<Val>> {
+		match self {
+			Self::Lazy(l) => match l.get(index) {
+				Some(v) => Ok(Some(v.evaluate()?.unwrap_if_lazy()?)),
+				None => Ok(None),
+			},
+			Self::Eager(e) => match e.get(index) {
+				Some(v) => Ok(Some(v.unwrap_if_lazy()?)),
+				None => Ok(None),
+			},
+			Self::Range { .. } => Ok(self.get_range(index)),
+			Self::Concat { .. } | Self::Slice { .. } => {
+				if index >= self.len() {
+					return Ok(None);
+				}
+				let (arr, index) = self.locate(index);
+				arr.get(index)
+			}
+		}
+	}
+	pub fn get_lazy(&self, index: usize) -> Option<LazyVal> {
+		match self {
+			Self::Lazy(l) => l.get(index).cloned(),
+			Self::Eager(e) => e.get(index).cloned().map(LazyVal::new_resolved),
+			Self::Range { .. } => self.get_range(index).map(LazyVal::new_resolved),
+			Self::Concat { .. } | Self::Slice { .. } => {
+				if index >= self.len() {
+					return None;
+				}
+				let (arr, index) = self.locate(index);
+				arr.get_lazy(index)
+			}
+		}
+	}
+	fn get_range(&self, index: usize) -> Option<Val> {
+		match self {
+			Self::Range {
+				start, reversed, ..
+			} if index < self.len() => Some(Val::Num(if *reversed {
+				start - index as i64
+			} else {
+				start + index as i64
+			} as f64)),
+			_ => None,
+		}
+	}
+
+	/// Evaluates all elements
+	pub fn evaluated(&self) -> Result<Rc<Vec<Val>>> {
+		Ok(match self {
+			Self::Lazy(l) => {
+				let mut out = Vec::with_capacity(l.len());
+				for item in l.iter() {
+					out.push(item.evaluate()?.unwrap_if_lazy()?);
+				}
+				Rc::new(out)
+			}
+			Self::Eager(e) => e.clone(),
+			Self::Range { .. } => Rc::new(
+				(0..self.len())
+					.map(|i| self.get_range(i).expect("index is in bounds"))
+					.collect(),
+			),
+			Self::Concat { .. } | Self::Slice { .. } => {
+				Rc::new(self.iter().collect::<Result<_>>()?)
+			}
+		})
+	}
+
+	pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<Val>> + '_ {
+		(0..self.len()).map(move |i| match self {
+			Self::Lazy(l) => l[i].evaluate()?.unwrap_if_lazy(),
+			Self::Eager(e) => e[i].unwrap_if_lazy(),
+			Self::Range { .. } => Ok(self.get_range(i).expect("index is in bounds")),
+			Self::Concat { .. } | Self::Slice { .. } => {
+				let (arr, i) = self.locate(i);
+				arr.get(i).map(|v| v.expect("index is in bounds"))
+			}
+		})
+	}
+	pub fn iter_lazy(&self) -> impl DoubleEndedIterator<Item = LazyVal> + '_ {
+		(0..self.len()).map(move |i| self.get_lazy(i).expect("index is in bounds"))
+	}
+	pub fn iter_indexed_lazy(&self) -> impl DoubleEndedIterator<Item = (usize, LazyVal)> + '_ {
+		(0..self.len()).map(move |i| (i, self.get_lazy(i).expect("index is in bounds")))
+	}
+
+	/// Index of first element satisfying `pred`, elements after it are not evaluated
+	pub fn position(&self, pred: impl Fn(&Val) -> Result<bool>) -> Result<Option<usize>> {
+		self.position_from(0, pred)
+	}
+	/// Same as [`ArrValue::position`], but elements before `start` are skipped without evaluation
+	pub fn position_from(
+		&self,
+		start: usize,
+		pred: impl Fn(&Val) -> Result<bool>,
+	) -> Result<Option<usize>> {
+		for i in start..self.len() {
+			if pred(&self.get(i)?.expect("index is in bounds"))? {
+				return Ok(Some(i));
+			}
+		}
+		Ok(None)
+	}
+	/// Groups elements by string key, returned by `key_f`, order of elements is preserved within groups
+	pub fn group_by(&self, key_f: &FuncVal) -> Result<ObjValue> {
+		let mut groups: IndexMap<Rc<str>, Vec<Val>> = IndexMap::new();
+		for item in self.iter() {
+			let item = item?;
+			let key = key_f
+				.evaluate_values(Context::new(), std::slice::from_ref(&item))?
+				.try_cast_str("groupBy key")?;
+			groups.entry(key).or_default().push(item);
+		}
+		Ok(ObjValue::from_values(
+			groups
+				.into_iter()
+				.map(|(key, items)| (key, Val::Arr(items.into()))),
+		))
+	}
+
+	/// Concatenates array of arrays one level deep, elements of inner arrays are not evaluated
+	pub fn flatten(&self) -> Result<Self> {
+		let mut out = ArrValueBuilder::new();
+		for item in self.iter() {
+			match item? {
+				Val::Arr(inner) => out.extend(&inner),
+				v => throw!(TypeMismatch(
+					"flattened array element",
+					vec![ValType::Arr],
+					v.value_type()?
+				)),
+			}
+		}
+		Ok(out.build())
+	}
+
+	pub fn reversed(self) -> Self {
+		match self {
+			Self::Lazy(mut l) => {
+				Rc::make_mut(&mut l).reverse();
+				Self::Lazy(l)
+			}
+			Self::Eager(mut e) => {
+				Rc::make_mut(&mut e).reverse();
+				Self::Eager(e)
+			}
+			Self::Range {
+				start,
+				end,
+				reversed,
+			} => Self::Range {
+				start: end,
+				end: start,
+				reversed: !reversed,
+			},
+			Self::Concat { .. } | Self::Slice { .. } => {
+				Self::Lazy(Rc::new(self.iter_lazy().rev().collect()))
+			}
+		}
+	}
+
+	pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+		match (a, b) {
+			(Self::Lazy(a), Self::Lazy(b)) => Rc::ptr_eq(a, b),
+			(Self::Eager(a), Self::Eager(b)) => Rc::ptr_eq(a, b),
+			(
+				Self::Range {
+					start: a_start,
+					end: a_end,
+					reversed: a_reversed,
+				},
+				Self::Range {
+					start: b_start,
+					end: b_end,
+					reversed: b_reversed,
+				},
+			) => a_start == b_start && a_end == b_end && a_reversed == b_reversed,
+			(
+				Self::Concat {
+					left: a_left,
+					right: a_right,
+					..
+				},
+				Self::Concat {
+					left: b_left,
+					right: b_right,
+					..
+				},
+			) => Rc::ptr_eq(a_left, b_left) && Rc::ptr_eq(a_right, b_right),
+			(
+				Self::Slice {
+					inner: a_inner,
+					start: a_start,
+					end: a_end,
+					step: a_step,
+				},
+				Self::Slice {
+					inner: b_inner,
+					start: b_start,
+					end: b_end,
+					step: b_step,
+				},
+			) => {
+				Rc::ptr_eq(a_inner, b_inner)
+					&& a_start == b_start
+					&& a_end == b_end
+					&& a_step == b_step
+			}
+			_ => false,
+		}
+	}
+}
+impl From<Vec<LazyVal>> for ArrValue {
+	fn from(v: Vec<LazyVal>) -> Self {
+		Self::Lazy(Rc::new(v))
+	}
+}
+impl From<Vec<Val>> for ArrValue {
+	fn from(v: Vec<Val>) -> Self {
+		Self::Eager(Rc::new(v))
+	}
+}
+
+/// Collects elements of incrementally built array, backing `Vec` is only wrapped in `Rc` once,
+/// when [`ArrValueBuilder::build`] is called
+#[derive(Default)]
+pub struct ArrValueBuilder(Vec<LazyVal>);
+impl ArrValueBuilder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	pub fn with_capacity(capacity: usize) -> Self {
+		Self(Vec::with_capacity(capacity))
+	}
+	pub fn push(&mut self, item: LazyVal) {
+		self.0.push(item);
+	}
+	pub fn push_val(&mut self, item: Val) {
+		self.0.push(LazyVal::new_resolved(item));
+	}
+	/// Appends all elements of `arr`, without evaluating them
+	pub fn extend(&mut self, arr: &ArrValue) {
+		self.0.reserve(arr.len());
+		self.0.extend(arr.iter_lazy());
+	}
+	pub const fn len(&self) -> usize {
+		self.0.len()
+	}
+	pub const fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+	pub fn build(self) -> ArrValue {
+		ArrValue::Lazy(Rc::new(self.0))
+	}
+}
+
+/// Pending concatenation of two strings, flattened on first read
+pub struct StrConcat {
+	len: usize,
+	/// Dropped after flattening
+	parts: RefCell<Option<(StrValue, StrValue)>>,
+	flat: OnceCell<Rc<str>>,
+}
+impl Drop for StrConcat {
+	fn drop(&mut self) {
+		// Long concatenation chains are dropped iteratively, as recursive drop may overflow stack
+		let mut stack = Vec::new();
+		if let Some((a, b)) = self.parts.get_mut().take() {
+			stack.push(a);
+			stack.push(b);
+		}
+		while let Some(part) = stack.pop() {
+			if let StrValue::Concat(concat) = part {
+				if let Ok(mut concat) = Rc::try_unwrap(concat) {
+					if let Some((a, b)) = concat.parts.get_mut().take() {
+						stack.push(a);
+						stack.push(b);
+					}
+				}
+			}
+		}
+	}
+}
+
+/// String value, concatenation of strings is deferred until string is read,
+/// so repeated `+` is amortized linear instead of copying accumulated string every time
+#[derive(Clone)]
+pub enum StrValue {
+	Flat(Rc<str>),
+	Concat(Rc<StrConcat>),
+}
+impl StrValue {
+	pub fn concat(a: Self, b: Self) -> Self {
+		if a.is_empty() {
+			return b;
+		}
+		if b.is_empty() {
+			return a;
+		}
+		Self::Concat(Rc::new(StrConcat {
+			len: a.len() + b.len(),
+			parts: RefCell::new(Some((a, b))),
+			flat: OnceCell::new(),
+		}))
+	}
+	/// Length in bytes, doesn't flatten string
+	pub fn len(&self) -> usize {
+		match self {
+			Self::Flat(s) => s.len(),
+			Self::Concat(c) => c.len,
+		}
+	}
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+	pub fn flatten(&self) -> Rc<str> {
+		match self {
+			Self::Flat(s) => s.clone(),
+			Self::Concat(c) => c.flatten().clone(),
+		}
+	}
+}
+impl StrConcat {
+	fn flatten(&self) -> &Rc<str> {
+		self.flat.get_or_init(|| {
+			let mut out = String::with_capacity(self.len);
+			let (a, b) = self.parts.borrow_mut().take().expect("not yet flattened");
+			// Right parts are pushed first, so they are written after left
+			let mut stack = vec![b, a];
+			while let Some(part) = stack.pop() {
+				match part {
+					StrValue::Concat(c) if c.flat.get().is_none() => {
+						let (a, b) = c.parts.borrow().clone().expect("not yet flattened");
+						stack.push(b);
+						stack.push(a);
+					}
+					part => out.push_str(&part),
+				}
+			}
+			out.into()
+		})
+	}
+}
+impl Deref for StrValue {
+	type Target = str;
+	fn deref(&self) -> &str {
+		match self {
+			Self::Flat(s) => s,
+			Self::Concat(c) => c.flatten(),
+		}
+	}
+}
+impl From<Rc<str>> for StrValue {
+	fn from(s: Rc<str>) -> Self {
+		Self::Flat(s)
+	}
+}
+impl From<&str> for StrValue {
+	fn from(s: &str) -> Self {
+		Self::Flat(s.into())
+	}
+}
+impl From<String> for StrValue {
+	fn from(s: String) -> Self {
+		Self::Flat(s.into())
+	}
+}
+impl From<StrValue> for Rc<str> {
+	fn from(s: StrValue) -> Self {
+		s.flatten()
+	}
+}
+impl PartialEq for StrValue {
+	fn eq(&self, other: &Self) -> bool {
+		**self == **other
+	}
+}
+impl Eq for StrValue {}
+impl PartialOrd for StrValue {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+impl Ord for StrValue {
+	fn cmp(&self, other: &Self) -> Ordering {
+		(**self).cmp(&**other)
+	}
+}
+impl Debug for StrValue {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		Debug::fmt(&**self, f)
+	}
+}
+impl Display for StrValue {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		Display::fmt(&**self, f)
+	}
+}
+
 #[derive(Debug, Clone)]
 pub enum Val {
 	Bool(bool),
 	Null,
-	Str(Rc<str>),
+	Str(StrValue),
 	Num(f64),
 	Lazy(LazyVal),
-	Arr(Rc<Vec<Val>>),
+	Arr(ArrValue),
 	Obj(ObjValue),
 	Func(Rc<FuncVal>),
 }
@@ -250,7 +975,7 @@ impl Val {
 	}
 	pub fn try_cast_str(self, context: &'static str) -> Result<Rc<str>> {
 		self.assert_type(context, ValType::Str)?;
-		Ok(matches_unwrap!(self.unwrap_if_lazy()?, Self::Str(v), v))
+		Ok(matches_unwrap!(self.unwrap_if_lazy()?, Self::Str(v), v.flatten()))
 	}
 	pub fn try_cast_num(self, context: &'static str) -> Result<f64> {
 		self.assert_type(context, ValType::Num)?;
@@ -281,18 +1006,77 @@ impl Val {
 			Self::Lazy(_) => self.clone().unwrap_if_lazy()?.value_type()?,
 		})
 	}
+	/// Name of value type, same as returned by `std.type`, i.e `"number"`.
+	/// Lazy values are evaluated, `"<error>"` is returned if this fails, as in [`Display`] output
+	pub fn type_name(&self) -> &'static str {
+		self.value_type().map_or("<error>", |t| t.name())
+	}
+
+	/// Calls `f` for every scalar value, passing path of object fields/array indices to it
+	///
+	/// Only visible fields are visited, functions are skipped
+	pub fn for_each_leaf(&self, mut f: impl FnMut(&[String], &Self) -> Result<()>) -> Result<()> {
+		fn walk(
+			val: &Val,
+			path: &mut Vec<String>,
+			f: &mut dyn FnMut(&[String], &Val) -> Result<()>,
+		) -> Result<()> {
+			match val.unwrap_if_lazy()? {
+				Val::Arr(items) => {
+					for (i, item) in items.iter().enumerate() {
+						path.push(i.to_string());
+						walk(&item?, path, f)?;
+						path.pop();
+					}
+				}
+				Val::Obj(obj) => {
+					for field in obj.visible_fields() {
+						path.push(field.to_string());
+						walk(&obj.get(field)?.unwrap(), path, f)?;
+						path.pop();
+					}
+				}
+				Val::Func(_) => {}
+				v => f(path, &v)?,
+			}
+			Ok(())
+		}
+		walk(self, &mut Vec::new(), &mut f)
+	}
+
+	/// Layers objects on top of this object, same as `self + overrides[0] + overrides[1] + ...` in jsonnet,
+	/// so nested objects are only merged for fields declared with `+:`
+	pub fn merge_into(self, overrides: impl IntoIterator<Item = Self>) -> Result<Self> {
+		self.assert_type("merge base", ValType::Obj)?;
+		let mut out = matches_unwrap!(self.unwrap_if_lazy()?, Self::Obj(v), v);
+		for over in overrides {
+			over.assert_type("merge override", ValType::Obj)?;
+			out = out.merge(&matches_unwrap!(over.unwrap_if_lazy()?, Self::Obj(v), v));
+		}
+		Ok(Self::Obj(out))
+	}
 
+	/// Converts value to string the same way as `std.toString` and string concatenation do
 	pub fn to_string(&self) -> Result<Rc<str>> {
+		self.to_string_ex(false)
+	}
+	/// Same as [`Val::to_string`], but hidden object fields are included, intended for logging
+	pub fn to_string_with_hidden(&self) -> Result<Rc<str>> {
+		self.to_string_ex(true)
+	}
+	fn to_string_ex(&self, include_hidden: bool) -> Result<Rc<str>> {
 		Ok(match self.unwrap_if_lazy()? {
 			Self::Bool(true) => "true".into(),
 			Self::Bool(false) => "false".into(),
 			Self::Null => "null".into(),
-			Self::Str(s) => s,
+			Self::Str(s) => s.flatten(),
 			v => manifest_json_ex(
 				&v,
 				&ManifestJsonOptions {
 					padding: "",
 					mtype: ManifestType::ToString,
+					include_hidden,
+					..Default::default()
 				},
 			)?
 			.into(),
@@ -324,15 +1108,28 @@ impl Val {
 			_ => throw!(StreamManifestOutputIsNotAArray),
 		};
 		let mut out = Vec::with_capacity(arr.len());
-		for i in arr.iter() {
-			out.push(i.manifest(ty)?);
+		match arr {
+			// Avoid per-element dispatch for already evaluated arrays
+			ArrValue::Eager(items) => {
+				for i in items.iter() {
+					out.push(i.unwrap_if_lazy()?.manifest(ty)?);
+				}
+			}
+			ArrValue::Lazy(_)
+			| ArrValue::Range { .. }
+			| ArrValue::Concat { .. }
+			| ArrValue::Slice { .. } => {
+				for i in arr.iter() {
+					out.push(i?.manifest(ty)?);
+				}
+			}
 		}
 		Ok(out)
 	}
 
 	pub fn manifest(&self, ty: &ManifestFormat) -> Result<Rc<str>> {
 		Ok(match ty {
-			ManifestFormat::YamlStream(format) => {
+			ManifestFormat::YamlStream(format, empty) => {
 				let arr = match self {
 					Self::Arr(a) => a,
 					_ => throw!(StreamManifestOutputIsNotAArray),
@@ -340,7 +1137,7 @@ impl Val {
 				let mut out = String::new();
 
 				match format as &ManifestFormat {
-					ManifestFormat::YamlStream(_) => throw!(StreamManifestOutputCannotBeRecursed),
+					ManifestFormat::YamlStream(..) => throw!(StreamManifestOutputCannotBeRecursed),
 					ManifestFormat::String => throw!(StreamManifestCannotNestString),
 					_ => {}
 				};
@@ -348,38 +1145,125 @@ impl Val {
 				if !arr.is_empty() {
 					for v in arr.iter() {
 						out.push_str("---\n");
-						out.push_str(&v.manifest(format)?);
+						out.push_str(&v?.manifest(format)?);
 						out.push('\n');
 					}
 					out.push_str("...");
+				} else if *empty == EmptyYamlStream::Document {
+					out.push_str("---\n");
 				}
 
 				out.into()
 			}
 			ManifestFormat::Yaml(padding) => self.to_yaml(*padding)?,
+			ManifestFormat::YamlIndent(indent) => self.to_yaml_indent(indent)?,
 			ManifestFormat::Json(padding) => self.to_json(*padding)?,
+			ManifestFormat::JsonIndent(indent) => self.to_json_indent(indent)?,
+			ManifestFormat::Toml(padding) => self.to_toml(*padding)?,
+			ManifestFormat::Ini => self.to_ini()?,
+			ManifestFormat::Csv { delimiter, header } => self.to_csv(*delimiter, *header)?,
 			ManifestFormat::ToString => self.to_string()?,
 			ManifestFormat::String => match self {
-				Self::Str(s) => s.clone(),
+				Self::Str(s) => s.flatten(),
 				_ => throw!(StringManifestOutputIsNotAString),
 			},
 		})
 	}
 
+	/// Canonical representation of value, values which are [equal](equals) have the same key
+	pub fn hash_key(&self) -> Result<Rc<str>> {
+		manifest_json_ex(
+			self,
+			&ManifestJsonOptions {
+				mtype: ManifestType::Minify,
+				// Adding zero turns -0 into 0
+				render_number: Some(&|n| (n + 0.0).to_string()),
+				..Default::default()
+			},
+		)
+		.map(|s| s.into())
+	}
+
+	/// Feeds value to `state` consistently with [`equals`], so equal values have equal hashes.
+	/// Value is deeply evaluated, which may fail, functions are not hashable, as they are not
+	/// comparable, and produce error
+	pub fn hash_structural<H: Hasher>(&self, state: &mut H) -> Result<()> {
+		match self.unwrap_if_lazy()? {
+			Self::Bool(v) => {
+				0u8.hash(state);
+				v.hash(state);
+			}
+			Self::Null => 1u8.hash(state),
+			Self::Str(s) => {
+				2u8.hash(state);
+				s.flatten().hash(state);
+			}
+			Self::Num(n) => {
+				3u8.hash(state);
+				// Adding zero turns -0 into 0
+				(n + 0.0).to_bits().hash(state);
+			}
+			Self::Arr(arr) => {
+				4u8.hash(state);
+				arr.len().hash(state);
+				for item in arr.iter() {
+					item?.hash_structural(state)?;
+				}
+			}
+			Self::Obj(obj) => {
+				5u8.hash(state);
+				let fields = obj.visible_fields();
+				fields.len().hash(state);
+				for field in fields {
+					field.hash(state);
+					obj.get(field)?
+						.expect("field exists")
+						.hash_structural(state)?;
+				}
+			}
+			Self::Func(_) => throw!(RuntimeError("cannot hash functions".into())),
+			Self::Lazy(_) => unreachable!(),
+		}
+		Ok(())
+	}
+
 	/// For manifestification
 	pub fn to_json(&self, padding: usize) -> Result<Rc<str>> {
-		manifest_json_ex(
+		self.to_json_indent(&" ".repeat(padding))
+	}
+	/// Same as [`Val::to_json`], but every nesting level is indented with `indent`
+	pub fn to_json_indent(&self, indent: &str) -> Result<Rc<str>> {
+		let mut out = Vec::new();
+		self.write_json_indent(&mut out, indent)?;
+		Ok(String::from_utf8(out)
+			.expect("manifested json is valid utf-8")
+			.into())
+	}
+	/// Same as [`Val::to_json`], but output is written to `writer` while values are evaluated,
+	/// without keeping whole output in memory
+	pub fn write_json<W: std::io::Write>(&self, writer: &mut W, padding: usize) -> Result<()> {
+		self.write_json_indent(writer, &" ".repeat(padding))
+	}
+	/// Same as [`Val::write_json`], but every nesting level is indented with `indent`
+	pub fn write_json_indent<W: std::io::Write>(&self, writer: &mut W, indent: &str) -> Result<()> {
+		manifest_json_ex_to(
 			self,
+			writer,
 			&ManifestJsonOptions {
-				padding: &" ".repeat(padding),
-				mtype: if padding == 0 {
+				padding: indent,
+				mtype: if indent.is_empty() {
 					ManifestType::Minify
 				} else {
 					ManifestType::Manifest
 				},
+				..Default::default()
 			},
 		)
-		.map(|s| s.into())
+	}
+
+	/// Json with type annotations for every value, intended for diagnostics
+	pub fn to_debug_json(&self) -> Result<Rc<str>> {
+		manifest_debug_json(self).map(|s| s.into())
 	}
 
 	/// Calls `std.manifestJson`
@@ -390,6 +1274,7 @@ impl Val {
 			&ManifestJsonOptions {
 				padding: &" ".repeat(padding),
 				mtype: ManifestType::Std,
+				..Default::default()
 			},
 		)
 		.map(|s| s.into())
@@ -398,56 +1283,167 @@ impl Val {
 	/// Calls `std.manifestJson`
 	#[cfg(not(feature = "faster"))]
 	pub fn to_std_json(&self, padding: usize) -> Result<Rc<str>> {
-		with_state(|s| {
-			let ctx = s
-				.create_default_context()?
-				.with_var("__tmp__to_json__".into(), self.clone())?;
-			Ok(evaluate(
-				ctx,
-				&el!(Expr::Apply(
-					el!(Expr::Index(
-						el!(Expr::Var("std".into())),
-						el!(Expr::Str("manifestJsonEx".into()))
-					)),
-					ArgsDesc(vec![
-						Arg(None, el!(Expr::Var("__tmp__to_json__".into()))),
-						Arg(None, el!(Expr::Str(" ".repeat(padding).into())))
-					]),
-					false
-				)),
-			)?
-			.try_cast_str("to json")?)
+		self.call_std_manifest("manifestJsonEx", padding, || {
+			el!(Expr::Str(" ".repeat(padding).into()))
 		})
 	}
+	/// Calls `std.manifestTomlEx`
+	pub fn to_toml(&self, padding: usize) -> Result<Rc<str>> {
+		manifest_toml_ex(
+			self,
+			&ManifestTomlOptions {
+				padding: &" ".repeat(padding),
+			},
+		)
+		.map(|s| s.into())
+	}
+	/// Calls `std.manifestIni`
+	pub fn to_ini(&self) -> Result<Rc<str>> {
+		manifest_ini_ex(
+			self,
+			&ManifestIniOptions {
+				array_mode: IniArrayMode::Repeat,
+			},
+		)
+		.map(|s| s.into())
+	}
+	/// Writes array of objects as CSV, or TSV when `delimiter` is `\t`
+	pub fn to_csv(&self, delimiter: char, header: bool) -> Result<Rc<str>> {
+		manifest_csv_ex(self, &ManifestCsvOptions { delimiter, header }).map(|s| s.into())
+	}
+
+	/// Same as [`Val::to_yaml`] with non-zero padding, but nesting levels are indented with `indent`.
+	/// YAML doesn't allow tabs in indentation, strict parsers will reject tab-indented output
+	pub fn to_yaml_indent(&self, indent: &str) -> Result<Rc<str>> {
+		if indent.is_empty() {
+			return self.to_yaml(0);
+		}
+		manifest_yaml_ex(
+			self,
+			&ManifestYamlOptions {
+				padding: indent,
+				indent_array_in_object: true,
+				quote_strings: QuoteStrings::Always,
+				..Default::default()
+			},
+		)
+		.map(|s| s.into())
+	}
+
+	/// Calls `std.manifestYamlDoc`
+	#[cfg(feature = "faster")]
 	pub fn to_yaml(&self, padding: usize) -> Result<Rc<str>> {
-		with_state(|s| {
-			let ctx = s
-				.create_default_context()?
-				.with_var("__tmp__to_json__".into(), self.clone());
-			Ok(evaluate(
-				ctx,
-				&el!(Expr::Apply(
-					el!(Expr::Index(
-						el!(Expr::Var("std".into())),
-						el!(Expr::Str("manifestYamlDoc".into()))
-					)),
-					ArgsDesc(vec![
-						Arg(None, el!(Expr::Var("__tmp__to_json__".into()))),
-						Arg(
-							None,
-							el!(Expr::Literal(if padding != 0 {
-								LiteralType::True
-							} else {
-								LiteralType::False
-							}))
-						)
-					]),
-					false
-				)),
-			)?
-			.try_cast_str("to json")?)
+		manifest_yaml_ex(
+			self,
+			&ManifestYamlOptions {
+				padding: "  ",
+				indent_array_in_object: padding != 0,
+				quote_strings: QuoteStrings::Always,
+				..Default::default()
+			},
+		)
+		.map(|s| s.into())
+	}
+
+	/// Calls `std.manifestYamlDoc`
+	#[cfg(not(feature = "faster"))]
+	pub fn to_yaml(&self, padding: usize) -> Result<Rc<str>> {
+		self.call_std_manifest("manifestYamlDoc", padding.min(1), || {
+			el!(Expr::Literal(if padding != 0 {
+				LiteralType::True
+			} else {
+				LiteralType::False
+			}))
 		})
 	}
+
+	/// Calls std function `name` with this value and second argument, built by `arg`.
+	/// Built call is cached per `name` and `key`. Call only refers to `std`, so instead of
+	/// default context with every global, per call context only binds `std` and this value
+	#[cfg(not(feature = "faster"))]
+	fn call_std_manifest(
+		&self,
+		name: &'static str,
+		key: usize,
+		arg: impl FnOnce() -> LocExpr,
+	) -> Result<Rc<str>> {
+		let call = STD_MANIFEST_CALLS.with(|calls| {
+			calls
+				.borrow_mut()
+				.entry((name, key))
+				.or_insert_with(|| {
+					el!(Expr::Apply(
+						el!(Expr::Index(
+							el!(Expr::Var("std".into())),
+							el!(Expr::Str(name.into()))
+						)),
+						ArgsDesc(vec![
+							Arg(None, el!(Expr::Var("__tmp__to_json__".into()))),
+							Arg(None, arg()),
+						]),
+						false
+					))
+				})
+				.clone()
+		});
+		let std = with_state(|s| s.settings().globals.get("std").cloned())
+			.ok_or_else(|| VariableIsNotDefined("std".into()))?;
+		let ctx = Context::new()
+			.with_var("std".into(), std)
+			.with_var("__tmp__to_json__".into(), self.clone());
+		evaluate(ctx, &call)?.try_cast_str("to json")
+	}
+}
+
+/// Compact json-like rendering for debugging, evaluation errors are written as `<error>`.
+/// Unlike [`Val::to_string`], strings are always quoted
+impl Display for Val {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let val = match self.unwrap_if_lazy() {
+			Ok(v) => v,
+			Err(_) => return f.write_str("<error>"),
+		};
+		match val {
+			Self::Bool(v) => write!(f, "{}", v),
+			Self::Null => f.write_str("null"),
+			Self::Str(s) => f.write_str(&escape_string_json(&s)),
+			Self::Num(n) => {
+				match manifest_json_ex(&Self::Num(n), &ManifestJsonOptions::default()) {
+					Ok(n) => f.write_str(&n),
+					Err(_) => write!(f, "{}", n),
+				}
+			}
+			Self::Arr(items) => {
+				f.write_str("[")?;
+				for (i, item) in items.iter().enumerate() {
+					if i != 0 {
+						f.write_str(", ")?;
+					}
+					match item {
+						Ok(item) => Display::fmt(&item, f)?,
+						Err(_) => f.write_str("<error>")?,
+					}
+				}
+				f.write_str("]")
+			}
+			Self::Obj(obj) => {
+				f.write_str("{")?;
+				for (i, field) in obj.visible_fields().into_iter().enumerate() {
+					if i != 0 {
+						f.write_str(", ")?;
+					}
+					write!(f, "{}: ", escape_string_json(&field))?;
+					match obj.get(field) {
+						Ok(Some(value)) => Display::fmt(&value, f)?,
+						_ => f.write_str("<error>")?,
+					}
+				}
+				f.write_str("}")
+			}
+			Self::Func(_) => f.write_str("<function>"),
+			Self::Lazy(_) => unreachable!(),
+		}
+	}
 }
 
 const fn is_function_like(val: &Val) -> bool {
@@ -460,7 +1456,7 @@ pub fn primitive_equals(val_a: &Val, val_b: &Val) -> Result<bool> {
 		(Val::Bool(a), Val::Bool(b)) => a == b,
 		(Val::Null, Val::Null) => true,
 		(Val::Str(a), Val::Str(b)) => a == b,
-		(Val::Num(a), Val::Num(b)) => (a - b).abs() <= f64::EPSILON,
+		(Val::Num(a), Val::Num(b)) => a == b,
 		(Val::Arr(_), Val::Arr(_)) => throw!(RuntimeError(
 			"primitiveEquals operates on primitive types, got array".into(),
 		)),
@@ -489,7 +1485,7 @@ pub fn equals(val_a: &Val, val_b: &Val) -> Result<bool> {
 				return Ok(false);
 			}
 			for (a, b) in a.iter().zip(b.iter()) {
-				if !equals(&a.unwrap_if_lazy()?, &b.unwrap_if_lazy()?)? {
+				if !equals(&a?, &b?)? {
 					return Ok(false);
 				}
 			}
@@ -510,3 +1506,36 @@ pub fn equals(val_a: &Val, val_b: &Val) -> Result<bool> {
 		(a, b) => Ok(primitive_equals(&a, &b)?),
 	}
 }
+
+/// Native implementation of `std.__compare`, only numbers, strings and arrays of them are comparable
+pub fn compare(val_a: &Val, val_b: &Val) -> Result<Ordering> {
+	Ok(match (val_a.unwrap_if_lazy()?, val_b.unwrap_if_lazy()?) {
+		(Val::Num(a), Val::Num(b)) => match a.partial_cmp(&b) {
+			Some(ordering) => ordering,
+			None => throw!(RuntimeError("can't compare NaN".into())),
+		},
+		(Val::Str(a), Val::Str(b)) => a.cmp(&b),
+		(Val::Arr(a), Val::Arr(b)) => {
+			for (a, b) in a.iter().zip(b.iter()) {
+				let ordering = compare(&a?, &b?)?;
+				if ordering != Ordering::Equal {
+					return Ok(ordering);
+				}
+			}
+			a.len().cmp(&b.len())
+		}
+		(a, b) => throw!(BinaryOperatorDoesNotOperateOnValues(
+			BinaryOpType::Lt,
+			a.value_type()?,
+			b.value_type()?,
+		)),
+	})
+}
+
+/// Compares arrays as sets, ignoring order and duplicate elements
+pub fn equals_set(a: &ArrValue, b: &ArrValue) -> Result<bool> {
+	fn keys(arr: &ArrValue) -> Result<HashSet<Rc<str>>> {
+		arr.iter().map(|item| item?.hash_key()).collect()
+	}
+	Ok(keys(a)? == keys(b)?)
+}
@@ -2,6 +2,7 @@ use peg::parser;
 use std::{path::PathBuf, rc::Rc};
 mod expr;
 pub use expr::*;
+pub mod outline;
 pub use peg;
 
 #[derive(Default)]
@@ -13,6 +14,7 @@ pub struct ParserSettings {
 parser! {
 	grammar jsonnet_parser() for str {
 		use peg::ParseLiteral;
+		use crate::outline::SkipTo;
 
 		/// Standard C-like comments
 		rule comment()
@@ -290,6 +292,14 @@ parser! {
 			/ e:expr_basic(s) {e}
 
 		pub rule jsonnet(s: &ParserSettings) -> LocExpr = _ e:expr(s) _ {e}
+
+		/// Parses single top-level member, starting at `start`, see [`crate::outline`]
+		pub rule outline_member(s: &ParserSettings, start: usize) -> expr::Member
+			= ##skip_to(start) _ m:member(s) _ {m}
+		pub rule outline_element(s: &ParserSettings, start: usize) -> LocExpr
+			= ##skip_to(start) _ e:expr(s) _ {e}
+		pub rule outline_field_name(s: &ParserSettings, start: usize, end: usize) -> expr::FieldName
+			= ##skip_to(start) _ n:field_name(s) ##skip_to(end) {n}
 	}
 }
 
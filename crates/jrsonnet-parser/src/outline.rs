@@ -0,0 +1,349 @@
+//! Outline parsing, for tools which only need top-level structure of (possibly huge) input
+//!
+//! Only boundaries of top-level object members/array elements are found upfront,
+//! members themselves are parsed on demand
+use crate::{expr::*, jsonnet_parser, parse, ParseError, ParserSettings};
+use peg::RuleResult;
+use std::{cell::Cell, rc::Rc};
+
+/// Allows grammar rules to start parsing in the middle of input (`##skip_to(pos)`),
+/// so locations of lazily parsed members are the same as if the whole input was parsed
+pub trait SkipTo {
+	fn skip_to(&self, pos: usize, target: usize) -> RuleResult<()>;
+}
+impl SkipTo for str {
+	fn skip_to(&self, pos: usize, target: usize) -> RuleResult<()> {
+		if pos <= target && target <= self.len() {
+			RuleResult::Matched(target, ())
+		} else {
+			RuleResult::Failed
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutlineKind {
+	Object,
+	Array,
+}
+
+pub struct Outline<'s> {
+	source: &'s str,
+	settings: &'s ParserSettings,
+	kind: OutlineKind,
+	/// Source ranges of members, including surrounding whitespace, but not separating commas
+	members: Vec<(usize, usize)>,
+	parsed_count: Cell<usize>,
+}
+
+impl<'s> Outline<'s> {
+	pub const fn kind(&self) -> OutlineKind {
+		self.kind
+	}
+	pub fn len(&self) -> usize {
+		self.members.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.members.is_empty()
+	}
+	/// Number of members parsed via [`Self::member`] and [`Self::element`]
+	pub fn parsed_count(&self) -> usize {
+		self.parsed_count.get()
+	}
+
+	/// Name of object member, without parsing its value
+	///
+	/// Returns `None` for locals, assertions and fields with dynamic names
+	pub fn field_name(&self, index: usize) -> Option<Rc<str>> {
+		let (start, end) = self.members[index];
+		match jsonnet_parser::outline_field_name(&self.source[..end], self.settings, start, end) {
+			Ok(FieldName::Fixed(name)) => Some(name),
+			_ => None,
+		}
+	}
+	pub fn field_names(&self) -> Vec<Rc<str>> {
+		(0..self.len()).filter_map(|i| self.field_name(i)).collect()
+	}
+
+	/// Parses object member
+	pub fn member(&self, index: usize) -> Result<Member, ParseError> {
+		debug_assert_eq!(self.kind, OutlineKind::Object);
+		let (start, end) = self.members[index];
+		self.parsed_count.set(self.parsed_count.get() + 1);
+		jsonnet_parser::outline_member(&self.source[..end], self.settings, start)
+	}
+	/// Parses object member with specified name
+	pub fn field(&self, name: &str) -> Option<Result<Member, ParseError>> {
+		let index = (0..self.len()).find(|i| self.field_name(*i).as_deref() == Some(name))?;
+		Some(self.member(index))
+	}
+
+	/// Parses array element
+	pub fn element(&self, index: usize) -> Result<LocExpr, ParseError> {
+		debug_assert_eq!(self.kind, OutlineKind::Array);
+		let (start, end) = self.members[index];
+		self.parsed_count.set(self.parsed_count.get() + 1);
+		jsonnet_parser::outline_element(&self.source[..end], self.settings, start)
+	}
+}
+
+/// Finds top-level structure of input, which should be an object or array literal
+///
+/// Returns `Ok(None)` if input is valid jsonnet, but top-level expression is something else
+/// (i.e `{} + {}`, or object/array comprehension)
+pub fn parse_outline<'s>(
+	str: &'s str,
+	settings: &'s ParserSettings,
+) -> Result<Option<Outline<'s>>, ParseError> {
+	match scan_outline(str.as_bytes()) {
+		Some((kind, members)) => Ok(Some(Outline {
+			source: str,
+			settings,
+			kind,
+			members,
+			parsed_count: Cell::new(0),
+		})),
+		// Let full parser report the error
+		None => parse(str, settings).map(|_| None),
+	}
+}
+
+fn scan_outline(bytes: &[u8]) -> Option<(OutlineKind, Vec<(usize, usize)>)> {
+	let open = skip_whitespace(bytes, 0)?;
+	let (kind, close) = match bytes.get(open)? {
+		b'{' => (OutlineKind::Object, b'}'),
+		b'[' => (OutlineKind::Array, b']'),
+		_ => return None,
+	};
+
+	let mut members = Vec::new();
+	let mut depth = 0usize;
+	let mut member_start = open + 1;
+	let mut has_content = false;
+	let mut i = open + 1;
+	while i < bytes.len() {
+		let c = bytes[i];
+		match c {
+			b' ' | b'\t' | b'\r' | b'\n' => {
+				i += 1;
+				continue;
+			}
+			b'/' | b'#' if is_comment_start(bytes, i) => {
+				i = skip_comment(bytes, i)?;
+				continue;
+			}
+			_ => {}
+		}
+		match c {
+			b'"' | b'\'' => i = skip_quoted(bytes, i)?,
+			b'@' if matches!(bytes.get(i + 1), Some(b'"') | Some(b'\'')) => {
+				i = skip_verbatim(bytes, i + 1)?
+			}
+			b'|' if bytes[i..].starts_with(b"|||") => i = skip_text_block(bytes, i)?,
+			b'{' | b'[' | b'(' => {
+				depth += 1;
+				i += 1;
+			}
+			b'}' | b']' | b')' if depth == 0 => {
+				if c != close {
+					return None;
+				}
+				if has_content {
+					members.push((member_start, i));
+				}
+				// Only trailing whitespace is allowed after top-level literal
+				if skip_whitespace(bytes, i + 1)? != bytes.len() {
+					return None;
+				}
+				return Some((kind, members));
+			}
+			b'}' | b']' | b')' => {
+				depth -= 1;
+				i += 1;
+			}
+			c if c.is_ascii_alphabetic() || c == b'_' => {
+				let start = i;
+				while matches!(bytes.get(i), Some(c) if c.is_ascii_alphanumeric() || *c == b'_') {
+					i += 1;
+				}
+				// Comprehension, members can't be parsed separately
+				if depth == 0 && &bytes[start..i] == b"for" {
+					return None;
+				}
+			}
+			b',' if depth == 0 => {
+				if !has_content {
+					return None;
+				}
+				members.push((member_start, i));
+				member_start = i + 1;
+				has_content = false;
+				i += 1;
+				continue;
+			}
+			_ => i += 1,
+		}
+		has_content = true;
+	}
+	None
+}
+
+fn is_comment_start(bytes: &[u8], i: usize) -> bool {
+	bytes[i] == b'#' || matches!(bytes.get(i + 1), Some(b'/') | Some(b'*'))
+}
+/// Returns position after comment
+fn skip_comment(bytes: &[u8], i: usize) -> Option<usize> {
+	if bytes[i..].starts_with(b"/*") {
+		let len = find(&bytes[i + 2..], b"*/")?;
+		Some(i + 2 + len + 2)
+	} else {
+		Some(find(&bytes[i..], b"\n").map_or(bytes.len(), |len| i + len + 1))
+	}
+}
+/// Returns position of first non-whitespace character
+fn skip_whitespace(bytes: &[u8], mut i: usize) -> Option<usize> {
+	while i < bytes.len() {
+		match bytes[i] {
+			b' ' | b'\t' | b'\r' | b'\n' => i += 1,
+			b'/' | b'#' if is_comment_start(bytes, i) => i = skip_comment(bytes, i)?,
+			_ => break,
+		}
+	}
+	Some(i)
+}
+/// Returns position after closing quote
+fn skip_quoted(bytes: &[u8], i: usize) -> Option<usize> {
+	let quote = bytes[i];
+	let mut i = i + 1;
+	while i < bytes.len() {
+		match bytes[i] {
+			b'\\' => i += 2,
+			c if c == quote => return Some(i + 1),
+			_ => i += 1,
+		}
+	}
+	None
+}
+/// Returns position after closing quote, quotes are escaped by doubling
+fn skip_verbatim(bytes: &[u8], i: usize) -> Option<usize> {
+	let quote = bytes[i];
+	let mut i = i + 1;
+	while i < bytes.len() {
+		if bytes[i] == quote {
+			if bytes.get(i + 1) == Some(&quote) {
+				i += 2;
+				continue;
+			}
+			return Some(i + 1);
+		}
+		i += 1;
+	}
+	None
+}
+/// Returns position after closing `|||`, which is the first line consisting only of it
+fn skip_text_block(bytes: &[u8], i: usize) -> Option<usize> {
+	let mut line = i + find(&bytes[i..], b"\n")? + 1;
+	loop {
+		let mut j = line;
+		while matches!(bytes.get(j), Some(b' ') | Some(b'\t')) {
+			j += 1;
+		}
+		if bytes[j..].starts_with(b"|||") {
+			return Some(j + 3);
+		}
+		line = j + find(&bytes[j..], b"\n")? + 1;
+	}
+}
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+	haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::PathBuf;
+
+	fn settings() -> ParserSettings {
+		ParserSettings {
+			loc_data: true,
+			file_name: Rc::new(PathBuf::from("/test.jsonnet")),
+		}
+	}
+
+	#[test]
+	fn lazy_fields() {
+		let mut source = String::from("{\n");
+		for i in 0..1000 {
+			source.push_str(&format!(
+				"\tfield{}: {{ a: [1, 2, '}}'], b: |||\n\t\ttext ]\n\t|||, c: \"{}\" }}, // ,\n",
+				i, i
+			));
+		}
+		source.push_str("\tlocal x = 1,\n\tassert x == 1,\n}\n");
+
+		let settings = settings();
+		let outline = parse_outline(&source, &settings).unwrap().unwrap();
+		assert_eq!(outline.kind(), OutlineKind::Object);
+		assert_eq!(outline.len(), 1002);
+		assert_eq!(outline.field_names().len(), 1000);
+		assert_eq!(outline.parsed_count(), 0);
+
+		let field = match outline.field("field500").unwrap().unwrap() {
+			Member::Field(field) => field,
+			_ => panic!("expected field"),
+		};
+		assert_eq!(field.name, FieldName::Fixed("field500".into()));
+		// Locations are the same as in full parse
+		let ExprLocation(_, start, _) = field.value.1.clone().unwrap();
+		assert!(source[start..].starts_with("{ a: [1, 2, '}'], b:"));
+		assert!(source[..start].ends_with("field500: "));
+
+		assert!(matches!(
+			outline.member(1001).unwrap(),
+			Member::AssertStmt(_)
+		));
+		assert_eq!(outline.parsed_count(), 2);
+	}
+
+	#[test]
+	fn array() {
+		let settings = settings();
+		let outline = parse_outline("[1, {a: 2}, [3,],]", &settings)
+			.unwrap()
+			.unwrap();
+		assert_eq!(outline.kind(), OutlineKind::Array);
+		assert_eq!(outline.len(), 3);
+		assert!(matches!(&*outline.element(1).unwrap().0, Expr::Obj(_)));
+		assert_eq!(outline.parsed_count(), 1);
+	}
+
+	#[test]
+	fn not_a_literal() {
+		let settings = settings();
+		assert!(parse_outline("{} + {}", &settings).unwrap().is_none());
+		assert!(parse_outline("{a: 1", &settings).is_err());
+	}
+
+	#[test]
+	fn comprehension() {
+		let settings = settings();
+		assert!(parse_outline("{[k]: 1 for k in ['a', 'b']}", &settings)
+			.unwrap()
+			.is_none());
+		assert!(
+			parse_outline("{local v = 1, [k]: v for k in ['a']}", &settings)
+				.unwrap()
+				.is_none()
+		);
+		assert!(parse_outline("[x for x in [1, 2]]", &settings)
+			.unwrap()
+			.is_none());
+		assert!(parse_outline("[x for x in", &settings).is_err());
+
+		// Nested comprehensions and identifiers containing `for` don't affect outline
+		let outline = parse_outline("[[x for x in [1]], format, for_]", &settings)
+			.unwrap()
+			.unwrap();
+		assert_eq!(outline.len(), 3);
+	}
+}